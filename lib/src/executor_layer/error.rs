@@ -13,6 +13,18 @@ pub enum ExecutorError {
     #[error("Table not found")]
     TableNotFound,
 
+    #[error("Can not convert {0}")]
+    ConversionError(String),
+
+    #[error("Arithmetic error: {0}")]
+    ArithmeticError(String),
+
+    #[error("Function error: {0}")]
+    FunctionError(String),
+
+    #[error("Subquery error: {0}")]
+    SubqueryError(String),
+
     #[error("Underlying error: {0}")]
     StorageError(StorageError),
 }
@@ -1,6 +1,11 @@
 use std::boxed::Box;
+use std::cmp::Ordering;
 
+use super::error::ExecutorError;
+use super::function::ScalarFunction;
+use super::pattern::Pattern;
 use super::schema::*;
+use super::subquery::{Subquery, ValueSet};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
@@ -15,85 +20,276 @@ pub enum Expression {
         op: UnaryOperator,
         expr: Box<Expression>,
     },
+    InList {
+        expr: Box<Expression>,
+        list: Vec<Expression>,
+    },
+    Cast {
+        expr: Box<Expression>,
+        db_type: DBType,
+    },
+    FunctionCall {
+        function: ScalarFunction,
+        args: Vec<Expression>,
+    },
+    PatternMatch {
+        expr: Box<Expression>,
+        pattern: Pattern,
+    },
+    MessageConstructor {
+        message_type: MessageType,
+        fields: Vec<Expression>,
+    },
+    EnumConstructor {
+        enum_type: EnumType,
+        choice: usize,
+        fields: Vec<Expression>,
+    },
+    Exists(Box<Subquery>),
+    InSubquery {
+        expr: Box<Expression>,
+        subquery: Box<Subquery>,
+    },
+    ScalarSubquery {
+        subquery: Box<Subquery>,
+        db_type: DBType,
+    },
+    //uncorrelated IN subqueries and decorrelated EXISTS are reduced to a lookup in a set
+    InSet {
+        expr: Box<Expression>,
+        values: ValueSet,
+    },
 }
 
 impl Expression {
-    pub fn evaluate(&self, message: &Message) -> DBValue {
+    pub fn evaluate(&self, message: &Message) -> Result<DBValue, ExecutorError> {
         match self {
-            Expression::Literal(value) => value.clone(),
-            Expression::ColumnRef(index) => message.fields[*index].clone(),
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::ColumnRef(index) => Ok(message.fields[*index].clone()),
             Expression::BinaryOp { op, left, right } => {
-                op.apply(left.evaluate(message), right.evaluate(message))
+                op.apply(left.evaluate(message)?, right.evaluate(message)?)
+            }
+            Expression::UnaryOp { op, expr } => op.apply(expr.evaluate(message)?),
+            Expression::InList { expr, list } => {
+                let value = expr.evaluate(message)?;
+                for item in list.iter() {
+                    if item.evaluate(message)?.equals(&value) {
+                        return Ok(DBValue::Bool(true));
+                    }
+                }
+                Ok(DBValue::Bool(false))
+            }
+            Expression::Cast { expr, db_type } => cast_value(expr.evaluate(message)?, db_type),
+            Expression::FunctionCall { function, args } => function.call(args, message),
+            Expression::PatternMatch { expr, pattern } => match expr.evaluate(message)? {
+                DBValue::String(s) => Ok(DBValue::Bool(pattern.is_match(&s))),
+                _ => panic!("Incorrect pattern matching"),
+            },
+            Expression::MessageConstructor {
+                message_type,
+                fields,
+            } => Ok(DBValue::Message(Message {
+                type_name: Some(message_type.name.clone()),
+                fields: Self::evaluate_all(fields, message)?,
+            })),
+            Expression::EnumConstructor {
+                enum_type,
+                choice,
+                fields,
+            } => Ok(DBValue::EnumValue(EnumValue {
+                type_name: Some(enum_type.name.clone()),
+                choice: *choice,
+                values: Self::evaluate_all(fields, message)?,
+            })),
+            Expression::Exists(subquery) => Ok(DBValue::Bool(subquery.exists(message)?)),
+            Expression::InSubquery { expr, subquery } => {
+                let value = expr.evaluate(message)?;
+                Ok(DBValue::Bool(subquery.contains(&value, message)?))
+            }
+            Expression::ScalarSubquery {
+                subquery,
+                db_type: _,
+            } => subquery.scalar(message),
+            Expression::InSet { expr, values } => {
+                Ok(DBValue::Bool(values.contains(&expr.evaluate(message)?)))
             }
-            Expression::UnaryOp { op, expr } => op.apply(expr.evaluate(message)),
         }
     }
+
+    fn evaluate_all(
+        expressions: &[Expression],
+        message: &Message,
+    ) -> Result<Vec<DBValue>, ExecutorError> {
+        expressions
+            .iter()
+            .map(|expression| expression.evaluate(message))
+            .collect()
+    }
+}
+
+//Converts a value to the target type, failing on values that do not fit into it
+pub fn cast_value(value: DBValue, db_type: &DBType) -> Result<DBValue, ExecutorError> {
+    let conversion_error =
+        |value: &DBValue| ExecutorError::ConversionError(format!("{:?} to {:?}", value, db_type));
+
+    let result = match (&value, db_type) {
+        (DBValue::Bool(_), DBType::Bool)
+        | (DBValue::Double(_), DBType::Double)
+        | (DBValue::Int(_), DBType::Int)
+        | (DBValue::UInt(_), DBType::UInt)
+        | (DBValue::String(_), DBType::String) => Some(value.clone()),
+        (DBValue::Int(i), DBType::UInt) => u32::try_from(*i).ok().map(DBValue::UInt),
+        (DBValue::Int(i), DBType::Double) => Some(DBValue::Double(*i as f32)),
+        (DBValue::Int(i), DBType::Bool) => Some(DBValue::Bool(*i != 0)),
+        (DBValue::UInt(u), DBType::Int) => i32::try_from(*u).ok().map(DBValue::Int),
+        (DBValue::UInt(u), DBType::Double) => Some(DBValue::Double(*u as f32)),
+        (DBValue::UInt(u), DBType::Bool) => Some(DBValue::Bool(*u != 0)),
+        (DBValue::Double(d), DBType::Int) => {
+            let truncated = d.trunc();
+            (truncated.is_finite()
+                && truncated >= i32::MIN as f32
+                && truncated < -(i32::MIN as f32))
+                .then_some(DBValue::Int(truncated as i32))
+        }
+        (DBValue::Double(d), DBType::UInt) => {
+            let truncated = d.trunc();
+            (truncated.is_finite() && truncated >= 0f32 && truncated < u32::MAX as f32)
+                .then_some(DBValue::UInt(truncated as u32))
+        }
+        (DBValue::Bool(b), DBType::Int) => Some(DBValue::Int(*b as i32)),
+        (DBValue::Bool(b), DBType::UInt) => Some(DBValue::UInt(*b as u32)),
+        (DBValue::Bool(b), DBType::String) => Some(DBValue::String(b.to_string())),
+        (DBValue::Double(d), DBType::String) => Some(DBValue::String(d.to_string())),
+        (DBValue::Int(i), DBType::String) => Some(DBValue::String(i.to_string())),
+        (DBValue::UInt(u), DBType::String) => Some(DBValue::String(u.to_string())),
+        (DBValue::String(s), DBType::Bool) => s.trim().parse().ok().map(DBValue::Bool),
+        (DBValue::String(s), DBType::Double) => s.trim().parse().ok().map(DBValue::Double),
+        (DBValue::String(s), DBType::Int) => s.trim().parse().ok().map(DBValue::Int),
+        (DBValue::String(s), DBType::UInt) => s.trim().parse().ok().map(DBValue::UInt),
+        (_, _) => None,
+    };
+
+    result.ok_or_else(|| conversion_error(&value))
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOperator {
-    Add,         // +
-    Subtract,    // -
-    Multiply,    // *
-    Divide,      // /
-    Equals,      // =
-    NotEquals,   // !=
-    LessThan,    // <
-    GreaterThan, // >
-    And,         // &
-    Or,          // |
+    Add,            // +
+    Subtract,       // -
+    Multiply,       // *
+    Divide,         // /
+    Modulo,         // %
+    Concat,         // ++
+    Equals,         // =
+    NotEquals,      // !=
+    LessThan,       // <
+    GreaterThan,    // >
+    LessOrEqual,    // <=
+    GreaterOrEqual, // >=
+    And,            // &
+    Or,             // |
+}
+
+//Int and UInt operands of comparisons are compared exactly
+fn order(left: &DBValue, right: &DBValue) -> Ordering {
+    left.compare_integers(right)
+        .unwrap_or_else(|| left.compare(right))
 }
 
 impl BinaryOperator {
-    pub fn apply(&self, left: DBValue, right: DBValue) -> DBValue {
+    //comparisons accept Int and UInt operands without converting them
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Equals
+                | BinaryOperator::NotEquals
+                | BinaryOperator::LessThan
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::GreaterOrEqual
+        )
+    }
+
+    //integer arithmetic is checked, overflow and division by zero fail the row
+    pub fn apply(&self, left: DBValue, right: DBValue) -> Result<DBValue, ExecutorError> {
+        let overflow = || ExecutorError::ArithmeticError(format!("overflow in {:?}", self));
+        let zero = || ExecutorError::ArithmeticError("division by zero".to_owned());
         match self {
             BinaryOperator::Add => match (left, right) {
-                (DBValue::Double(l), DBValue::Double(r)) => DBValue::Double(l + r),
-                (DBValue::Int(l), DBValue::Int(r)) => DBValue::Int(l + r),
-                (DBValue::UInt(l), DBValue::UInt(r)) => DBValue::UInt(l + r),
+                (DBValue::Double(l), DBValue::Double(r)) => Ok(DBValue::Double(l + r)),
+                (DBValue::Int(l), DBValue::Int(r)) => {
+                    l.checked_add(r).map(DBValue::Int).ok_or_else(overflow)
+                }
+                (DBValue::UInt(l), DBValue::UInt(r)) => {
+                    l.checked_add(r).map(DBValue::UInt).ok_or_else(overflow)
+                }
                 (_, _) => panic!("Incorrect addition"),
             },
             BinaryOperator::Subtract => match (left, right) {
-                (DBValue::Double(l), DBValue::Double(r)) => DBValue::Double(l - r),
-                (DBValue::Int(l), DBValue::Int(r)) => DBValue::Int(l - r),
-                (DBValue::UInt(l), DBValue::UInt(r)) => DBValue::UInt(l - r),
+                (DBValue::Double(l), DBValue::Double(r)) => Ok(DBValue::Double(l - r)),
+                (DBValue::Int(l), DBValue::Int(r)) => {
+                    l.checked_sub(r).map(DBValue::Int).ok_or_else(overflow)
+                }
+                (DBValue::UInt(l), DBValue::UInt(r)) => {
+                    l.checked_sub(r).map(DBValue::UInt).ok_or_else(overflow)
+                }
                 (_, _) => panic!("Incorrect subtraction"),
             },
             BinaryOperator::Multiply => match (left, right) {
-                (DBValue::Double(l), DBValue::Double(r)) => DBValue::Double(l * r),
-                (DBValue::Int(l), DBValue::Int(r)) => DBValue::Int(l * r),
-                (DBValue::UInt(l), DBValue::UInt(r)) => DBValue::UInt(l * r),
+                (DBValue::Double(l), DBValue::Double(r)) => Ok(DBValue::Double(l * r)),
+                (DBValue::Int(l), DBValue::Int(r)) => {
+                    l.checked_mul(r).map(DBValue::Int).ok_or_else(overflow)
+                }
+                (DBValue::UInt(l), DBValue::UInt(r)) => {
+                    l.checked_mul(r).map(DBValue::UInt).ok_or_else(overflow)
+                }
                 (_, _) => panic!("Incorrect multiplication"),
             },
             BinaryOperator::Divide => match (left, right) {
-                (DBValue::Double(l), DBValue::Double(r)) => DBValue::Double(l / r),
-                (DBValue::Int(l), DBValue::Int(r)) => DBValue::Int(l / r),
-                (DBValue::UInt(l), DBValue::UInt(r)) => DBValue::UInt(l / r),
+                (DBValue::Double(l), DBValue::Double(r)) => Ok(DBValue::Double(l / r)),
+                (DBValue::Int(_), DBValue::Int(0)) | (DBValue::UInt(_), DBValue::UInt(0)) => {
+                    Err(zero())
+                }
+                (DBValue::Int(l), DBValue::Int(r)) => {
+                    l.checked_div(r).map(DBValue::Int).ok_or_else(overflow)
+                }
+                (DBValue::UInt(l), DBValue::UInt(r)) => Ok(DBValue::UInt(l / r)),
                 (_, _) => panic!("Incorrect division"),
             },
-            BinaryOperator::Equals => DBValue::Bool(left == right),
-            BinaryOperator::NotEquals => DBValue::Bool(left != right),
-            BinaryOperator::LessThan => match (left, right) {
-                (DBValue::Double(l), DBValue::Double(r)) => DBValue::Bool(l < r),
-                (DBValue::Int(l), DBValue::Int(r)) => DBValue::Bool(l < r),
-                (DBValue::UInt(l), DBValue::UInt(r)) => DBValue::Bool(l < r),
-                (DBValue::String(l), DBValue::String(r)) => DBValue::Bool(l < r),
-                (_, _) => panic!("Incorrect comparison"),
+            BinaryOperator::Modulo => match (left, right) {
+                (DBValue::Double(l), DBValue::Double(r)) => Ok(DBValue::Double(l % r)),
+                (DBValue::Int(_), DBValue::Int(0)) | (DBValue::UInt(_), DBValue::UInt(0)) => {
+                    Err(zero())
+                }
+                (DBValue::Int(l), DBValue::Int(r)) => {
+                    l.checked_rem(r).map(DBValue::Int).ok_or_else(overflow)
+                }
+                (DBValue::UInt(l), DBValue::UInt(r)) => Ok(DBValue::UInt(l % r)),
+                (_, _) => panic!("Incorrect modulo"),
             },
-            BinaryOperator::GreaterThan => match (left, right) {
-                (DBValue::Double(l), DBValue::Double(r)) => DBValue::Bool(l > r),
-                (DBValue::Int(l), DBValue::Int(r)) => DBValue::Bool(l > r),
-                (DBValue::UInt(l), DBValue::UInt(r)) => DBValue::Bool(l > r),
-                (DBValue::String(l), DBValue::String(r)) => DBValue::Bool(l > r),
-                (_, _) => panic!("Incorrect comparison"),
+            BinaryOperator::Concat => match (left, right) {
+                (DBValue::String(l), DBValue::String(r)) => Ok(DBValue::String(l + &r)),
+                (_, _) => panic!("Incorrect concatenation"),
             },
+            BinaryOperator::Equals => Ok(DBValue::Bool(left.equals(&right))),
+            BinaryOperator::NotEquals => Ok(DBValue::Bool(!left.equals(&right))),
+            BinaryOperator::LessThan => Ok(DBValue::Bool(order(&left, &right) == Ordering::Less)),
+            BinaryOperator::GreaterThan => {
+                Ok(DBValue::Bool(order(&left, &right) == Ordering::Greater))
+            }
+            BinaryOperator::LessOrEqual => {
+                Ok(DBValue::Bool(order(&left, &right) != Ordering::Greater))
+            }
+            BinaryOperator::GreaterOrEqual => {
+                Ok(DBValue::Bool(order(&left, &right) != Ordering::Less))
+            }
             BinaryOperator::And => match (left, right) {
-                (DBValue::Bool(l), DBValue::Bool(r)) => DBValue::Bool(l & r),
+                (DBValue::Bool(l), DBValue::Bool(r)) => Ok(DBValue::Bool(l & r)),
                 (_, _) => panic!("Incorrect binary AND"),
             },
             BinaryOperator::Or => match (left, right) {
-                (DBValue::Bool(l), DBValue::Bool(r)) => DBValue::Bool(l | r),
-                (_, _) => panic!("Incorrect binary AND"),
+                (DBValue::Bool(l), DBValue::Bool(r)) => Ok(DBValue::Bool(l | r)),
+                (_, _) => panic!("Incorrect binary OR"),
             },
         }
     }
@@ -101,34 +297,85 @@ impl BinaryOperator {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOperator {
-    Negate,                     // -
-    Not,                        // NOT
-    MessageField(usize),        // foo.bar
-    EnumMatch(Vec<Expression>), // match enum, foo => bar, lol => kek etc
+    Negate,                   // -
+    Not,                      // NOT
+    MessageField(usize),      // foo.bar
+    IsVariant(usize),         // foo IS EnumType::Variant, compares only the choice
+    EnumMatch(Vec<MatchArm>), // match enum, foo => bar, lol => kek etc
+}
+
+//arms are tried in order, the first one with matching choice and passing guard is evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    //None for the wildcard arm
+    pub choice: Option<usize>,
+    //indices of variant values visible inside the arm, in the order of its columns
+    pub bindings: Vec<usize>,
+    pub guard: Option<Expression>,
+    pub expression: Expression,
+}
+
+impl MatchArm {
+    fn evaluate(&self, enum_value: &EnumValue) -> Result<Option<DBValue>, ExecutorError> {
+        if self
+            .choice
+            .is_some_and(|choice| choice != enum_value.choice)
+        {
+            return Ok(None);
+        }
+
+        let scope = Message {
+            type_name: None,
+            fields: self
+                .bindings
+                .iter()
+                .map(|index| enum_value.values[*index].clone())
+                .collect(),
+        };
+
+        if let Some(guard) = &self.guard {
+            match guard.evaluate(&scope)? {
+                DBValue::Bool(true) => {}
+                DBValue::Bool(false) => return Ok(None),
+                _ => panic!("Incorrect match guard"),
+            }
+        }
+
+        self.expression.evaluate(&scope).map(Some)
+    }
 }
 
 impl UnaryOperator {
-    pub fn apply(&self, value: DBValue) -> DBValue {
+    pub fn apply(&self, value: DBValue) -> Result<DBValue, ExecutorError> {
         match self {
             UnaryOperator::Negate => match value {
-                DBValue::Int(x) => DBValue::Int(-x),
-                DBValue::Double(x) => DBValue::Double(-x),
+                DBValue::Int(x) => x
+                    .checked_neg()
+                    .map(DBValue::Int)
+                    .ok_or_else(|| ExecutorError::ArithmeticError("overflow in Negate".to_owned())),
+                DBValue::Double(x) => Ok(DBValue::Double(-x)),
                 _ => panic!("Incorrect negation"),
             },
             UnaryOperator::Not => match value {
-                DBValue::Bool(x) => DBValue::Bool(!x),
+                DBValue::Bool(x) => Ok(DBValue::Bool(!x)),
                 _ => panic!("Incorrect logical not"),
             },
             UnaryOperator::MessageField(index) => match value {
-                DBValue::Message(message) => message.fields[*index].clone(),
+                DBValue::Message(message) => Ok(message.fields[*index].clone()),
                 _ => panic!("Incorrect message field ref"),
             },
-            UnaryOperator::EnumMatch(expressions) => match value {
+            UnaryOperator::IsVariant(choice) => match value {
+                DBValue::EnumValue(enum_value) => Ok(DBValue::Bool(enum_value.choice == *choice)),
+                _ => panic!("Incorrect enum variant test"),
+            },
+            UnaryOperator::EnumMatch(arms) => match value {
                 DBValue::EnumValue(enum_value) => {
-                    expressions[enum_value.choice].evaluate(&Message {
-                        type_name: None,
-                        fields: enum_value.values.clone(),
-                    })
+                    for arm in arms.iter() {
+                        if let Some(result) = arm.evaluate(&enum_value)? {
+                            return Ok(result);
+                        }
+                    }
+                    panic!("Non exhaustive enum matching")
                 }
                 _ => panic!("Incorrect enum matching"),
             },
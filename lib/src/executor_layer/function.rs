@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::error::ExecutorError;
+use super::expression::Expression;
+use super::schema::*;
+
+pub type FunctionBody = Arc<dyn Fn(&[DBValue]) -> Result<DBValue, ExecutorError> + Send + Sync>;
+
+//takes argument types, returns expected argument types and the return type
+pub type SignatureResolver = fn(&[DBType]) -> Option<(Vec<DBType>, DBType)>;
+
+//Lazy functions receive their arguments unevaluated so they can skip some of them
+pub type LazyFunctionBody = fn(&[Expression], &Message) -> Result<DBValue, ExecutorError>;
+
+#[derive(Clone)]
+pub enum Implementation {
+    Eager(FunctionBody),
+    Lazy(LazyFunctionBody),
+}
+
+#[derive(Clone, Debug)]
+pub enum Signature {
+    Exact {
+        arguments: Vec<DBType>,
+        return_type: DBType,
+    },
+    //any positive number of arguments of the same type
+    Variadic {
+        argument: DBType,
+        return_type: DBType,
+    },
+    Custom(SignatureResolver),
+}
+
+impl Signature {
+    //Returns the types arguments have to be coerced to and the return type, None if the call
+    //can not match this signature
+    pub fn resolve(&self, argument_types: &[DBType]) -> Option<(Vec<DBType>, DBType)> {
+        match self {
+            Signature::Exact {
+                arguments,
+                return_type,
+            } => (arguments.len() == argument_types.len())
+                .then(|| (arguments.clone(), return_type.clone())),
+            Signature::Variadic {
+                argument,
+                return_type,
+            } => (!argument_types.is_empty()).then(|| {
+                (
+                    vec![argument.clone(); argument_types.len()],
+                    return_type.clone(),
+                )
+            }),
+            Signature::Custom(resolve) => resolve(argument_types),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ScalarFunction {
+    pub name: String,
+    pub signature: Signature,
+    //deterministic functions called with literal arguments are evaluated during planning
+    pub deterministic: bool,
+    pub implementation: Implementation,
+}
+
+impl ScalarFunction {
+    pub fn new<F>(name: &str, signature: Signature, body: F) -> Self
+    where
+        F: Fn(&[DBValue]) -> Result<DBValue, ExecutorError> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_owned(),
+            signature,
+            deterministic: true,
+            implementation: Implementation::Eager(Arc::new(body)),
+        }
+    }
+
+    pub fn lazy(name: &str, signature: Signature, body: LazyFunctionBody) -> Self {
+        Self {
+            name: name.to_owned(),
+            signature,
+            deterministic: true,
+            implementation: Implementation::Lazy(body),
+        }
+    }
+
+    //marks function as one that may return different results for the same arguments
+    pub fn nondeterministic(mut self) -> Self {
+        self.deterministic = false;
+        self
+    }
+
+    pub fn call(&self, args: &[Expression], message: &Message) -> Result<DBValue, ExecutorError> {
+        match &self.implementation {
+            Implementation::Eager(body) => {
+                let values = args
+                    .iter()
+                    .map(|arg| arg.evaluate(message))
+                    .collect::<Result<Vec<DBValue>, ExecutorError>>()?;
+                body(&values)
+            }
+            Implementation::Lazy(body) => body(args, message),
+        }
+    }
+}
+
+impl fmt::Debug for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScalarFunction")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .field("deterministic", &self.deterministic)
+            .finish()
+    }
+}
+
+//function names are unique within a registry
+impl PartialEq for ScalarFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, ScalarFunction>,
+}
+
+impl FunctionRegistry {
+    pub fn empty() -> Self {
+        Self {
+            functions: HashMap::new(),
+        }
+    }
+
+    //returns the previously registered function with the same name
+    pub fn register(&mut self, function: ScalarFunction) -> Option<ScalarFunction> {
+        self.functions.insert(function.name.clone(), function)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ScalarFunction> {
+        self.functions.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for function in builtin_functions() {
+            registry.register(function);
+        }
+        registry
+    }
+}
+
+fn string_arg(value: &DBValue) -> &str {
+    match value {
+        DBValue::String(s) => s,
+        _ => panic!("Incorrect string argument"),
+    }
+}
+
+fn int_arg(value: &DBValue) -> i32 {
+    match value {
+        DBValue::Int(i) => *i,
+        _ => panic!("Incorrect int argument"),
+    }
+}
+
+fn double_arg(value: &DBValue) -> f32 {
+    match value {
+        DBValue::Double(d) => *d,
+        _ => panic!("Incorrect double argument"),
+    }
+}
+
+fn exact(arguments: Vec<DBType>, return_type: DBType) -> Signature {
+    Signature::Exact {
+        arguments,
+        return_type,
+    }
+}
+
+//To add a new built-in function append it here
+pub fn builtin_functions() -> Vec<ScalarFunction> {
+    vec![
+        ScalarFunction::new("length", exact(vec![DBType::String], DBType::Int), |args| {
+            Ok(DBValue::Int(string_arg(&args[0]).chars().count() as i32))
+        }),
+        ScalarFunction::new(
+            "lower",
+            exact(vec![DBType::String], DBType::String),
+            |args| Ok(DBValue::String(string_arg(&args[0]).to_lowercase())),
+        ),
+        ScalarFunction::new(
+            "upper",
+            exact(vec![DBType::String], DBType::String),
+            |args| Ok(DBValue::String(string_arg(&args[0]).to_uppercase())),
+        ),
+        //substr(string, start) or substr(string, start, length), start is 1-based
+        ScalarFunction::new(
+            "substr",
+            Signature::Custom(|types| match types.len() {
+                2 | 3 => {
+                    let mut arguments = vec![DBType::String];
+                    arguments.resize(types.len(), DBType::Int);
+                    Some((arguments, DBType::String))
+                }
+                _ => None,
+            }),
+            |args| {
+                let start = (int_arg(&args[1]).max(1) - 1) as usize;
+                let chars = string_arg(&args[0]).chars().skip(start);
+                let result = match args.get(2) {
+                    Some(length) => {
+                        let length = usize::try_from(int_arg(length)).map_err(|_| {
+                            ExecutorError::FunctionError(
+                                "substr length must not be negative".to_owned(),
+                            )
+                        })?;
+                        chars.take(length).collect()
+                    }
+                    None => chars.collect(),
+                };
+                Ok(DBValue::String(result))
+            },
+        ),
+        ScalarFunction::new(
+            "concat",
+            Signature::Variadic {
+                argument: DBType::String,
+                return_type: DBType::String,
+            },
+            |args| Ok(DBValue::String(args.iter().map(string_arg).collect())),
+        ),
+        ScalarFunction::new(
+            "starts_with",
+            exact(vec![DBType::String, DBType::String], DBType::Bool),
+            |args| {
+                Ok(DBValue::Bool(
+                    string_arg(&args[0]).starts_with(string_arg(&args[1])),
+                ))
+            },
+        ),
+        ScalarFunction::new(
+            "contains",
+            exact(vec![DBType::String, DBType::String], DBType::Bool),
+            |args| {
+                Ok(DBValue::Bool(
+                    string_arg(&args[0]).contains(string_arg(&args[1])),
+                ))
+            },
+        ),
+        ScalarFunction::new(
+            "abs",
+            Signature::Custom(|types| match types {
+                [db_type] if db_type.numeric_rank().is_some() => {
+                    Some((types.to_vec(), db_type.clone()))
+                }
+                _ => None,
+            }),
+            |args| match &args[0] {
+                DBValue::Int(i) => i
+                    .checked_abs()
+                    .map(DBValue::Int)
+                    .ok_or_else(|| ExecutorError::FunctionError(format!("abs of {} overflows", i))),
+                DBValue::UInt(u) => Ok(DBValue::UInt(*u)),
+                DBValue::Double(d) => Ok(DBValue::Double(d.abs())),
+                _ => panic!("Incorrect abs argument"),
+            },
+        ),
+        ScalarFunction::new(
+            "round",
+            exact(vec![DBType::Double], DBType::Double),
+            |args| Ok(DBValue::Double(double_arg(&args[0]).round())),
+        ),
+        ScalarFunction::new(
+            "floor",
+            exact(vec![DBType::Double], DBType::Double),
+            |args| Ok(DBValue::Double(double_arg(&args[0]).floor())),
+        ),
+        ScalarFunction::new(
+            "sqrt",
+            exact(vec![DBType::Double], DBType::Double),
+            |args| {
+                let value = double_arg(&args[0]);
+                if value < 0f32 {
+                    return Err(ExecutorError::FunctionError(format!(
+                        "sqrt of negative number {}",
+                        value
+                    )));
+                }
+                Ok(DBValue::Double(value.sqrt()))
+            },
+        ),
+        ScalarFunction::new(
+            "pow",
+            exact(vec![DBType::Double, DBType::Double], DBType::Double),
+            |args| {
+                Ok(DBValue::Double(
+                    double_arg(&args[0]).powf(double_arg(&args[1])),
+                ))
+            },
+        ),
+        //if(condition, then, else) evaluates only the chosen branch
+        ScalarFunction::lazy(
+            "if",
+            Signature::Custom(|types| match types {
+                [DBType::Bool, then_type, else_type] => {
+                    let common_type = then_type.common_type(else_type)?;
+                    Some((
+                        vec![DBType::Bool, common_type.clone(), common_type.clone()],
+                        common_type,
+                    ))
+                }
+                _ => None,
+            }),
+            |args, message| match args[0].evaluate(message)? {
+                DBValue::Bool(true) => args[1].evaluate(message),
+                DBValue::Bool(false) => args[2].evaluate(message),
+                _ => panic!("Incorrect if condition"),
+            },
+        ),
+        //coalesce(a, b, ...) returns the first argument that is not null. Values can not be null
+        //yet, so the other arguments are only type checked and never evaluated
+        ScalarFunction::lazy(
+            "coalesce",
+            Signature::Custom(|types| {
+                let (first, rest) = types.split_first()?;
+                let common_type = rest
+                    .iter()
+                    .try_fold(first.clone(), |acc, db_type| acc.common_type(db_type))?;
+                Some((vec![common_type.clone(); types.len()], common_type))
+            }),
+            |args, message| args[0].evaluate(message),
+        ),
+    ]
+}
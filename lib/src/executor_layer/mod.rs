@@ -1,17 +1,22 @@
 pub mod error;
 pub mod expression;
+pub mod function;
 pub mod object_storage;
 pub mod operator;
+pub mod pattern;
 pub mod physical_plan;
 pub mod schema;
+pub mod subquery;
 pub mod table_manager;
 
 #[cfg(test)]
 mod tests {
     use std::boxed::Box;
+    use std::sync::Arc;
 
     use super::super::storage_layer::paged_storage::PagedStorage;
-    use super::expression::{BinaryOperator, Expression, UnaryOperator};
+    use super::error::ExecutorError;
+    use super::expression::{BinaryOperator, Expression, MatchArm, UnaryOperator};
     use super::object_storage::ObjectStorage;
     use super::operator::*;
     use super::physical_plan::PhysicalPlan;
@@ -43,7 +48,7 @@ mod tests {
             fields: vec![],
         };
 
-        assert_eq!(first.evaluate(&empty_message), DBValue::UInt(3u32));
+        assert_eq!(first.evaluate(&empty_message).unwrap(), DBValue::UInt(3u32));
 
         let a = Message {
             type_name: None,
@@ -64,11 +69,11 @@ mod tests {
             left: Box::new(Expression::ColumnRef(0usize)),
             right: Box::new(Expression::ColumnRef(1usize)),
         };
-        assert_eq!(second.evaluate(&a), DBValue::Bool(true));
+        assert_eq!(second.evaluate(&a).unwrap(), DBValue::Bool(true));
 
         let third = Expression::ColumnRef(3usize);
         assert_eq!(
-            third.evaluate(&a),
+            third.evaluate(&a).unwrap(),
             DBValue::String("Hello world".to_owned())
         );
 
@@ -80,13 +85,13 @@ mod tests {
                 expr: Box::new(Expression::ColumnRef(4usize)),
             }),
         };
-        assert_eq!(fourth.evaluate(&a), DBValue::Bool(false));
+        assert_eq!(fourth.evaluate(&a).unwrap(), DBValue::Bool(false));
 
         let fifth = Expression::UnaryOp {
             op: UnaryOperator::Not,
             expr: Box::new(Expression::ColumnRef(2usize)),
         };
-        assert_eq!(fifth.evaluate(&a), DBValue::Bool(false));
+        assert_eq!(fifth.evaluate(&a).unwrap(), DBValue::Bool(false));
 
         let b = Message {
             type_name: None,
@@ -108,22 +113,175 @@ mod tests {
 
         let sixth = Expression::UnaryOp {
             op: UnaryOperator::EnumMatch(vec![
-                Expression::ColumnRef(0usize),
-                Expression::Literal(DBValue::String("Second option".to_owned())),
+                MatchArm {
+                    choice: Some(0usize),
+                    bindings: vec![0usize],
+                    guard: None,
+                    expression: Expression::ColumnRef(0usize),
+                },
+                MatchArm {
+                    choice: None,
+                    bindings: vec![],
+                    guard: None,
+                    expression: Expression::Literal(DBValue::String("Second option".to_owned())),
+                },
             ]),
             expr: Box::new(Expression::ColumnRef(0usize)),
         };
 
         assert_eq!(
-            sixth.evaluate(&b),
+            sixth.evaluate(&b).unwrap(),
             DBValue::String("First option".to_owned())
         );
         assert_eq!(
-            sixth.evaluate(&c),
+            sixth.evaluate(&c).unwrap(),
             DBValue::String("Second option".to_owned())
         );
     }
 
+    #[test]
+    fn comparison_operators_test() {
+        let empty_message = Message {
+            type_name: None,
+            fields: vec![],
+        };
+
+        let apply = |op: BinaryOperator, left: DBValue, right: DBValue| {
+            Expression::BinaryOp {
+                op,
+                left: Box::new(Expression::Literal(left)),
+                right: Box::new(Expression::Literal(right)),
+            }
+            .evaluate(&empty_message)
+            .unwrap()
+        };
+
+        assert_eq!(
+            apply(
+                BinaryOperator::LessOrEqual,
+                DBValue::Int(3),
+                DBValue::Int(3)
+            ),
+            DBValue::Bool(true)
+        );
+        assert_eq!(
+            apply(
+                BinaryOperator::GreaterOrEqual,
+                DBValue::Double(1.5f32),
+                DBValue::Double(2.5f32)
+            ),
+            DBValue::Bool(false)
+        );
+        assert_eq!(
+            apply(BinaryOperator::Modulo, DBValue::UInt(17), DBValue::UInt(5)),
+            DBValue::UInt(2)
+        );
+
+        //integer arithmetic errors fail the row instead of the process
+        let fails = |op: BinaryOperator, left: DBValue, right: DBValue| {
+            matches!(
+                Expression::BinaryOp {
+                    op,
+                    left: Box::new(Expression::Literal(left)),
+                    right: Box::new(Expression::Literal(right)),
+                }
+                .evaluate(&empty_message),
+                Err(ExecutorError::ArithmeticError(_))
+            )
+        };
+        assert!(fails(
+            BinaryOperator::Modulo,
+            DBValue::Int(7),
+            DBValue::Int(0)
+        ));
+        assert!(fails(
+            BinaryOperator::Modulo,
+            DBValue::UInt(7),
+            DBValue::UInt(0)
+        ));
+        assert!(fails(
+            BinaryOperator::Modulo,
+            DBValue::Int(i32::MIN),
+            DBValue::Int(-1)
+        ));
+        assert!(fails(
+            BinaryOperator::Divide,
+            DBValue::Int(7),
+            DBValue::Int(0)
+        ));
+        assert!(fails(
+            BinaryOperator::Subtract,
+            DBValue::UInt(2),
+            DBValue::UInt(5)
+        ));
+        assert!(fails(
+            BinaryOperator::Multiply,
+            DBValue::Int(i32::MAX),
+            DBValue::Int(2)
+        ));
+        assert_eq!(
+            apply(
+                BinaryOperator::Concat,
+                DBValue::String("Hello ".to_owned()),
+                DBValue::String("world".to_owned())
+            ),
+            DBValue::String("Hello world".to_owned())
+        );
+        assert_eq!(
+            apply(
+                BinaryOperator::LessThan,
+                DBValue::Bool(false),
+                DBValue::Bool(true)
+            ),
+            DBValue::Bool(true)
+        );
+
+        let small = DBValue::Message(Message {
+            type_name: None,
+            fields: vec![DBValue::Int(1), DBValue::String("b".to_owned())],
+        });
+        let big = DBValue::Message(Message {
+            type_name: None,
+            fields: vec![DBValue::Int(1), DBValue::String("c".to_owned())],
+        });
+        assert_eq!(
+            apply(BinaryOperator::LessThan, small.clone(), big.clone()),
+            DBValue::Bool(true)
+        );
+        assert_eq!(
+            apply(BinaryOperator::GreaterOrEqual, small.clone(), small),
+            DBValue::Bool(true)
+        );
+
+        //enums are ordered by variant first
+        let first = DBValue::EnumValue(EnumValue {
+            type_name: None,
+            choice: 0usize,
+            values: vec![DBValue::Int(100)],
+        });
+        let second = DBValue::EnumValue(EnumValue {
+            type_name: None,
+            choice: 1usize,
+            values: vec![],
+        });
+        assert_eq!(
+            apply(BinaryOperator::GreaterThan, second, first),
+            DBValue::Bool(true)
+        );
+
+        let in_list = Expression::InList {
+            expr: Box::new(Expression::Literal(DBValue::Int(5))),
+            list: vec![
+                Expression::Literal(DBValue::Int(1)),
+                Expression::Literal(DBValue::Int(5)),
+            ],
+        };
+        assert_eq!(
+            in_list.evaluate(&empty_message).unwrap(),
+            DBValue::Bool(true)
+        );
+    }
+
     #[test]
     fn enum_matching_test() {
         let enum_type = DBType::EnumType(EnumType {
@@ -587,10 +745,78 @@ mod tests {
 
         physical_plan.open().unwrap();
 
-        let retreived_messages: Vec<Message> = physical_plan.collect();
+        let retreived_messages: Vec<Message> = physical_plan
+            .collect::<Result<Vec<Message>, ExecutorError>>()
+            .unwrap();
 
         assert_eq!(retreived_messages, expected);
 
         utility::cleanup(path);
     }
+
+    #[test]
+    fn deduplication_test() {
+        let message = |type_name: Option<&str>, fields: Vec<DBValue>| Message {
+            type_name: type_name.map(|name| name.to_owned()),
+            fields,
+        };
+        let rows = vec![
+            message(None, vec![DBValue::Double(0.0), DBValue::Int(1)]),
+            message(None, vec![DBValue::Double(-0.0), DBValue::Int(1)]),
+            message(None, vec![DBValue::Double(f32::NAN), DBValue::Int(1)]),
+            message(None, vec![DBValue::Double(-f32::NAN), DBValue::Int(1)]),
+            message(
+                None,
+                vec![DBValue::Message(message(
+                    Some("Point"),
+                    vec![DBValue::Int(1), DBValue::Int(2)],
+                ))],
+            ),
+            message(
+                None,
+                vec![DBValue::Message(message(
+                    None,
+                    vec![DBValue::Int(1), DBValue::Int(2)],
+                ))],
+            ),
+            message(
+                None,
+                vec![DBValue::EnumValue(EnumValue {
+                    type_name: None,
+                    choice: 1,
+                    values: vec![DBValue::Int(1), DBValue::Int(2)],
+                })],
+            ),
+        ];
+
+        let mut distinct = Distinct::new(Box::new(Values::new(Arc::new(rows.clone()))));
+        distinct.open().unwrap();
+        let distinct_rows = distinct
+            .collect::<Result<Vec<Message>, ExecutorError>>()
+            .unwrap();
+
+        //-0.0 and 0.0, NaNs and type names of nested messages do not make rows different
+        let keys = |rows: Vec<Message>| {
+            rows.into_iter()
+                .map(|row| RowKey(row.fields))
+                .collect::<Vec<RowKey>>()
+        };
+        assert_eq!(
+            keys(distinct_rows),
+            keys(vec![
+                rows[0].clone(),
+                rows[2].clone(),
+                rows[4].clone(),
+                rows[6].clone()
+            ])
+        );
+
+        let mut except = SetFilter::new(
+            Box::new(Values::new(Arc::new(rows.clone()))),
+            Box::new(Values::new(Arc::new(vec![rows[1].clone()]))),
+            false,
+        );
+        except.open().unwrap();
+        assert_eq!(except.count(), 5);
+    }
 }
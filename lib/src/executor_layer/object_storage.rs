@@ -31,38 +31,78 @@ pub enum WrappedMessage {
     Index(PageId),
 }
 
+// Position of an iterator inside a table. Iteration can be stopped and resumed from it later,
+// e.g. after the table was modified
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScanCursor {
+    page_index: usize,
+    page_offset: usize,
+    page_obj_count: usize,
+    //(last page index, object count on it) when the scan was started, later rows are skipped
+    end: Option<(usize, usize)>,
+}
+
+impl ScanCursor {
+    //position in rows kept in memory, only the offset is used
+    pub fn in_memory(offset: usize) -> Self {
+        Self {
+            page_offset: offset,
+            ..Default::default()
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.page_offset
+    }
+
+    fn is_finished(&self, pages_count: usize) -> bool {
+        match self.end {
+            Some((last_page, obj_count)) => {
+                self.page_index > last_page
+                    || (self.page_index == last_page && self.page_obj_count >= obj_count)
+            }
+            None => self.page_index >= pages_count,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct MessageIterator<'a, 'b> {
     object_storage: &'a ObjectStorage,
     //we need to acces page ref directly here to avoid copying it's content every read
     paged_storage: &'b PagedStorage,
-    page_index: usize,
-    page_offset: usize,
-    page_obj_count: usize,
+    cursor: ScanCursor,
+}
+
+impl MessageIterator<'_, '_> {
+    pub fn cursor(&self) -> ScanCursor {
+        self.cursor
+    }
 }
 
 impl Iterator for MessageIterator<'_, '_> {
     type Item = Message;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.page_index >= self.object_storage.pages.len() {
+        let cursor = &mut self.cursor;
+        if cursor.is_finished(self.object_storage.pages.len()) {
             return None;
         }
 
-        let page_id = self.object_storage.pages[self.page_index];
+        let page_id = self.object_storage.pages[cursor.page_index];
         let page = self.paged_storage.buffer_pool().get_page(page_id).unwrap();
 
-        let encoded_segment = &page.0.data[self.page_offset..];
+        let encoded_segment = &page.0.data[cursor.page_offset..];
         let (message, len) =
             ObjectStorage::decode_and_unwrap(self.paged_storage.storage(), encoded_segment)
                 .unwrap();
 
-        self.page_offset += len;
-        self.page_obj_count += 1usize;
-        if self.page_obj_count == page.0.header.obj_count {
-            self.page_offset = 0usize;
-            self.page_obj_count = 0usize;
-            self.page_index += 1;
+        cursor.page_offset += len;
+        cursor.page_obj_count += 1usize;
+        if cursor.page_obj_count == page.0.header.obj_count {
+            cursor.page_offset = 0usize;
+            cursor.page_obj_count = 0usize;
+            cursor.page_index += 1;
         }
 
         Some(message)
@@ -177,12 +217,34 @@ impl ObjectStorage {
     }
 
     pub fn iter<'a, 'b>(&'a self, paged_storage: &'b PagedStorage) -> MessageIterator<'a, 'b> {
+        self.iter_from(paged_storage, ScanCursor::default())
+    }
+
+    pub fn iter_from<'a, 'b>(
+        &'a self,
+        paged_storage: &'b PagedStorage,
+        cursor: ScanCursor,
+    ) -> MessageIterator<'a, 'b> {
         MessageIterator {
             object_storage: self,
             paged_storage,
-            page_index: 0usize,
-            page_offset: 0usize,
-            page_obj_count: 0usize,
+            cursor,
         }
     }
+
+    //cursor at the start of the table that does not see messages inserted after its creation
+    pub fn snapshot_cursor(
+        &self,
+        paged_storage: &PagedStorage,
+    ) -> Result<ScanCursor, ExecutorError> {
+        let end = match self.pages.last() {
+            Some(page_id) => (self.pages.len() - 1, paged_storage.get_obj_count(*page_id)?),
+            None => (0usize, 0usize),
+        };
+
+        Ok(ScanCursor {
+            end: Some(end),
+            ..ScanCursor::default()
+        })
+    }
 }
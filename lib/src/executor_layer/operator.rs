@@ -1,24 +1,43 @@
+use std::collections::HashSet;
 use std::iter::Iterator;
 use std::ops::DerefMut;
+use std::sync::Arc;
 
 use super::error::ExecutorError;
 use super::expression::Expression;
-use super::object_storage::MessageIterator;
-use super::schema::{DBValue, Message};
+use super::object_storage::{MessageIterator, ScanCursor};
+use super::schema::{DBValue, Message, RowKey};
 use super::table_manager::TableManager;
 
 //TODO rewrite everything to Box<enum>
 
 //At this stage we assume all physical operators are correctly planned by the query planner
-pub trait PhysicalOperator: Iterator<Item = Message> {
+//Errors raised while evaluating expressions on a row are yielded in place of that row
+pub trait PhysicalOperator: Iterator<Item = Result<Message, ExecutorError>> {
     //The contract is to call open before calling next
     fn open(&mut self) -> Result<(), ExecutorError>;
+
+    //Position of the underlying scans. A plan can be dropped and rebuilt later, then seek has to
+    //be called before open to continue from the same row
+    fn cursor(&self) -> Option<PlanCursor>;
+
+    fn seek(&mut self, cursor: PlanCursor);
+}
+
+//Cursors are nested like the operators that read from more than one source
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanCursor {
+    Scan(ScanCursor),
+    //positions of both sides, the right one is where its scan starts until the left one is read
+    Append(Box<PlanCursor>, Box<PlanCursor>),
 }
 
 pub struct TableScan<'a> {
     pub table_manager: &'a TableManager,
     pub table_name: String,
     pub iterator: Option<MessageIterator<'a, 'a>>,
+    //where to start on open, by default the scan sees the table as it was when opened
+    pub start: Option<ScanCursor>,
 }
 
 impl<'a> TableScan<'a> {
@@ -27,23 +46,46 @@ impl<'a> TableScan<'a> {
             table_manager,
             table_name,
             iterator: None,
+            start: None,
         }
     }
 }
 
 impl Iterator for TableScan<'_> {
-    type Item = Message;
+    type Item = Result<Message, ExecutorError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iterator.as_mut().and_then(|iter| iter.next())
+        self.iterator.as_mut().and_then(|iter| iter.next()).map(Ok)
     }
 }
 
 impl PhysicalOperator for TableScan<'_> {
     fn open(&mut self) -> Result<(), ExecutorError> {
-        self.iterator = Some(self.table_manager.iter(self.table_name.clone())?);
+        let cursor = match self.start {
+            Some(cursor) => cursor,
+            None => self
+                .table_manager
+                .snapshot_cursor(self.table_name.clone())?,
+        };
+        self.iterator = Some(
+            self.table_manager
+                .iter_from(self.table_name.clone(), cursor)?,
+        );
         Ok(())
     }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        match &self.iterator {
+            Some(iterator) => Some(PlanCursor::Scan(iterator.cursor())),
+            None => self.start.map(PlanCursor::Scan),
+        }
+    }
+
+    fn seek(&mut self, cursor: PlanCursor) {
+        if let PlanCursor::Scan(cursor) = cursor {
+            self.start = Some(cursor);
+        }
+    }
 }
 
 pub struct Projection<'a> {
@@ -52,26 +94,26 @@ pub struct Projection<'a> {
 }
 
 impl Projection<'_> {
-    pub fn project(&self, message: Message) -> Message {
-        Message {
+    pub fn project(&self, message: Message) -> Result<Message, ExecutorError> {
+        Ok(Message {
             type_name: None,
             fields: self
                 .expressions
                 .iter()
                 .map(|expression| expression.evaluate(&message))
-                .collect(),
-        }
+                .collect::<Result<Vec<DBValue>, ExecutorError>>()?,
+        })
     }
 }
 
 impl Iterator for Projection<'_> {
-    type Item = Message;
+    type Item = Result<Message, ExecutorError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.source
             .deref_mut()
             .next()
-            .map(|item| self.project(item))
+            .map(|item| item.and_then(|message| self.project(message)))
     }
 }
 
@@ -79,6 +121,14 @@ impl PhysicalOperator for Projection<'_> {
     fn open(&mut self) -> Result<(), ExecutorError> {
         self.source.deref_mut().open()
     }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        self.source.cursor()
+    }
+
+    fn seek(&mut self, cursor: PlanCursor) {
+        self.source.deref_mut().seek(cursor)
+    }
 }
 
 pub struct Filter<'a> {
@@ -88,13 +138,19 @@ pub struct Filter<'a> {
 }
 
 impl Iterator for Filter<'_> {
-    type Item = Message;
+    type Item = Result<Message, ExecutorError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         //cant use filter here bc it consumes the iterator
-        while let Some(message) = self.source.deref_mut().next() {
-            if self.filter_expr.evaluate(&message) == DBValue::Bool(true) {
-                return Some(message);
+        while let Some(item) = self.source.deref_mut().next() {
+            let message = match item {
+                Ok(message) => message,
+                Err(e) => return Some(Err(e)),
+            };
+            match self.filter_expr.evaluate(&message) {
+                Ok(DBValue::Bool(true)) => return Some(Ok(message)),
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
             }
         }
         None
@@ -105,7 +161,206 @@ impl PhysicalOperator for Filter<'_> {
     fn open(&mut self) -> Result<(), ExecutorError> {
         self.source.deref_mut().open()
     }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        self.source.cursor()
+    }
+
+    fn seek(&mut self, cursor: PlanCursor) {
+        self.source.deref_mut().seek(cursor)
+    }
+}
+
+//Rows computed before the query is executed
+pub struct Values {
+    pub rows: Arc<Vec<Message>>,
+    pub position: usize,
+}
+
+impl Values {
+    pub fn new(rows: Arc<Vec<Message>>) -> Self {
+        Self { rows, position: 0 }
+    }
+}
+
+impl Iterator for Values {
+    type Item = Result<Message, ExecutorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.rows.get(self.position)?.clone();
+        self.position += 1;
+        Some(Ok(message))
+    }
+}
+
+impl PhysicalOperator for Values {
+    fn open(&mut self) -> Result<(), ExecutorError> {
+        Ok(())
+    }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        Some(PlanCursor::Scan(ScanCursor::in_memory(self.position)))
+    }
+
+    fn seek(&mut self, cursor: PlanCursor) {
+        if let PlanCursor::Scan(cursor) = cursor {
+            self.position = cursor.offset();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperator {
+    Union,     // UNION
+    UnionAll,  // UNION ALL
+    Intersect, // INTERSECT
+    Except,    // EXCEPT
+}
+
+//Distinct and SetFilter keep state that is lost when the plan is rebuilt, so they can not be
+//resumed and report no cursor
+
+//Skips rows that were already returned
+pub struct Distinct<'a> {
+    pub source: Box<dyn PhysicalOperator + 'a>,
+    pub seen: HashSet<RowKey>,
+}
+
+impl<'a> Distinct<'a> {
+    pub fn new(source: Box<dyn PhysicalOperator + 'a>) -> Self {
+        Self {
+            source,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl Iterator for Distinct<'_> {
+    type Item = Result<Message, ExecutorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.source.deref_mut().next() {
+            let message = match item {
+                Ok(message) => message,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.seen.insert(RowKey(message.fields.clone())) {
+                return Some(Ok(message));
+            }
+        }
+        None
+    }
+}
+
+impl PhysicalOperator for Distinct<'_> {
+    fn open(&mut self) -> Result<(), ExecutorError> {
+        self.seen.clear();
+        self.source.deref_mut().open()
+    }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        None
+    }
+
+    fn seek(&mut self, _cursor: PlanCursor) {}
+}
+
+//Rows of the left source followed by rows of the right one
+pub struct Append<'a> {
+    pub left: Box<dyn PhysicalOperator + 'a>,
+    pub right: Box<dyn PhysicalOperator + 'a>,
+}
+
+impl Iterator for Append<'_> {
+    type Item = Result<Message, ExecutorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.left
+            .deref_mut()
+            .next()
+            .or_else(|| self.right.deref_mut().next())
+    }
+}
+
+impl PhysicalOperator for Append<'_> {
+    fn open(&mut self) -> Result<(), ExecutorError> {
+        self.left.deref_mut().open()?;
+        self.right.deref_mut().open()
+    }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        Some(PlanCursor::Append(
+            Box::new(self.left.cursor()?),
+            Box::new(self.right.cursor()?),
+        ))
+    }
+
+    fn seek(&mut self, cursor: PlanCursor) {
+        if let PlanCursor::Append(left, right) = cursor {
+            self.left.deref_mut().seek(*left);
+            self.right.deref_mut().seek(*right);
+        }
+    }
+}
+
+//Rows of the source that are (INTERSECT) or are not (EXCEPT) among the rows of the other source.
+//The other source is read into a hash set on open
+pub struct SetFilter<'a> {
+    pub source: Box<dyn PhysicalOperator + 'a>,
+    pub other: Box<dyn PhysicalOperator + 'a>,
+    pub keep_matching: bool,
+    pub other_rows: HashSet<RowKey>,
+}
+
+impl<'a> SetFilter<'a> {
+    pub fn new(
+        source: Box<dyn PhysicalOperator + 'a>,
+        other: Box<dyn PhysicalOperator + 'a>,
+        keep_matching: bool,
+    ) -> Self {
+        Self {
+            source,
+            other,
+            keep_matching,
+            other_rows: HashSet::new(),
+        }
+    }
+}
+
+impl Iterator for SetFilter<'_> {
+    type Item = Result<Message, ExecutorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.source.deref_mut().next() {
+            let message = match item {
+                Ok(message) => message,
+                Err(e) => return Some(Err(e)),
+            };
+            let key = RowKey(message.fields.clone());
+            if self.other_rows.contains(&key) == self.keep_matching {
+                return Some(Ok(message));
+            }
+        }
+        None
+    }
+}
+
+impl PhysicalOperator for SetFilter<'_> {
+    fn open(&mut self) -> Result<(), ExecutorError> {
+        self.other.deref_mut().open()?;
+        self.other_rows = self
+            .other
+            .deref_mut()
+            .map(|item| item.map(|message| RowKey(message.fields)))
+            .collect::<Result<HashSet<RowKey>, ExecutorError>>()?;
+        self.source.deref_mut().open()
+    }
+
+    fn cursor(&self) -> Option<PlanCursor> {
+        None
+    }
+
+    fn seek(&mut self, _cursor: PlanCursor) {}
 }
 
 //TODO order by, group by, join
-//TODO set union
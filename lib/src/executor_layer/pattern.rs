@@ -0,0 +1,67 @@
+use regex::{Regex, RegexBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternKind {
+    Like,  // LIKE, % matches any sequence and _ matches a single character
+    ILike, // ILIKE, case insensitive LIKE
+    Regex, // ~, matches if the regex matches any part of the string
+}
+
+// Patterns are compiled once while the query is planned
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: &str, kind: PatternKind) -> Result<Self, regex::Error> {
+        let regex = match kind {
+            PatternKind::Like => Self::build_like(source, false)?,
+            PatternKind::ILike => Self::build_like(source, true)?,
+            PatternKind::Regex => Regex::new(source)?,
+        };
+
+        Ok(Self {
+            kind,
+            source: source.to_owned(),
+            regex,
+        })
+    }
+
+    //backslash escapes the next character, so \% and \_ match literal symbols
+    fn build_like(source: &str, case_insensitive: bool) -> Result<Regex, regex::Error> {
+        let mut translated = String::from("^");
+        let mut chars = source.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '%' => translated.push_str(".*"),
+                '_' => translated.push('.'),
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        translated.push_str(&regex::escape(&escaped.to_string()));
+                    }
+                }
+                _ => translated.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        translated.push('$');
+
+        RegexBuilder::new(&translated)
+            .case_insensitive(case_insensitive)
+            .dot_matches_new_line(true)
+            .build()
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.source == other.source
+    }
+}
@@ -3,7 +3,7 @@ use std::iter::Iterator;
 use std::ops::DerefMut;
 
 use super::error::ExecutorError;
-use super::operator::PhysicalOperator;
+use super::operator::{PhysicalOperator, PlanCursor};
 use super::schema::*;
 
 pub struct PhysicalPlan<'a> {
@@ -14,10 +14,18 @@ impl PhysicalPlan<'_> {
     pub fn open(&mut self) -> Result<(), ExecutorError> {
         self.root.deref_mut().open()
     }
+
+    pub fn cursor(&self) -> Option<PlanCursor> {
+        self.root.cursor()
+    }
+
+    pub fn seek(&mut self, cursor: PlanCursor) {
+        self.root.deref_mut().seek(cursor)
+    }
 }
 
 impl Iterator for PhysicalPlan<'_> {
-    type Item = Message;
+    type Item = Result<Message, ExecutorError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.root.deref_mut().next()
@@ -1,4 +1,7 @@
+use std::cmp::Ordering;
 use std::convert::From;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::string::String;
 use std::vec::Vec;
 
@@ -16,6 +19,48 @@ pub enum DBType {
     EnumType(EnumType),
 }
 
+impl DBType {
+    //numeric types are implicitly widened along UInt -> Int -> Double
+    pub fn numeric_rank(&self) -> Option<usize> {
+        match self {
+            DBType::UInt => Some(0),
+            DBType::Int => Some(1),
+            DBType::Double => Some(2),
+            _ => None,
+        }
+    }
+
+    //returns the type both sides are converted to without an explicit cast. The conversion is
+    //checked: UInt values above the Int range fail it, like an overflow would. Comparisons of
+    //UInt and Int are exact and do not use it, see DBValue::equals
+    pub fn common_type(&self, other: &DBType) -> Option<DBType> {
+        if self == other {
+            return Some(self.clone());
+        }
+
+        match (self.numeric_rank(), other.numeric_rank()) {
+            (Some(l), Some(r)) if l > r => Some(self.clone()),
+            (Some(_), Some(_)) => Some(other.clone()),
+            _ => None,
+        }
+    }
+}
+
+//types are written the way they are declared in dbuf files
+impl fmt::Display for DBType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DBType::Bool => write!(f, "Bool"),
+            DBType::Double => write!(f, "Float"),
+            DBType::Int => write!(f, "Int"),
+            DBType::UInt => write!(f, "Unsigned"),
+            DBType::String => write!(f, "String"),
+            DBType::MessageType(message_type) => write!(f, "{}", message_type.name),
+            DBType::EnumType(enum_type) => write!(f, "{}", enum_type.name),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Encode, Decode)]
 pub struct Column {
     pub column_name: String,
@@ -41,6 +86,130 @@ pub enum DBValue {
     EnumValue(EnumValue),
 }
 
+impl DBValue {
+    //position of the variant in the declaration, values of different variants are ordered by it
+    fn rank(&self) -> usize {
+        match self {
+            DBValue::Bool(_) => 0,
+            DBValue::Double(_) => 1,
+            DBValue::Int(_) => 2,
+            DBValue::UInt(_) => 3,
+            DBValue::String(_) => 4,
+            DBValue::Message(_) => 5,
+            DBValue::EnumValue(_) => 6,
+        }
+    }
+
+    //Int and UInt values are compared exactly, without converting one into the other
+    pub fn compare_integers(&self, other: &DBValue) -> Option<Ordering> {
+        match (self, other) {
+            (DBValue::Int(l), DBValue::UInt(r)) => Some(i64::from(*l).cmp(&i64::from(*r))),
+            (DBValue::UInt(l), DBValue::Int(r)) => Some(i64::from(*l).cmp(&i64::from(*r))),
+            _ => None,
+        }
+    }
+
+    //== that also compares Int and UInt values exactly
+    pub fn equals(&self, other: &DBValue) -> bool {
+        match self.compare_integers(other) {
+            Some(ordering) => ordering == Ordering::Equal,
+            None => self == other,
+        }
+    }
+
+    //Total order over all values: messages are compared field by field, enums by variant
+    //index first and then by their content, doubles follow f32::total_cmp
+    pub fn compare(&self, other: &DBValue) -> Ordering {
+        match (self, other) {
+            (DBValue::Bool(l), DBValue::Bool(r)) => l.cmp(r),
+            (DBValue::Double(l), DBValue::Double(r)) => l.total_cmp(r),
+            (DBValue::Int(l), DBValue::Int(r)) => l.cmp(r),
+            (DBValue::UInt(l), DBValue::UInt(r)) => l.cmp(r),
+            (DBValue::String(l), DBValue::String(r)) => l.cmp(r),
+            (DBValue::Message(l), DBValue::Message(r)) => compare_values(&l.fields, &r.fields),
+            (DBValue::EnumValue(l), DBValue::EnumValue(r)) => l
+                .choice
+                .cmp(&r.choice)
+                .then_with(|| compare_values(&l.values, &r.values)),
+            (l, r) => l.rank().cmp(&r.rank()),
+        }
+    }
+}
+
+//-0.0 is the same as 0.0 and all NaNs are the same when values are deduplicated
+fn normalize_double(value: f32) -> f32 {
+    if value == 0.0 {
+        0.0
+    } else if value.is_nan() {
+        f32::NAN
+    } else {
+        value
+    }
+}
+
+//Values are hashed by their content, type names of messages and enums are ignored. The hash is
+//consistent with both == and RowKey equality
+impl Hash for DBValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            DBValue::Bool(value) => value.hash(state),
+            DBValue::Double(value) => normalize_double(*value).to_bits().hash(state),
+            DBValue::Int(value) => value.hash(state),
+            DBValue::UInt(value) => value.hash(state),
+            DBValue::String(value) => value.hash(state),
+            DBValue::Message(message) => message.fields.hash(state),
+            DBValue::EnumValue(enum_value) => {
+                enum_value.choice.hash(state);
+                enum_value.values.hash(state);
+            }
+        }
+    }
+}
+
+//Row used as a hash set key by DISTINCT and set operations. Unlike == it considers all NaNs
+//equal and ignores type names
+#[derive(Debug, Clone)]
+pub struct RowKey(pub Vec<DBValue>);
+
+fn same_values(left: &[DBValue], right: &[DBValue]) -> bool {
+    left.len() == right.len()
+        && left.iter().zip(right.iter()).all(|(l, r)| match (l, r) {
+            (DBValue::Double(l), DBValue::Double(r)) => {
+                normalize_double(*l).to_bits() == normalize_double(*r).to_bits()
+            }
+            (DBValue::Message(l), DBValue::Message(r)) => same_values(&l.fields, &r.fields),
+            (DBValue::EnumValue(l), DBValue::EnumValue(r)) => {
+                l.choice == r.choice && same_values(&l.values, &r.values)
+            }
+            (l, r) => l == r,
+        })
+}
+
+impl PartialEq for RowKey {
+    fn eq(&self, other: &Self) -> bool {
+        same_values(&self.0, &other.0)
+    }
+}
+
+impl Eq for RowKey {}
+
+impl Hash for RowKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+fn compare_values(left: &[DBValue], right: &[DBValue]) -> Ordering {
+    for (l, r) in left.iter().zip(right.iter()) {
+        let ordering = l.compare(r);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
 #[derive(PartialEq, Debug, Clone, Encode, Decode)]
 pub struct Message {
     //Should be not None for literals only
@@ -61,6 +230,21 @@ impl MessageType {
     }
 }
 
+//dependencies are listed after the column type
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "message {} {{", self.name)?;
+        for column in self.columns.iter() {
+            write!(f, "    {} {}", column.column_name, column.column_type)?;
+            for index in column.dependencies.iter() {
+                write!(f, " {}", self.columns[*index].column_name)?;
+            }
+            writeln!(f, ";")?;
+        }
+        write!(f, "}}")
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Encode, Decode)]
 pub struct EnumVariantType {
     pub name: String,
@@ -100,6 +284,24 @@ pub struct EnumValue {
     pub values: Vec<DBValue>,
 }
 
+impl fmt::Display for EnumType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "enum {} {{", self.name)?;
+        for variant in self.variants.iter() {
+            write!(f, "    {}", variant.name)?;
+            if !variant.content.is_empty() {
+                write!(f, " {{")?;
+                for (field_name, field_type) in variant.content.iter() {
+                    write!(f, " {} {};", field_name, field_type)?;
+                }
+                write!(f, " }}")?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "}}")
+    }
+}
+
 impl EnumType {
     pub fn match_enum(&self, enum_value: &EnumValue) -> bool {
         enum_value.choice < self.variants.len()
@@ -0,0 +1,127 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use super::error::ExecutorError;
+use super::expression::Expression;
+use super::schema::*;
+
+//Subqueries are planned before the outer query runs. Rows of the subquery source that pass the
+//uncorrelated part of its condition are read once during planning and kept here, the correlated
+//part is evaluated for every outer row on inner fields followed by outer fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subquery {
+    pub rows: Arc<Vec<Message>>,
+    pub filter: Option<Expression>,
+    //selected expression, None for EXISTS
+    pub projection: Option<Expression>,
+    //outer columns the subquery depends on
+    pub outer_refs: Vec<usize>,
+}
+
+impl Subquery {
+    fn matching_rows<'a>(
+        &'a self,
+        outer: &'a Message,
+    ) -> impl Iterator<Item = Result<Message, ExecutorError>> + 'a {
+        self.rows.iter().filter_map(move |row| {
+            let combined = Message {
+                type_name: None,
+                fields: row
+                    .fields
+                    .iter()
+                    .chain(outer.fields.iter())
+                    .cloned()
+                    .collect(),
+            };
+
+            match &self.filter {
+                Some(filter) => match filter.evaluate(&combined) {
+                    Ok(DBValue::Bool(true)) => Some(Ok(combined)),
+                    Ok(DBValue::Bool(false)) => None,
+                    Ok(_) => panic!("Incorrect subquery condition"),
+                    Err(e) => Some(Err(e)),
+                },
+                None => Some(Ok(combined)),
+            }
+        })
+    }
+
+    fn values<'a>(
+        &'a self,
+        outer: &'a Message,
+    ) -> impl Iterator<Item = Result<DBValue, ExecutorError>> + 'a {
+        let projection = self
+            .projection
+            .as_ref()
+            .expect("Subquery without projection");
+        self.matching_rows(outer)
+            .map(move |row| projection.evaluate(&row?))
+    }
+
+    pub fn exists(&self, outer: &Message) -> Result<bool, ExecutorError> {
+        self.matching_rows(outer)
+            .next()
+            .transpose()
+            .map(|row| row.is_some())
+    }
+
+    //values are matched like in ValueSet, so correlated and uncorrelated IN agree
+    pub fn contains(&self, value: &DBValue, outer: &Message) -> Result<bool, ExecutorError> {
+        for item in self.values(outer) {
+            if item?.compare(value) == Ordering::Equal {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    //scalar subqueries must return exactly one row
+    pub fn scalar(&self, outer: &Message) -> Result<DBValue, ExecutorError> {
+        let mut values = self.values(outer);
+        let value = match values.next() {
+            Some(value) => value?,
+            None => {
+                return Err(ExecutorError::SubqueryError(
+                    "scalar subquery returned no rows".to_owned(),
+                ));
+            }
+        };
+
+        if values.next().is_some() {
+            return Err(ExecutorError::SubqueryError(
+                "scalar subquery returned more than one row".to_owned(),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+//Values of an uncorrelated IN subquery, sorted so they can be searched with binary search
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueSet {
+    values: Arc<Vec<DBValue>>,
+}
+
+impl ValueSet {
+    pub fn new(mut values: Vec<DBValue>) -> Self {
+        values.sort_by(|l, r| l.compare(r));
+        values.dedup_by(|l, r| l.compare(r) == Ordering::Equal);
+        Self {
+            values: Arc::new(values),
+        }
+    }
+
+    pub fn contains(&self, value: &DBValue) -> bool {
+        self.values
+            .binary_search_by(|item| item.compare(value))
+            .is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
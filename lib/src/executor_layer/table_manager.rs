@@ -6,7 +6,7 @@ use super::super::storage_layer::{
     utils::{load, save},
 };
 use super::error::ExecutorError;
-use super::object_storage::{MessageIterator, ObjectStorage};
+use super::object_storage::{MessageIterator, ObjectStorage, ScanCursor};
 use super::schema::{Message, MessageType};
 
 use bincode::{Decode, Encode};
@@ -73,6 +73,45 @@ impl TableManager {
         Ok(())
     }
 
+    //schemas may only be replaced with ones that stored messages still match
+    pub fn update_schemas<F: FnMut(&MessageType) -> MessageType>(
+        &mut self,
+        mut update: F,
+    ) -> Result<(), ExecutorError> {
+        for object_storage in self.state.tables.values_mut() {
+            object_storage.schema = update(&object_storage.schema);
+        }
+
+        save(self.paged_storage.marble(), &self.state, TABLE_STATE_INDEX)?;
+        self.paged_storage.flush()?;
+
+        Ok(())
+    }
+
+    //moves the content of the replacement table into the table, old content is dropped
+    pub fn replace_table(
+        &mut self,
+        table_name: String,
+        replacement: String,
+    ) -> Result<(), ExecutorError> {
+        if !self.state.tables.contains_key(&table_name) {
+            return Err(ExecutorError::TableNotFound);
+        }
+        let object_storage = match self.state.tables.remove(&replacement) {
+            Some(object_storage) => object_storage,
+            None => return Err(ExecutorError::TableNotFound),
+        };
+
+        if let Some(mut old) = self.state.tables.insert(table_name, object_storage) {
+            old.drop_items(&mut self.paged_storage)?;
+        }
+
+        save(self.paged_storage.marble(), &self.state, TABLE_STATE_INDEX)?;
+        self.paged_storage.flush()?;
+
+        Ok(())
+    }
+
     pub fn insert_messages<T: Iterator<Item = Message>>(
         &mut self,
         table_name: String,
@@ -98,5 +137,23 @@ impl TableManager {
         }
     }
 
+    pub fn iter_from(
+        &self,
+        table_name: String,
+        cursor: ScanCursor,
+    ) -> Result<MessageIterator<'_, '_>, ExecutorError> {
+        match self.state.tables.get(&table_name) {
+            Some(object_storage) => Ok(object_storage.iter_from(&self.paged_storage, cursor)),
+            None => Err(ExecutorError::TableNotFound),
+        }
+    }
+
+    pub fn snapshot_cursor(&self, table_name: String) -> Result<ScanCursor, ExecutorError> {
+        match self.state.tables.get(&table_name) {
+            Some(object_storage) => object_storage.snapshot_cursor(&self.paged_storage),
+            None => Err(ExecutorError::TableNotFound),
+        }
+    }
+
     //TODO message deletions with FnMut
 }
@@ -1,20 +1,108 @@
+use bincode::{Decode, Encode};
+
+// FETCH TYPES "path" [REPLACE | CHECK]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FetchMode {
+    //only new types, existing types must not change
+    Add,
+    Replace,
+    //report the changes without applying them
+    Check,
+}
+
 #[derive(Debug, Clone)]
 pub enum Query {
-    FetchTypes(String),
+    FetchTypes(String, FetchMode),
     CreateTable(String, String),
+    // CREATE TABLE table [TypeName] AS SELECT ...
+    CreateTableAs {
+        table: String,
+        type_name: Option<String>,
+        select: SelectQuery,
+    },
     DropTable(String),
+    // DROP TYPE TypeName [CASCADE]
+    DropType {
+        type_name: String,
+        cascade: bool,
+    },
+    // ALTER TABLE table SET TYPE TypeName [USING expr]
+    AlterTableType {
+        table: String,
+        type_name: String,
+        using: Option<Expression>,
+    },
+    // CREATE [MATERIALIZED] VIEW name AS SELECT ...
+    CreateView {
+        name: String,
+        select: SelectQuery,
+        materialized: bool,
+    },
+    RefreshMaterializedView(String),
+    DropView(String),
     InsertMessages {
         table: String,
         messages: Vec<Message>,
     },
-    Select {
+    InsertSelect {
         table: String,
-        fields: Vec<(Expression, String)>,
-        condition: Option<Expression>,
+        select: SelectQuery,
     },
+    Select(SelectQuery),
+    ShowTables,
+    ShowTypes,
+    // DESCRIBE table
+    Describe(String),
+    // DESCRIBE TYPE TypeName
+    DescribeType(String),
 }
 
-#[derive(Debug, Clone)]
+impl Query {
+    //true if the query doesn't change the database, only such queries run in read-only mode
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Query::FetchTypes(_, FetchMode::Check)
+                | Query::Select(_)
+                | Query::ShowTables
+                | Query::ShowTypes
+                | Query::Describe(_)
+                | Query::DescribeType(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct SelectQuery {
+    pub table: String,
+    pub fields: Vec<(Expression, String)>,
+    pub condition: Option<Expression>,
+    // SELECT DISTINCT
+    pub distinct: bool,
+    // UNION / INTERSECT / EXCEPT with other queries, applied from left to right
+    pub compound: Vec<(SetOperator, SelectQuery)>,
+    // WITH name AS (SELECT ...), ... in front of the query
+    pub ctes: Vec<CommonTableExpression>,
+}
+
+#[derive(Debug, Clone, Copy, Encode, Decode)]
+pub enum SetOperator {
+    Union,     // UNION
+    UnionAll,  // UNION ALL
+    Intersect, // INTERSECT
+    Except,    // EXCEPT
+}
+
+// name AS (SELECT ...)
+// after WITH RECURSIVE the query may end with UNION [ALL] SELECT ... that refers to name
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct CommonTableExpression {
+    pub name: String,
+    pub query: SelectQuery,
+    pub recursive: bool,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum Value {
     Int(i32),
     Double(f32),
@@ -24,20 +112,22 @@ pub enum Value {
     Enum(Enum),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Message {
     pub type_name: String,
     pub values: Vec<Value>,
+    // Some for [Type { field: value, ... }], names are in the order of values
+    pub field_names: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Enum {
     pub type_name: String,
     pub variant_name: String,
     pub values: Vec<Value>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum Expression {
     Literal(Value),
     ColumnRef(String),
@@ -50,26 +140,103 @@ pub enum Expression {
         op: UnaryOperator,
         expr: Box<Expression>,
     },
+    // expr BETWEEN low AND high, bounds are inclusive
+    Between {
+        expr: Box<Expression>,
+        low: Box<Expression>,
+        high: Box<Expression>,
+    },
+    // expr IN (a, b, c)
+    InList {
+        expr: Box<Expression>,
+        list: Vec<Expression>,
+    },
+    // expr IN (SELECT ...)
+    InSubquery {
+        expr: Box<Expression>,
+        query: Box<SelectQuery>,
+    },
+    // EXISTS (SELECT ...)
+    Exists(Box<SelectQuery>),
+    // (SELECT ...) returning a single value
+    ScalarSubquery(Box<SelectQuery>),
+    // expr IS EnumType::Variant or expr IS NOT EnumType::Variant
+    IsVariant {
+        expr: Box<Expression>,
+        type_name: String,
+        variant_name: String,
+        negated: bool,
+    },
+    // [Type { expr, expr, ... }] or [Type { field: expr, ... }]
+    MessageConstructor {
+        type_name: String,
+        fields: Vec<Expression>,
+        field_names: Option<Vec<String>>,
+    },
+    // [Type::Variant { expr, expr, ... }]
+    EnumConstructor {
+        type_name: String,
+        variant_name: String,
+        fields: Vec<Expression>,
+    },
+    // CAST(expr AS Type)
+    Cast {
+        expr: Box<Expression>,
+        type_name: String,
+    },
+    // name(arg, arg, ...)
+    FunctionCall {
+        name: String,
+        args: Vec<Expression>,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Encode, Decode)]
 pub enum BinaryOperator {
-    Add,         // +
-    Subtract,    // -
-    Multiply,    // *
-    Divide,      // /
-    Equals,      // =
-    NotEquals,   // !=
-    LessThan,    // <
-    GreaterThan, // >
-    And,         // &
-    Or,          // |
+    Add,            // +
+    Subtract,       // -
+    Multiply,       // *
+    Divide,         // /
+    Modulo,         // %
+    Concat,         // ++
+    Equals,         // =
+    NotEquals,      // !=
+    LessThan,       // <
+    GreaterThan,    // >
+    LessOrEqual,    // <=
+    GreaterOrEqual, // >=
+    Like,           // LIKE
+    ILike,          // ILIKE
+    RegexMatch,     // ~
+    And,            // &
+    Or,             // |
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum UnaryOperator {
-    Negate,                               // unary minus
-    Not,                                  // !
-    MessageField(String),                 // foo.bar
-    EnumMatch(Vec<(String, Expression)>), // match EnumType.Foo => expression
+    Negate,                   // unary minus
+    Not,                      // !
+    MessageField(String),     // foo.bar
+    EnumMatch(Vec<MatchArm>), // MATCH foo { EnumType::Foo => expression }
+}
+
+// pattern if guard => expression
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct MatchArm {
+    pub pattern: MatchPattern,
+    pub guard: Option<Expression>,
+    pub expression: Expression,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum MatchPattern {
+    // _
+    Wildcard,
+    // EnumType::Variant or EnumType::Variant { field: alias, other_field }
+    // without braces all variant fields are available by their names
+    Variant {
+        type_name: String,
+        variant_name: String,
+        bindings: Option<Vec<(String, String)>>,
+    },
 }
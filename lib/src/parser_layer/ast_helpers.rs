@@ -1,4 +1,4 @@
-use super::ast::{BinaryOperator, Expression, UnaryOperator};
+use super::ast::{BinaryOperator, Enum, Expression, Message, UnaryOperator, Value};
 
 pub fn bin_op(op: BinaryOperator, left: Expression, right: Expression) -> Expression {
     Expression::BinaryOp {
@@ -14,3 +14,114 @@ pub fn unary_op(op: UnaryOperator, expr: Expression) -> Expression {
         expr: Box::new(expr),
     }
 }
+
+fn literal_values(fields: &[Expression]) -> Option<Vec<Value>> {
+    fields
+        .iter()
+        .map(|field| match field {
+            Expression::Literal(value) => Some(value.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+//constructors with only literal fields stay literals, so they can still be inserted
+pub fn message_constructor(
+    type_name: String,
+    (fields, field_names): (Vec<Expression>, Option<Vec<String>>),
+) -> Expression {
+    match literal_values(&fields) {
+        Some(values) => Expression::Literal(Value::Message(Message {
+            type_name,
+            values,
+            field_names,
+        })),
+        None => Expression::MessageConstructor {
+            type_name,
+            fields,
+            field_names,
+        },
+    }
+}
+
+//splits name: value pairs into values and names
+pub fn named_fields<T>(fields: Vec<(String, T)>) -> (Vec<T>, Option<Vec<String>>) {
+    let (names, values) = fields.into_iter().unzip();
+    (values, Some(names))
+}
+
+pub fn enum_constructor(
+    type_name: String,
+    variant_name: String,
+    fields: Vec<Expression>,
+) -> Expression {
+    match literal_values(&fields) {
+        Some(values) => Expression::Literal(Value::Enum(Enum {
+            type_name,
+            variant_name,
+            values,
+        })),
+        None => Expression::EnumConstructor {
+            type_name,
+            variant_name,
+            fields,
+        },
+    }
+}
+
+//Error of a grammar action with the span of the rejected tokens, offsets are relative to the
+//parsed text like the ones lalrpop reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionError {
+    pub start: usize,
+    pub end: usize,
+    pub message: &'static str,
+}
+
+//Replaces escape sequences in the contents of a string literal: \" \\ \n \r \t \0 and unicode
+//escapes like \u{1F600}
+pub fn unescape(literal: &str) -> Result<String, &'static str> {
+    let mut result = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('0') => result.push('\0'),
+            Some('u') => {
+                if chars.next() != Some('{') {
+                    return Err("Invalid unicode escape in string literal");
+                }
+                let mut digits = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    digits.push(c);
+                }
+                let code = match digits.len() {
+                    1..=6 if closed => u32::from_str_radix(&digits, 16).ok(),
+                    _ => None,
+                };
+                match code.and_then(char::from_u32) {
+                    Some(c) => result.push(c),
+                    None => return Err("Invalid unicode escape in string literal"),
+                }
+            }
+            _ => return Err("Invalid escape sequence in string literal"),
+        }
+    }
+
+    Ok(result)
+}
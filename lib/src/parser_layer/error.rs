@@ -1,28 +1,231 @@
+use std::fmt;
+
 use thiserror::Error;
 
+//Position in a dbuf file, lines and columns start from 1. Keeps the line it points to, so that
+//errors can be shown with a snippet
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    //number of highlighted characters
+    pub length: usize,
+    pub source_line: String,
+}
+
+impl SourceLocation {
+    pub fn from_offset(file: &str, text: &str, offset: usize) -> Self {
+        Self::from_span(file, text, offset, 1)
+    }
+
+    pub fn from_span(file: &str, text: &str, offset: usize, length: usize) -> Self {
+        let offset = offset.min(text.len());
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let line_end = text[offset..]
+            .find('\n')
+            .map_or(text.len(), |index| offset + index);
+        Self {
+            file: file.to_owned(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            length: length.max(1),
+            source_line: text[line_start..line_end].trim_end_matches('\r').to_owned(),
+        }
+    }
+
+    //highlighted part of the line
+    pub fn text(&self) -> String {
+        self.source_line
+            .chars()
+            .skip(self.column - 1)
+            .take(self.length)
+            .collect()
+    }
+
+    //the message followed by the location and the line with the span underlined:
+    //
+    //Unknown type: Usr
+    // --> user.dbuf:2:9
+    //  |
+    //2 |   owner Usr;
+    //  |         ^^^
+    pub fn render(&self, message: &str) -> String {
+        let number = self.line.to_string();
+        let margin = " ".repeat(number.len());
+        let line_length = self.source_line.chars().count();
+        let caret_length = self
+            .length
+            .min(line_length.saturating_sub(self.column - 1))
+            .max(1);
+        let source_line = self.source_line.replace('\t', " ");
+        format!(
+            "{}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}",
+            message,
+            margin,
+            self,
+            margin,
+            number,
+            source_line,
+            margin,
+            " ".repeat(self.column - 1),
+            "^".repeat(caret_length)
+        )
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ParsingError {
-    #[error("Could not parse types")]
-    TypesParseError,
+    //syntax errors of the dbuf parser with its message
+    #[error("Could not parse types: {0}")]
+    TypesParseError(String, SourceLocation),
+
+    #[error("Can't read {0}: {1}")]
+    ReadError(String, String),
+
+    #[error("No dbuf files found: {0}")]
+    NoFilesFound(String),
+
+    //names of the other errors are looked up in the source, their location is unknown if they
+    //can't be found
+    #[error("Unresolved type: {0}")]
+    UnresolvedType(String, Option<SourceLocation>),
+
+    //every type of the cycle with the location of its definition
+    #[error(
+        "Cyclic type definitions: {}",
+        .0.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>().join(" -> ")
+    )]
+    CyclicTypes(Vec<(String, Option<SourceLocation>)>),
 
     #[error("Cant deduce field type : {0}")]
-    CantDeduceFieldType(String),
+    CantDeduceFieldType(String, Option<SourceLocation>),
 
     #[error("Unknown type : {0}")]
-    UnknownType(String),
+    UnknownType(String, Option<SourceLocation>),
 
     #[error("Duplicate field: {0}")]
-    DuplicateField(String),
+    DuplicateField(String, Option<SourceLocation>),
 
     #[error("Duplicate type name: {0}")]
-    DuplicateTypeName(String),
+    DuplicateTypeName(String, Option<SourceLocation>),
 
     #[error("Duplicate variant name: {0}")]
-    DuplicateVariantName(String),
+    DuplicateVariantName(String, Option<SourceLocation>),
 
     #[error("Field not found: {0}")]
-    FieldNotFound(String),
+    FieldNotFound(String, Option<SourceLocation>),
 
     #[error("Invalid argument")]
-    InvalidArgument,
+    InvalidArgument(Option<SourceLocation>),
+}
+
+impl ParsingError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            ParsingError::TypesParseError(_, location) => Some(location),
+            ParsingError::ReadError(..) | ParsingError::NoFilesFound(_) => None,
+            ParsingError::CyclicTypes(types) => {
+                types.first().and_then(|(_, location)| location.as_ref())
+            }
+            ParsingError::UnresolvedType(_, location)
+            | ParsingError::CantDeduceFieldType(_, location)
+            | ParsingError::UnknownType(_, location)
+            | ParsingError::DuplicateField(_, location)
+            | ParsingError::DuplicateTypeName(_, location)
+            | ParsingError::DuplicateVariantName(_, location)
+            | ParsingError::FieldNotFound(_, location)
+            | ParsingError::InvalidArgument(location) => location.as_ref(),
+        }
+    }
+
+    //message with a snippet of the source if the error has a location
+    pub fn render(&self) -> String {
+        if let ParsingError::CyclicTypes(types) = self {
+            let mut rendered = self.to_string();
+            for (name, location) in types.iter() {
+                if let Some(location) = location {
+                    rendered.push('\n');
+                    rendered.push_str(&location.render(&format!("{} is defined here", name)));
+                }
+            }
+            return rendered;
+        }
+
+        match self.location() {
+            Some(location) => location.render(&self.to_string()),
+            None => self.to_string(),
+        }
+    }
+}
+
+//"`;`" or "one of `FROM`, `AS`, identifier"
+fn expected_list(expected: &[String]) -> String {
+    match expected {
+        [] => "nothing".to_owned(),
+        [token] => token.clone(),
+        _ => format!("one of {}", expected.join(", ")),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("Invalid token")]
+    InvalidToken(SourceLocation),
+
+    #[error("Unexpected end of query, expected {}", expected_list(.expected))]
+    UnexpectedEnd {
+        location: SourceLocation,
+        expected: Vec<String>,
+    },
+
+    #[error("Unexpected `{}`, expected {}", .location.text(), expected_list(.expected))]
+    UnexpectedToken {
+        location: SourceLocation,
+        expected: Vec<String>,
+        //keyword the token was probably meant to be
+        suggestion: Option<String>,
+    },
+
+    #[error("Unexpected `{}` after the end of the query", .0.text())]
+    ExtraToken(SourceLocation),
+
+    //errors reported by the grammar actions, like invalid escapes in string literals
+    #[error("{0}")]
+    InvalidQuery(String, SourceLocation),
+}
+
+impl QueryError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            QueryError::InvalidToken(location)
+            | QueryError::UnexpectedEnd { location, .. }
+            | QueryError::UnexpectedToken { location, .. }
+            | QueryError::ExtraToken(location)
+            | QueryError::InvalidQuery(_, location) => Some(location),
+        }
+    }
+
+    //message with a snippet of the query and a suggestion if there is one
+    pub fn render(&self) -> String {
+        let mut rendered = match self.location() {
+            Some(location) => location.render(&self.to_string()),
+            None => self.to_string(),
+        };
+        if let QueryError::UnexpectedToken {
+            suggestion: Some(suggestion),
+            ..
+        } = self
+        {
+            rendered.push_str(&format!("\nhelp: did you mean `{}`?", suggestion));
+        }
+        rendered
+    }
 }
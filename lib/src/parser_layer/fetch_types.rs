@@ -1,8 +1,9 @@
 use dbuf_core::ast::parsed::*;
-use dbuf_core::parser::parse;
+use dbuf_core::parser::{ParseError, parse};
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use super::super::executor_layer::schema::*;
 use super::error::*;
@@ -13,126 +14,494 @@ pub enum FetchedType {
     EnumType(EnumType),
 }
 
+//Contents of a dbuf file
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub path: String,
+    pub text: String,
+}
+
+fn primitive_types() -> HashMap<String, DBType> {
+    HashMap::from([
+        ("Bool".to_owned(), DBType::Bool),
+        ("Int".to_owned(), DBType::Int),
+        ("Unsigned".to_owned(), DBType::UInt),
+        ("Float".to_owned(), DBType::Double),
+        ("String".to_owned(), DBType::String),
+    ])
+}
+
 pub fn parse_types(data: String) -> Result<Vec<FetchedType>, ParsingError> {
-    let mut ans: Vec<FetchedType> = vec![];
+    let source = SourceFile {
+        path: "<input>".to_owned(),
+        text: data,
+    };
+    parse_sources(&[source], &HashMap::new())
+}
+
+//Reads a single file, every .dbuf file of a directory or files matching a glob pattern like
+//types/*.dbuf. Only the last component of the path may contain * and ?
+pub fn read_sources(path: &str) -> Result<Vec<SourceFile>, ParsingError> {
+    let read_error = |e: std::io::Error| ParsingError::ReadError(path.to_owned(), e.to_string());
+    let fs_path = Path::new(path);
+    let file_name = fs_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut paths = if fs_path.is_dir() {
+        std::fs::read_dir(fs_path)
+            .map_err(read_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, std::io::Error>>()
+            .map_err(read_error)?
+            .into_iter()
+            .filter(|file| {
+                file.extension()
+                    .is_some_and(|extension| extension == "dbuf")
+            })
+            .collect::<Vec<PathBuf>>()
+    } else if file_name.contains(['*', '?']) {
+        let directory = match fs_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let pattern = file_name.chars().collect::<Vec<char>>();
+        std::fs::read_dir(directory)
+            .map_err(read_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, std::io::Error>>()
+            .map_err(read_error)?
+            .into_iter()
+            .filter(|file| {
+                let name = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().chars().collect::<Vec<char>>())
+                    .unwrap_or_default();
+                file.is_file() && glob_match(&pattern, &name)
+            })
+            .collect()
+    } else {
+        vec![fs_path.to_path_buf()]
+    };
+
+    if paths.is_empty() {
+        return Err(ParsingError::NoFilesFound(path.to_owned()));
+    }
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|file| {
+            let display = file.to_string_lossy().to_string();
+            std::fs::read_to_string(&file)
+                .map(|text| SourceFile {
+                    path: display.clone(),
+                    text,
+                })
+                .map_err(|e| ParsingError::ReadError(display, e.to_string()))
+        })
+        .collect()
+}
+
+//* matches any sequence of characters, ? matches a single character
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((c, rest)) => match name.split_first() {
+            Some((first, name_rest)) => (*c == '?' || c == first) && glob_match(rest, name_rest),
+            None => false,
+        },
+    }
+}
+
+//byte offset of the first occurrence of the word that is not a part of a longer identifier
+fn find_word(text: &str, word: &str, from: usize) -> Option<usize> {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    text[from..]
+        .match_indices(word)
+        .map(|(index, _)| from + index)
+        .find(|index| {
+            let before = text[..*index].chars().next_back();
+            let after = text[index + word.len()..].chars().next();
+            !before.is_some_and(is_identifier) && !after.is_some_and(is_identifier)
+        })
+}
+
+//offset of the declaration of the type, found by its name after message or enum
+fn definition_offset(text: &str, name: &str, from: usize) -> Option<usize> {
+    let mut from = from;
+    while let Some(index) = find_word(text, name, from) {
+        let before = text[..index].trim_end();
+        if before.ends_with("message") || before.ends_with("enum") {
+            return Some(index);
+        }
+        from = index + name.len();
+    }
+    None
+}
+
+//Location of the first error of the dbuf parser, errors at the end of the file point right after
+//the last token. The token at the error is highlighted
+fn syntax_error(source: &SourceFile, errors: Vec<ParseError>) -> ParsingError {
+    let is_identifier = |c: &char| c.is_alphanumeric() || *c == '_';
+    let text = &source.text;
+    let (offset, message) = match errors.into_iter().next() {
+        Some(error) => (error.offset, error.message),
+        None => (text.len(), "invalid syntax".to_owned()),
+    };
+    let offset = offset.min(text.trim_end().len());
+    let length = text[offset..].chars().take_while(is_identifier).count();
+    ParsingError::TypesParseError(
+        message,
+        SourceLocation::from_span(&source.path, text, offset, length),
+    )
+}
+
+//The dbuf AST has no positions we rely on, so names from errors are found in the text of the
+//definition they come from. Names that can't be found have no location
+struct Locator<'a> {
+    source: &'a SourceFile,
+    name: &'a str,
+    start: Option<usize>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(source: &'a SourceFile, name: &'a str) -> Self {
+        Self {
+            source,
+            name,
+            start: definition_offset(&source.text, name, 0),
+        }
+    }
+
+    fn location(&self, offset: usize, word: &str) -> SourceLocation {
+        SourceLocation::from_span(
+            &self.source.path,
+            &self.source.text,
+            offset,
+            word.chars().count(),
+        )
+    }
 
-    let parsed = parse(&data).map_err(|_| ParsingError::TypesParseError)?;
+    fn find(&self, word: &str, from: usize) -> Option<usize> {
+        find_word(&self.source.text, word, from)
+    }
+
+    fn definition(&self) -> Option<SourceLocation> {
+        self.start.map(|start| self.location(start, self.name))
+    }
 
-    let mut type_cache = HashMap::<String, DBType>::new();
-    type_cache.insert("Bool".to_owned(), DBType::Bool);
-    type_cache.insert("Int".to_owned(), DBType::Int);
-    type_cache.insert("Unsigned".to_owned(), DBType::UInt);
-    type_cache.insert("Float".to_owned(), DBType::Double);
-    type_cache.insert("String".to_owned(), DBType::String);
+    //first occurrence of the word in the definition
+    fn word(&self, word: &str) -> Option<SourceLocation> {
+        let offset = self.find(word, self.start?)?;
+        Some(self.location(offset, word))
+    }
+
+    //first occurrence of the word after another one, e.g. the type of a field after its name
+    fn word_after(&self, word: &str, after: &str) -> Option<SourceLocation> {
+        let after_offset = self.find(after, self.start?)?;
+        let offset = self.find(word, after_offset + after.len())?;
+        Some(self.location(offset, word))
+    }
 
-    for definition in parsed.iter() {
-        let name = definition.name.clone();
+    //second occurrence of the word in the definition, used for duplicates
+    fn repeated_word(&self, word: &str) -> Option<SourceLocation> {
+        self.word_after(word, word)
+    }
+}
 
-        if type_cache.contains_key(&name) {
-            return Err(ParsingError::DuplicateTypeName(name));
+//type names used by fields, dependencies and enum variants of the definition
+fn referenced_names(definition: &Definition<TypeDeclaration>) -> Vec<String> {
+    let mut fields = definition.dependencies.iter().collect::<Vec<_>>();
+    match &definition.body {
+        TypeDefinition::Message(constructor_body) => fields.extend(constructor_body.iter()),
+        TypeDefinition::Enum(enum_branches) => {
+            for enum_branch in enum_branches.iter() {
+                for constructor in enum_branch.constructors.iter() {
+                    fields.extend(constructor.data.iter());
+                }
+            }
         }
+    }
 
-        if let TypeDefinition::Message(constructor_body) = &definition.body {
-            let mut message_type = MessageType {
-                name: name.clone(),
-                columns: vec![],
-            };
+    let mut names = Vec::<String>::new();
+    for field in fields {
+        match &field.data.node {
+            ExpressionNode::FunCall { fun, args: _ } if !names.contains(fun) => {
+                names.push(fun.clone())
+            }
+            _ => {}
+        }
+    }
+    names
+}
 
-            let mut column_indices = HashMap::<String, usize>::new();
+//Parses several files at once. Types may refer to types from other files, to each other in any
+//order and to already known types, e.g. the ones registered in the catalog. Returned types are
+//ordered so that every type comes after the types it uses
+pub fn parse_sources(
+    sources: &[SourceFile],
+    known: &HashMap<String, DBType>,
+) -> Result<Vec<FetchedType>, ParsingError> {
+    let primitives = primitive_types();
 
-            for (i, field) in definition
-                .dependencies
-                .iter()
-                .chain(constructor_body.iter())
-                .enumerate()
+    //(index of the source, definition)
+    let mut definitions = Vec::<(usize, Definition<TypeDeclaration>)>::new();
+    for (index, source) in sources.iter().enumerate() {
+        let parsed = parse(&source.text).map_err(|errors| syntax_error(source, errors))?;
+        definitions.extend(parsed.into_iter().map(|definition| (index, definition)));
+    }
+
+    let definition_location = |index: usize| {
+        let (source, definition) = &definitions[index];
+        Locator::new(&sources[*source], &definition.name).definition()
+    };
+
+    let mut indices = HashMap::<String, usize>::new();
+    for (index, (_, definition)) in definitions.iter().enumerate() {
+        if primitives.contains_key(&definition.name)
+            || indices.insert(definition.name.clone(), index).is_some()
+        {
+            //the second definition may be in the same file as the first one
+            let source = &sources[definitions[index].0];
+            let mut offset = definition_offset(&source.text, &definition.name, 0);
+            if let Some(previous) = indices
+                .get(&definition.name)
+                .filter(|previous| definitions[**previous].0 == definitions[index].0)
             {
-                let field_name = field.name.clone();
+                offset = definition_offset(&source.text, &definitions[*previous].1.name, 0)
+                    .and_then(|previous_offset| {
+                        definition_offset(
+                            &source.text,
+                            &definition.name,
+                            previous_offset + definition.name.len(),
+                        )
+                    });
+            }
+            return Err(ParsingError::DuplicateTypeName(
+                definition.name.clone(),
+                offset.map(|offset| {
+                    SourceLocation::from_span(
+                        &source.path,
+                        &source.text,
+                        offset,
+                        definition.name.chars().count(),
+                    )
+                }),
+            ));
+        }
+    }
 
-                if column_indices.contains_key(&field_name) {
-                    return Err(ParsingError::DuplicateField(field_name));
+    let mut dependencies = Vec::<Vec<usize>>::new();
+    for (source, definition) in definitions.iter() {
+        let mut used = vec![];
+        for name in referenced_names(definition) {
+            match indices.get(&name) {
+                Some(dependency) => used.push(*dependency),
+                None if primitives.contains_key(&name) || known.contains_key(&name) => {}
+                None => {
+                    let locator = Locator::new(&sources[*source], &definition.name);
+                    let location = locator.word(&name);
+                    return Err(ParsingError::UnresolvedType(name, location));
                 }
+            }
+        }
+        dependencies.push(used);
+    }
 
-                let node = &field.data.node;
+    let order = dependency_order(&dependencies).map_err(|cycle| {
+        ParsingError::CyclicTypes(
+            cycle
+                .into_iter()
+                .map(|index| {
+                    (
+                        definitions[index].1.name.clone(),
+                        definition_location(index),
+                    )
+                })
+                .collect(),
+        )
+    })?;
 
-                if let ExpressionNode::FunCall { fun, args } = node {
-                    if let Some(db_type) = type_cache.get(fun) {
-                        let mut dep_ind = Vec::<usize>::new();
+    //fetched types shadow known types with the same names
+    let mut type_cache = known.clone();
+    type_cache.extend(primitives);
 
-                        for arg in args.iter() {
-                            if let ExpressionNode::Variable { name } = &arg.node {
-                                if let Some(index) = column_indices.get(name) {
-                                    dep_ind.push(*index);
-                                } else {
-                                    return Err(ParsingError::FieldNotFound(name.clone()));
-                                }
-                            } else {
-                                return Err(ParsingError::InvalidArgument);
-                            }
-                        }
+    order
+        .into_iter()
+        .map(|index| {
+            let (source, definition) = &definitions[index];
+            let locator = Locator::new(&sources[*source], &definition.name);
+            convert_definition(definition, &locator, &mut type_cache)
+        })
+        .collect()
+}
 
-                        message_type.columns.push(Column {
-                            column_name: field_name.clone(),
-                            column_type: db_type.clone(),
-                            dependencies: dep_ind,
-                        });
-                    } else {
-                        return Err(ParsingError::UnknownType(fun.clone()));
+//Definitions in an order where each one comes after its dependencies, preferring the original
+//order. Returns the indices of a cycle if there is one
+fn dependency_order(dependencies: &[Vec<usize>]) -> Result<Vec<usize>, Vec<usize>> {
+    let mut done = vec![false; dependencies.len()];
+    let mut order = Vec::<usize>::with_capacity(dependencies.len());
+
+    while order.len() < dependencies.len() {
+        let next = (0..dependencies.len()).find(|index| {
+            !done[*index]
+                && dependencies[*index]
+                    .iter()
+                    .all(|dependency| done[*dependency])
+        });
+
+        match next {
+            Some(index) => {
+                done[index] = true;
+                order.push(index);
+            }
+            None => {
+                //every remaining definition waits for another remaining one, so following
+                //them leads to a cycle
+                let mut path = vec![(0..dependencies.len()).find(|index| !done[*index]).unwrap()];
+                loop {
+                    let current = *path.last().unwrap();
+                    let dependency = *dependencies[current]
+                        .iter()
+                        .find(|dependency| !done[**dependency])
+                        .unwrap();
+                    if let Some(start) = path.iter().position(|index| *index == dependency) {
+                        return Err(path.split_off(start));
                     }
-                } else {
-                    return Err(ParsingError::CantDeduceFieldType(field_name));
+                    path.push(dependency);
                 }
-
-                column_indices.insert(field_name.clone(), i);
             }
+        }
+    }
 
-            type_cache.insert(name, DBType::MessageType(message_type.clone()));
+    Ok(order)
+}
 
-            ans.push(FetchedType::MessageType(message_type));
-        } else if let TypeDefinition::Enum(enum_branches) = &definition.body {
-            let mut enum_type = EnumType {
-                name: name.clone(),
-                variants: vec![],
-            };
+//Converts a single definition, the types it uses must already be in the cache
+fn convert_definition(
+    definition: &Definition<TypeDeclaration>,
+    locator: &Locator,
+    type_cache: &mut HashMap<String, DBType>,
+) -> Result<FetchedType, ParsingError> {
+    let name = definition.name.clone();
 
-            let mut constructor_set = HashSet::<String>::new();
+    if let TypeDefinition::Message(constructor_body) = &definition.body {
+        let mut message_type = MessageType {
+            name: name.clone(),
+            columns: vec![],
+        };
 
-            for enum_branch in enum_branches.iter() {
-                for constructor in enum_branch.constructors.iter() {
-                    let constructor_name = constructor.name.clone();
+        let mut column_indices = HashMap::<String, usize>::new();
 
-                    if constructor_set.contains(&constructor_name) {
-                        return Err(ParsingError::DuplicateVariantName(constructor_name));
-                    }
-                    constructor_set.insert(constructor_name.clone());
+        for (i, field) in definition
+            .dependencies
+            .iter()
+            .chain(constructor_body.iter())
+            .enumerate()
+        {
+            let field_name = field.name.clone();
+
+            if column_indices.contains_key(&field_name) {
+                let location = locator.repeated_word(&field_name);
+                return Err(ParsingError::DuplicateField(field_name, location));
+            }
 
-                    let mut fields = Vec::<(String, DBType)>::new();
+            let node = &field.data.node;
 
-                    for definition in constructor.data.iter() {
-                        let field_name = definition.name.clone();
+            if let ExpressionNode::FunCall { fun, args } = node {
+                if let Some(db_type) = type_cache.get(fun) {
+                    let mut dep_ind = Vec::<usize>::new();
 
-                        if let ExpressionNode::FunCall { fun, args: _ } = &definition.data.node {
-                            if let Some(db_type) = type_cache.get(fun) {
-                                fields.push((field_name, db_type.clone()));
+                    for arg in args.iter() {
+                        if let ExpressionNode::Variable { name } = &arg.node {
+                            if let Some(index) = column_indices.get(name) {
+                                dep_ind.push(*index);
                             } else {
-                                return Err(ParsingError::UnknownType(fun.clone()));
+                                return Err(ParsingError::FieldNotFound(
+                                    name.clone(),
+                                    locator.word_after(name, fun),
+                                ));
                             }
                         } else {
-                            return Err(ParsingError::CantDeduceFieldType(field_name));
+                            return Err(ParsingError::InvalidArgument(
+                                locator.word_after(fun, &field_name),
+                            ));
                         }
                     }
 
-                    enum_type.variants.push(EnumVariantType {
-                        name: constructor_name,
-                        content: fields,
+                    message_type.columns.push(Column {
+                        column_name: field_name.clone(),
+                        column_type: db_type.clone(),
+                        dependencies: dep_ind,
                     });
+                } else {
+                    let location = locator.word_after(fun, &field_name);
+                    return Err(ParsingError::UnknownType(fun.clone(), location));
                 }
+            } else {
+                let location = locator.word(&field_name);
+                return Err(ParsingError::CantDeduceFieldType(field_name, location));
             }
 
-            type_cache.insert(name, DBType::EnumType(enum_type.clone()));
+            column_indices.insert(field_name.clone(), i);
+        }
+
+        type_cache.insert(name, DBType::MessageType(message_type.clone()));
+
+        Ok(FetchedType::MessageType(message_type))
+    } else if let TypeDefinition::Enum(enum_branches) = &definition.body {
+        let mut enum_type = EnumType {
+            name: name.clone(),
+            variants: vec![],
+        };
+
+        let mut constructor_set = HashSet::<String>::new();
+
+        for enum_branch in enum_branches.iter() {
+            for constructor in enum_branch.constructors.iter() {
+                let constructor_name = constructor.name.clone();
+
+                if constructor_set.contains(&constructor_name) {
+                    let location = locator.repeated_word(&constructor_name);
+                    return Err(ParsingError::DuplicateVariantName(
+                        constructor_name,
+                        location,
+                    ));
+                }
+                constructor_set.insert(constructor_name.clone());
+
+                let mut fields = Vec::<(String, DBType)>::new();
 
-            ans.push(FetchedType::EnumType(enum_type));
+                for definition in constructor.data.iter() {
+                    let field_name = definition.name.clone();
+
+                    if let ExpressionNode::FunCall { fun, args: _ } = &definition.data.node {
+                        if let Some(db_type) = type_cache.get(fun) {
+                            fields.push((field_name, db_type.clone()));
+                        } else {
+                            let location = locator.word_after(fun, &constructor_name);
+                            return Err(ParsingError::UnknownType(fun.clone(), location));
+                        }
+                    } else {
+                        let location = locator.word_after(&field_name, &constructor_name);
+                        return Err(ParsingError::CantDeduceFieldType(field_name, location));
+                    }
+                }
+
+                enum_type.variants.push(EnumVariantType {
+                    name: constructor_name,
+                    content: fields,
+                });
+            }
         }
-    }
 
-    Ok(ans)
+        type_cache.insert(name, DBType::EnumType(enum_type.clone()));
+
+        Ok(FetchedType::EnumType(enum_type))
+    } else {
+        unreachable!()
+    }
 }
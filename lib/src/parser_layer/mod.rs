@@ -2,6 +2,8 @@ pub mod ast;
 pub mod ast_helpers;
 pub mod error;
 pub mod fetch_types;
+pub mod parse_query;
+pub mod suggestions;
 
 use lalrpop_util::lalrpop_mod;
 lalrpop_mod!(pub query, "/parser_layer/query.rs");
@@ -21,24 +23,85 @@ mod tests {
         "#;
 
         match parser.parse(select) {
-            Ok(ast::Query::Select {
+            Ok(ast::Query::Select(ast::SelectQuery {
                 table: _,
                 fields: _,
                 condition: _,
-            }) => {}
+                ..
+            })) => {}
             _ => panic!("Cant parse select query"),
         }
 
+        let operators = r#"
+            SELECT name ++ surname AS full_name, upper(substr(name, 1, 3)) AS short, now() AS t
+            FROM users
+            WHERE age % 2 == 0 && age >= 18 && year <= 2000
+                && age BETWEEN 20 AND 30 && name IN ("John", "Jane")
+                && CAST(age AS Float) / 2.0 > 10.5
+                && name LIKE "Jo%" && surname ILIKE "d_e" && name ~ "^J.*n$";
+        "#;
+
+        match parser.parse(operators) {
+            Ok(ast::Query::Select(ast::SelectQuery {
+                table: _,
+                fields: _,
+                condition: Some(_),
+                ..
+            })) => {}
+            _ => panic!("Cant parse select query with operators"),
+        }
+
+        let match_arms = r#"
+            SELECT MATCH field {
+                ComplexEnum::First { a: x, b } if x > b => x - b,
+                ComplexEnum::Second {} => if(true, 1, 2),
+                _ => 0,
+            } AS number FROM enum_table;
+        "#;
+
+        match parser.parse(match_arms) {
+            Ok(ast::Query::Select(ast::SelectQuery {
+                table: _,
+                fields: _,
+                condition: None,
+                ..
+            })) => {}
+            _ => panic!("Cant parse select query with match arms"),
+        }
+
+        let variant_test = r#"
+            SELECT name FROM users WHERE status IS Status::Admin && role IS NOT Role::Guest;
+        "#;
+
+        match parser.parse(variant_test) {
+            Ok(ast::Query::Select(ast::SelectQuery {
+                table: _,
+                fields: _,
+                condition: Some(_),
+                ..
+            })) => {}
+            _ => panic!("Cant parse select query with variant test"),
+        }
+
         let fetch = r#"
             FETCH TYPES "something.dbuf";
         "#;
 
         match parser.parse(fetch) {
-            Ok(ast::Query::FetchTypes(_)) => {}
+            Ok(ast::Query::FetchTypes(_, ast::FetchMode::Add)) => {}
             Err(e) => println!("{:?}", e),
             _ => panic!("Cant parse fetch query"),
         }
 
+        let fetch_check = r#"
+            FETCH TYPES "something.dbuf" CHECK;
+        "#;
+
+        match parser.parse(fetch_check) {
+            Ok(ast::Query::FetchTypes(_, ast::FetchMode::Check)) => {}
+            _ => panic!("Cant parse fetch check query"),
+        }
+
         let create = r#"
             CREATE TABLE user_table User;
         "#;
@@ -57,6 +120,14 @@ mod tests {
             _ => panic!("Cant parse drop query"),
         }
 
+        match parser.parse("DROP TYPE User CASCADE;") {
+            Ok(ast::Query::DropType {
+                type_name: _,
+                cascade: true,
+            }) => {}
+            _ => panic!("Cant parse drop type query"),
+        }
+
         let insert = r#"
             INSERT INTO user_table VALUES [User {"John", "Doe"}],  [User {"Jane", "Doe"}];
         "#;
@@ -68,9 +139,371 @@ mod tests {
             }) => {}
             _ => panic!("Cant parse insert query"),
         }
+
+        let constructors = r#"
+            SELECT [Summary { name ++ "!", age + 1, [Size::Big { age }] }] AS summary FROM users;
+        "#;
+
+        match parser.parse(constructors) {
+            Ok(ast::Query::Select(ast::SelectQuery {
+                table: _,
+                fields,
+                condition: None,
+                ..
+            })) => assert!(matches!(
+                fields[0].0,
+                ast::Expression::MessageConstructor { .. }
+            )),
+            _ => panic!("Cant parse select query with constructors"),
+        }
+
+        let named_insert = r#"
+            INSERT INTO user_table VALUES [User { surname: "Doe", name: "John" }], [User {}];
+        "#;
+
+        match parser.parse(named_insert) {
+            Ok(ast::Query::InsertMessages { table: _, messages }) => {
+                assert_eq!(
+                    messages[0].field_names,
+                    Some(vec!["surname".to_owned(), "name".to_owned()])
+                );
+                assert!(messages[1].field_names.is_none());
+            }
+            _ => panic!("Cant parse insert query with named fields"),
+        }
+
+        let insert_select = r#"
+            INSERT INTO adults SELECT name, age FROM users WHERE age >= 18;
+        "#;
+
+        match parser.parse(insert_select) {
+            Ok(ast::Query::InsertSelect { table, select }) => {
+                assert_eq!(table, "adults");
+                assert_eq!(select.table, "users");
+                assert!(select.condition.is_some());
+            }
+            _ => panic!("Cant parse insert select query"),
+        }
+
+        let create_as = r#"
+            CREATE TABLE adults Adult AS SELECT name, age FROM users WHERE age >= 18;
+        "#;
+
+        match parser.parse(create_as) {
+            Ok(ast::Query::CreateTableAs {
+                table: _,
+                type_name: Some(_),
+                select: _,
+            }) => {}
+            _ => panic!("Cant parse create table as select query"),
+        }
+
+        let subqueries = r#"
+            SELECT name, (SELECT age FROM users WHERE id == owner) AS owner_age FROM pets
+            WHERE owner IN (SELECT id FROM users) && EXISTS (SELECT id FROM users WHERE age > 18);
+        "#;
+
+        match parser.parse(subqueries) {
+            Ok(ast::Query::Select(ast::SelectQuery {
+                table: _,
+                fields,
+                condition:
+                    Some(ast::Expression::BinaryOp {
+                        op: ast::BinaryOperator::And,
+                        left,
+                        right,
+                    }),
+                ..
+            })) => {
+                assert!(matches!(fields[1].0, ast::Expression::ScalarSubquery(_)));
+                assert!(matches!(*left, ast::Expression::InSubquery { .. }));
+                assert!(matches!(*right, ast::Expression::Exists(_)));
+            }
+            _ => panic!("Cant parse query with subqueries"),
+        }
+
+        let ctes = r#"
+            WITH RECURSIVE
+                adults AS (SELECT id, name FROM users WHERE age >= 18),
+                tree AS (
+                    SELECT id FROM nodes WHERE parent == 0
+                    UNION ALL
+                    SELECT id FROM nodes WHERE parent IN (SELECT id FROM tree)
+                )
+            SELECT name FROM adults WHERE id IN (SELECT id FROM tree);
+        "#;
+
+        match parser.parse(ctes) {
+            Ok(ast::Query::Select(select)) => {
+                assert_eq!(select.table, "adults");
+                assert!(select.ctes[1].recursive);
+                assert!(matches!(
+                    select.ctes[1].query.compound[..],
+                    [(ast::SetOperator::UnionAll, _)]
+                ));
+            }
+            _ => panic!("Cant parse query with common table expressions"),
+        }
+
+        let set_operations = r#"
+            SELECT DISTINCT name FROM users
+            UNION SELECT name FROM admins
+            EXCEPT SELECT name FROM banned WHERE active;
+        "#;
+
+        match parser.parse(set_operations) {
+            Ok(ast::Query::Select(select)) => {
+                assert!(select.distinct);
+                assert!(matches!(
+                    select.compound[..],
+                    [(ast::SetOperator::Union, _), (ast::SetOperator::Except, _)]
+                ));
+            }
+            _ => panic!("Cant parse query with set operations"),
+        }
+
+        let alter = r#"
+            ALTER TABLE users SET TYPE User_v2 USING [User_v2 { name, age, age >= 18 }];
+        "#;
+
+        match parser.parse(alter) {
+            Ok(ast::Query::AlterTableType {
+                table: _,
+                type_name,
+                using: Some(_),
+            }) => assert_eq!(type_name, "User_v2"),
+            _ => panic!("Cant parse alter table query"),
+        }
+
+        let catalog = [
+            "SHOW TABLES;",
+            "DESCRIBE sys.columns;",
+            "DESCRIBE TYPE User;",
+        ];
+
+        match catalog.map(|query| parser.parse(query)) {
+            [
+                Ok(ast::Query::ShowTables),
+                Ok(ast::Query::Describe(table)),
+                Ok(ast::Query::DescribeType(_)),
+            ] => assert_eq!(table, "sys.columns"),
+            _ => panic!("Cant parse catalog queries"),
+        }
+
+        let views = [
+            "CREATE MATERIALIZED VIEW adults AS SELECT name FROM users WHERE age >= 18;",
+            "REFRESH MATERIALIZED VIEW adults;",
+            "DROP VIEW adults;",
+        ];
+
+        match views.map(|query| parser.parse(query)) {
+            [
+                Ok(ast::Query::CreateView {
+                    name: _,
+                    select: _,
+                    materialized: true,
+                }),
+                Ok(ast::Query::RefreshMaterializedView(_)),
+                Ok(ast::Query::DropView(_)),
+            ] => {}
+            _ => panic!("Cant parse view queries"),
+        }
+
+        //inserted messages must be literals
+        let insert_expression = r#"
+            INSERT INTO user_table VALUES [User {"John", surname}];
+        "#;
+        assert!(parser.parse(insert_expression).is_err());
     }
 
+    #[test]
+    fn lexer_test() {
+        let parser = query::QueryParser::new();
+        let select = |query: &str| match parser.parse(query) {
+            Ok(ast::Query::Select(select)) => select,
+            result => panic!("Cant parse select query: {:?}", result),
+        };
+        let names = |select: &ast::SelectQuery| {
+            select
+                .fields
+                .iter()
+                .map(|(_, name)| name.clone())
+                .collect::<Vec<String>>()
+        };
+
+        //keywords in any case, longer words are identifiers
+        let lowercase =
+            select("select Selection, name As n From users wHeRe age between 1 and 5 && TRUE;");
+        assert_eq!(lowercase.table, "users");
+        assert_eq!(names(&lowercase), ["Selection", "n"]);
+
+        let comments =
+            select("SELECT name -- the name of the user\nFROM /* all\n the users */ users --;\n;");
+        assert_eq!(names(&comments), ["name"]);
+
+        let escapes = select(r#"SELECT name FROM users WHERE name == "say \"hi\"\n\\ \u{1F600}";"#);
+        match escapes.condition {
+            Some(ast::Expression::BinaryOp { right, .. }) => match *right {
+                ast::Expression::Literal(ast::Value::String(value)) => {
+                    assert_eq!(value, "say \"hi\"\n\\ \u{1F600}")
+                }
+                _ => panic!("String literal is not parsed"),
+            },
+            _ => panic!("Condition is not parsed"),
+        }
+        assert!(
+            parser
+                .parse(r#"SELECT name FROM users WHERE name == "\q";"#)
+                .is_err()
+        );
+        assert!(
+            parser
+                .parse(r#"SELECT name FROM users WHERE name == "\u{110000}";"#)
+                .is_err()
+        );
+
+        //quoted identifiers may be keywords, keywords after a dot are names
+        let quoted = select("SELECT `type`, `odd``name`, row.from AS f FROM `select`;");
+        assert_eq!(quoted.table, "select");
+        assert_eq!(names(&quoted), ["type", "odd`name", "f"]);
+        assert_eq!(select("SELECT name FROM sys.tables;").table, "sys.tables");
+    }
+
+    #[test]
+    fn read_only_queries_test() {
+        let parser = query::QueryParser::new();
+        let read_only = |query: &str| parser.parse(query).unwrap().is_read_only();
+
+        assert!(read_only("SELECT name FROM users;"));
+        assert!(read_only("DESCRIBE TYPE User;"));
+        assert!(read_only("FETCH TYPES \"types\" CHECK;"));
+        assert!(!read_only("FETCH TYPES \"types\";"));
+        assert!(!read_only("INSERT INTO users SELECT name FROM users;"));
+        assert!(!read_only("REFRESH MATERIALIZED VIEW adults;"));
+    }
+
+    #[test]
+    fn script_test() {
+        use super::parse_query::{parse_script, parse_statement, split_statements};
+
+        let script = "SHOW TABLES; -- first;\nSELECT \"a;\\\"b\" AS `c;` FROM t /* ; */;\n\nDESCRIBE t\n-- done;\n";
+        let statements = split_statements(script);
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statement| script[statement.clone()].trim())
+                .collect::<Vec<&str>>(),
+            [
+                "SHOW TABLES;",
+                "-- first;\nSELECT \"a;\\\"b\" AS `c;` FROM t /* ; */;",
+                "DESCRIBE t\n-- done;"
+            ]
+        );
+        assert!(split_statements("SHOW TABLES; -- nothing else\n ").len() == 1);
+
+        let parser = query::ScriptParser::new();
+        assert_eq!(
+            parse_script(&parser, "seed.sql", "SHOW TABLES;\nshow types; DESCRIBE t;")
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(parse_script(&parser, "seed.sql", "").unwrap().is_empty());
+
+        //errors of a statement point into the whole script
+        let script = "SHOW TABLES;\nSHOW TABLES;\nDESCRIBE;\n";
+        let error = parse_script(&parser, "seed.sql", script).unwrap_err();
+        assert_eq!(error.location().unwrap().to_string(), "seed.sql:3:9");
+        let statement = split_statements(script)[2].clone();
+        let error =
+            parse_statement(&query::QueryParser::new(), "seed.sql", script, statement).unwrap_err();
+        assert_eq!(error.location().unwrap().to_string(), "seed.sql:3:9");
+    }
+
+    #[test]
+    fn query_errors_test() {
+        use super::error::QueryError;
+        use super::parse_query::parse_query;
+        use super::suggestions::{closest, edit_distance};
+
+        assert_eq!(edit_distance("FORM", "FROM"), 1);
+        assert_eq!(edit_distance("select", "SELECT"), 0);
+        assert_eq!(
+            closest("usres", ["users", "roles"]),
+            Some("users".to_owned())
+        );
+        assert_eq!(closest("accounts", ["users", "roles"]), None);
+
+        let parser = query::QueryParser::new();
+        let error = parse_query(
+            &parser,
+            "<query>",
+            "INERT INTO user_table\nVALUES [User {\"John\"}];",
+        )
+        .unwrap_err();
+        match &error {
+            QueryError::UnexpectedToken {
+                location,
+                expected,
+                suggestion,
+            } => {
+                assert_eq!(location.text(), "INERT");
+                assert_eq!(location.to_string(), "<query>:1:1");
+                assert!(expected.contains(&"`INSERT`".to_owned()));
+                assert_eq!(suggestion.as_deref(), Some("INSERT"));
+            }
+            _ => panic!("Misspelled keyword is not reported"),
+        }
+        assert!(
+            error
+                .render()
+                .ends_with("1 | INERT INTO user_table\n  | ^^^^^\nhelp: did you mean `INSERT`?")
+        );
+
+        let error = parse_query(&parser, "<query>", "SELECT name\nFORM users;").unwrap_err();
+        assert!(matches!(
+            &error,
+            QueryError::UnexpectedToken { location, suggestion, .. }
+                if location.to_string() == "<query>:2:1" && suggestion.as_deref() == Some("FROM")
+        ));
+
+        let error = parse_query(&parser, "<query>", "SELECT name FROM users\n").unwrap_err();
+        match &error {
+            QueryError::UnexpectedEnd { location, expected } => {
+                assert_eq!(location.to_string(), "<query>:1:23");
+                assert!(expected.contains(&"`;`".to_owned()));
+            }
+            _ => panic!("Unexpected end is not reported"),
+        }
+
+        //invalid escapes point at their string literal
+        let error = parse_query(
+            &parser,
+            "<query>",
+            "SELECT name FROM users\nWHERE name == \"\\u{zz}\";",
+        )
+        .unwrap_err();
+        match &error {
+            QueryError::InvalidQuery(_, location) => {
+                assert_eq!(location.to_string(), "<query>:2:15");
+                assert_eq!(location.text(), "\"\\u{zz}\"");
+            }
+            _ => panic!("Invalid escape is not reported"),
+        }
+
+        let error = parse_query(&parser, "<query>", "SELECT name FROM 5;").unwrap_err();
+        assert!(matches!(
+            &error,
+            QueryError::UnexpectedToken { expected, suggestion: None, .. }
+                if expected == &["identifier".to_owned()]
+        ));
+    }
+
+    use super::super::executor_layer::schema::DBType;
+    use super::error::ParsingError;
+    use super::fetch_types::{FetchedType, SourceFile};
     use dbuf_core::parser::parse;
+    use std::collections::HashMap;
 
     #[test]
     fn fetch_types_test() {
@@ -80,4 +513,135 @@ mod tests {
         //println!("{:#?}", parsed);
         //assert_eq!(2 + 2, 5);
     }
+
+    #[test]
+    fn multiple_files_test() {
+        let name = |fetched: &FetchedType| match fetched {
+            FetchedType::MessageType(message_type) => message_type.name.clone(),
+            FetchedType::EnumType(enum_type) => enum_type.name.clone(),
+        };
+
+        //account.dbuf uses types from the files after it
+        let sources = fetch_types::read_sources("sample_dbuf/multi").unwrap();
+        assert_eq!(sources.len(), 3);
+        let parsed = fetch_types::parse_sources(&sources, &HashMap::new()).unwrap();
+        assert_eq!(
+            parsed.iter().map(name).collect::<Vec<String>>(),
+            ["Role", "User", "Account"]
+        );
+        assert_eq!(
+            fetch_types::read_sources("sample_dbuf/multi/*r*.dbuf")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(matches!(
+            fetch_types::read_sources("sample_dbuf/multi/*.proto"),
+            Err(ParsingError::NoFilesFound(_))
+        ));
+
+        let source = |path: &str, text: &str| SourceFile {
+            path: path.to_owned(),
+            text: text.to_owned(),
+        };
+
+        //names are resolved against known types
+        let account = [source(
+            "account.dbuf",
+            "message Account {\n  owner User;\n}\n",
+        )];
+        assert!(matches!(
+            fetch_types::parse_sources(&account, &HashMap::new()),
+            Err(ParsingError::UnresolvedType(name, Some(location)))
+                if name == "User" && location.to_string() == "account.dbuf:2:9"
+        ));
+        let known = HashMap::from([("User".to_owned(), DBType::String)]);
+        assert_eq!(
+            fetch_types::parse_sources(&account, &known).unwrap().len(),
+            1
+        );
+
+        let cycle = [
+            source("a.dbuf", "message A {\n  b B;\n}\n"),
+            source(
+                "b.dbuf",
+                "message Root {\n  x Int;\n}\n\nmessage B {\n  a A;\n}\n",
+            ),
+        ];
+        match fetch_types::parse_sources(&cycle, &HashMap::new()) {
+            Err(ParsingError::CyclicTypes(types)) => assert_eq!(
+                types
+                    .iter()
+                    .map(|(name, location)| format!("{} {}", name, location.as_ref().unwrap()))
+                    .collect::<Vec<String>>(),
+                ["A a.dbuf:1:9", "B b.dbuf:5:9"]
+            ),
+            _ => panic!("Cycle is not reported"),
+        }
+    }
+
+    #[test]
+    fn diagnostics_test() {
+        let parse_file = |text: &str| {
+            let source = SourceFile {
+                path: "user.dbuf".to_owned(),
+                text: text.to_owned(),
+            };
+            fetch_types::parse_sources(&[source], &HashMap::new()).unwrap_err()
+        };
+
+        let error = parse_file(
+            "message Role {\n  level Int;\n}\n\nmessage User {\n  name String;\n  role Rol;\n}\n",
+        );
+        assert!(matches!(&error, ParsingError::UnresolvedType(name, _) if name == "Rol"));
+        assert_eq!(
+            error.render(),
+            "Unresolved type: Rol\n --> user.dbuf:7:8\n  |\n7 |   role Rol;\n  |        ^^^"
+        );
+
+        let error = parse_file("message User {\n  name String;\n  age Int;\n  name String;\n}\n");
+        match &error {
+            ParsingError::DuplicateField(name, Some(location)) => {
+                assert_eq!(name, "name");
+                assert_eq!(location.to_string(), "user.dbuf:4:3");
+                assert_eq!(location.length, 4);
+            }
+            _ => panic!("Duplicate field is not reported"),
+        }
+        assert!(error.render().ends_with("4 |   name String;\n  |   ^^^^"));
+
+        let error = parse_file("enum Color {\n  Red {}\n  Green {}\n  Red {}\n}\n");
+        assert!(matches!(
+            &error,
+            ParsingError::DuplicateVariantName(name, Some(location))
+                if name == "Red" && location.to_string() == "user.dbuf:4:3"
+        ));
+
+        let error =
+            parse_file("message User {\n  name String;\n}\n\nmessage User {\n  age Int;\n}\n");
+        assert!(matches!(
+            &error,
+            ParsingError::DuplicateTypeName(name, Some(location))
+                if name == "User" && location.to_string() == "user.dbuf:5:9"
+        ));
+
+        //syntax errors keep the message of the dbuf parser and point at the token, errors at the
+        //end of the file point right after the last token
+        let error = parse_file("message User {\n  name String\n");
+        assert!(matches!(
+            &error,
+            ParsingError::TypesParseError(message, location)
+                if !message.is_empty() && location.to_string() == "user.dbuf:2:14"
+        ));
+        assert!(
+            error
+                .render()
+                .ends_with("2 |   name String\n  |              ^")
+        );
+
+        let error = parse_file("message User {\n  name String;\n}\n\nstruct Role {\n}\n");
+        let location = error.location().unwrap();
+        assert_eq!(location.to_string(), "user.dbuf:5:1");
+        assert_eq!(location.length, 6);
+    }
 }
@@ -0,0 +1,195 @@
+use lalrpop_util::ParseError;
+
+use std::fmt::Display;
+use std::ops::Range;
+
+use super::ast::Query;
+use super::ast_helpers::ActionError;
+use super::error::*;
+use super::query::{QueryParser, ScriptParser};
+use super::suggestions::closest;
+
+//Expected terminals as lalrpop reports them, keywords and punctuation are quoted and the
+//other tokens are regular expressions
+fn describe_expected(terminal: &str) -> String {
+    match terminal
+        .strip_prefix("r#\"")
+        .and_then(|regex| regex.strip_suffix("\"#"))
+    {
+        Some(regex) if regex.starts_with("\\\"") => "string".to_owned(),
+        Some(regex) if regex.starts_with('`') || regex.contains("a-zA-Z") => {
+            "identifier".to_owned()
+        }
+        Some(regex) if regex.contains('.') => "number".to_owned(),
+        Some(regex) if regex.contains("0-9") => "integer".to_owned(),
+        Some(regex) => regex.to_owned(),
+        None => format!("`{}`", terminal.trim_matches('"')),
+    }
+}
+
+//both plain and quoted identifiers are described as identifier
+fn describe_all_expected(expected: &[String]) -> Vec<String> {
+    let mut described = Vec::<String>::new();
+    for terminal in expected.iter() {
+        let description = describe_expected(terminal);
+        if !described.contains(&description) {
+            described.push(description);
+        }
+    }
+    described
+}
+
+fn is_keyword(terminal: &str) -> bool {
+    let name = terminal.trim_matches('"');
+    terminal.starts_with('"') && !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase())
+}
+
+//Parses a query, reporting errors with their position in the source. The source is a name like
+//a file path used in the error location
+pub fn parse_query(parser: &QueryParser, source: &str, text: &str) -> Result<Query, QueryError> {
+    parse_statement(parser, source, text, 0..text.len())
+}
+
+//Parses a single statement of a script, errors point into the whole script
+pub fn parse_statement(
+    parser: &QueryParser,
+    source: &str,
+    text: &str,
+    range: Range<usize>,
+) -> Result<Query, QueryError> {
+    parser
+        .parse(&text[range.clone()])
+        .map_err(|error| convert_error(source, text, range, error))
+}
+
+//Parses every statement of a script, nothing is returned if one of them is invalid
+pub fn parse_script(
+    parser: &ScriptParser,
+    source: &str,
+    text: &str,
+) -> Result<Vec<Query>, QueryError> {
+    parser
+        .parse(text)
+        .map_err(|error| convert_error(source, text, 0..text.len(), error))
+}
+
+//Ranges of the statements of a script, each one ends with its semicolon. Semicolons in strings,
+//quoted identifiers and comments don't end statements. The last statement may be unterminated,
+//whitespace and comments after the last semicolon are skipped
+pub fn split_statements(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut statements = vec![];
+    let mut start = 0usize;
+    let mut blank = true;
+    let mut i = 0usize;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'"', _) => {
+                blank = false;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            //a doubled backtick closes the name and opens it again
+            (b'`', _) => {
+                blank = false;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'`' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            (b'-', Some(b'-')) => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            (b'/', Some(b'*')) => {
+                i = text[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+            }
+            (b';', _) => {
+                statements.push(start..i + 1);
+                start = i + 1;
+                blank = true;
+                i += 1;
+            }
+            (c, _) => {
+                blank = blank && c.is_ascii_whitespace();
+                i += 1;
+            }
+        }
+    }
+
+    if !blank {
+        statements.push(start..bytes.len());
+    }
+    statements
+}
+
+fn convert_error<T: Display>(
+    source: &str,
+    text: &str,
+    range: Range<usize>,
+    error: ParseError<usize, T, ActionError>,
+) -> QueryError {
+    //offsets of the error are relative to the start of the statement
+    let location = |start: usize, end: usize| {
+        SourceLocation::from_span(
+            source,
+            text,
+            range.start + start,
+            text[range.start + start..range.start + end].chars().count(),
+        )
+    };
+
+    match error {
+        ParseError::InvalidToken { location: offset } => {
+            QueryError::InvalidToken(location(offset, offset))
+        }
+        ParseError::UnrecognizedEof {
+            location: _,
+            expected,
+        } => {
+            //points right after the last token instead of the trailing whitespace
+            let end = text[range.clone()].trim_end().len();
+            QueryError::UnexpectedEnd {
+                location: location(end, end),
+                expected: describe_all_expected(&expected),
+            }
+        }
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => {
+            let token = token.to_string();
+            let keywords = expected
+                .iter()
+                .filter(|terminal| is_keyword(terminal))
+                .map(|terminal| terminal.trim_matches('"'))
+                .collect::<Vec<&str>>();
+            let suggestion = match token.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                true => closest(&token, keywords),
+                false => None,
+            };
+            QueryError::UnexpectedToken {
+                location: location(start, end),
+                expected: describe_all_expected(&expected),
+                suggestion,
+            }
+        }
+        ParseError::ExtraToken {
+            token: (start, _, end),
+        } => QueryError::ExtraToken(location(start, end)),
+        ParseError::User { error } => {
+            QueryError::InvalidQuery(error.message.to_owned(), location(error.start, error.end))
+        }
+    }
+}
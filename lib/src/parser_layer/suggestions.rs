@@ -0,0 +1,46 @@
+//Edit distance between two names ignoring case, where swapping two neighbouring characters
+//counts as a single edit
+pub fn edit_distance(left: &str, right: &str) -> usize {
+    let left = left.to_lowercase().chars().collect::<Vec<char>>();
+    let right = right.to_lowercase().chars().collect::<Vec<char>>();
+
+    //distances[i][j] is the distance between the first i and the first j characters
+    let mut distances = vec![vec![0usize; right.len() + 1]; left.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=left.len() {
+        for j in 1..=right.len() {
+            let substitution = match left[i - 1] == right[j - 1] {
+                true => 0,
+                false => 1,
+            };
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[left.len()][right.len()]
+}
+
+//The candidate closest to a misspelled name. Longer names may have more mistakes, candidates
+//that differ from the name only in case are the closest ones
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let allowed = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_owned())
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::super::executor_layer::schema::*;
+
+//Catalog entries that may use a type
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Referent {
+    MessageType(String),
+    EnumType(String),
+    Table(String),
+    View(String),
+}
+
+impl fmt::Display for Referent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Referent::MessageType(name) => write!(f, "message type {}", name),
+            Referent::EnumType(name) => write!(f, "enum type {}", name),
+            Referent::Table(name) => write!(f, "table {}", name),
+            Referent::View(name) => write!(f, "view {}", name),
+        }
+    }
+}
+
+//Reverse dependencies between types and the catalog entries that use them directly. Nested
+//types are referenced by name, so an entry depends on every type in its columns, variants and,
+//for tables, on its own schema. Views also depend on the tables and views their queries read
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    referents: HashMap<String, Vec<Referent>>,
+    readers: HashMap<String, Vec<Referent>>,
+}
+
+impl DependencyGraph {
+    fn add_type_references(&mut self, db_type: &DBType, referent: &Referent) {
+        let name = match db_type {
+            DBType::MessageType(message_type) => &message_type.name,
+            DBType::EnumType(enum_type) => &enum_type.name,
+            _ => return,
+        };
+
+        let referents = self.referents.entry(name.clone()).or_default();
+        if !referents.contains(referent) {
+            referents.push(referent.clone());
+        }
+    }
+
+    fn add_columns(&mut self, message_type: &MessageType, referent: &Referent) {
+        for column in message_type.columns.iter() {
+            self.add_type_references(&column.column_type, referent);
+        }
+    }
+
+    pub fn add_message_type(&mut self, message_type: &MessageType) {
+        let referent = Referent::MessageType(message_type.name.clone());
+        self.add_columns(message_type, &referent);
+    }
+
+    pub fn add_enum_type(&mut self, enum_type: &EnumType) {
+        let referent = Referent::EnumType(enum_type.name.clone());
+        for variant in enum_type.variants.iter() {
+            for (_, field_type) in variant.content.iter() {
+                self.add_type_references(field_type, &referent);
+            }
+        }
+    }
+
+    pub fn add_table(&mut self, table_name: &str, schema: &MessageType) {
+        let referent = Referent::Table(table_name.to_owned());
+        self.add_type_references(&DBType::MessageType(schema.clone()), &referent);
+        self.add_columns(schema, &referent);
+    }
+
+    pub fn add_view(&mut self, view_name: &str, message_type: &MessageType, relations: &[String]) {
+        let referent = Referent::View(view_name.to_owned());
+        self.add_columns(message_type, &referent);
+        for relation in relations.iter() {
+            let readers = self.readers.entry(relation.clone()).or_default();
+            if !readers.contains(&referent) {
+                readers.push(referent.clone());
+            }
+        }
+    }
+
+    //entries that use the type directly
+    pub fn referents(&self, type_name: &str) -> &[Referent] {
+        self.referents
+            .get(type_name)
+            .map_or(&[], |referents| referents.as_slice())
+    }
+
+    //views that read the table or view
+    fn readers(&self, relation: &str) -> &[Referent] {
+        self.readers
+            .get(relation)
+            .map_or(&[], |readers| readers.as_slice())
+    }
+
+    //entries that use the type directly, through other types or by reading tables and views
+    //that use it, in the order they were found
+    pub fn all_referents(&self, type_name: &str) -> Vec<Referent> {
+        let mut found = self.referents(type_name).to_vec();
+        let mut position = 0usize;
+
+        while position < found.len() {
+            let next = match &found[position] {
+                Referent::MessageType(name) | Referent::EnumType(name) => self.referents(name),
+                Referent::Table(name) | Referent::View(name) => self.readers(name),
+            };
+            for referent in next {
+                if !found.contains(referent) {
+                    found.push(referent.clone());
+                }
+            }
+            position += 1;
+        }
+
+        found
+    }
+}
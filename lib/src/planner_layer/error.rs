@@ -22,15 +22,36 @@ pub enum PlannerError {
     #[error("Unexisting enum type: {0}")]
     UnexistingEnumType(String),
 
+    #[error("Unexisting type: {0}")]
+    UnexistingType(String),
+
+    #[error("Type {0} is used by {1:?}")]
+    TypeInUse(String, Vec<String>),
+
     #[error("Mismatched field types for type: {0}")]
     MismatchedFieldTypes(String),
 
+    #[error("Missing field {1} of type {0}")]
+    MissingField(String, String),
+
+    #[error("Duplicate field {1} of type {0}")]
+    DuplicateField(String, String),
+
+    #[error("Unknown field {1} of type {0}")]
+    UnknownField(String, String),
+
     #[error("Enum variant not found {0} {1}")]
     EnumVariantNotFound(String, String),
 
     #[error("Ill formed match statement")]
     IllFormedMatchStatement,
 
+    #[error("Non exhaustive match, missing variants: {0}")]
+    NonExhaustiveMatch(String),
+
+    #[error("Unreachable match arm: {0}")]
+    UnreachableMatchArm(String),
+
     #[error("Duplicate message type: {0}")]
     DuplicateMessageType(String),
 
@@ -40,6 +61,51 @@ pub enum PlannerError {
     #[error("Dependency dropped by projection")]
     DependencyDropped,
 
+    #[error("Query result is incompatible with table schema: {0}")]
+    IncompatibleSchema(String),
+
+    #[error("Invalid cast to type: {0}")]
+    InvalidCast(String),
+
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+
+    #[error("Duplicate function: {0}")]
+    DuplicateFunction(String),
+
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("Wrong arguments for function: {0}")]
+    WrongFunctionArguments(String),
+
+    #[error("Invalid subquery: {0}")]
+    InvalidSubquery(String),
+
+    #[error("Types already exist with other definitions, fetch them with REPLACE: {0:?}")]
+    ChangedTypes(Vec<String>),
+
+    #[error("Unexisting view: {0}")]
+    UnexistingView(String),
+
+    #[error("Duplicate view: {0}")]
+    DuplicateView(String),
+
+    #[error("View is not materialized: {0}")]
+    NotMaterialized(String),
+
+    #[error("{0} is a view, not a table")]
+    ViewNotTable(String),
+
+    #[error("Invalid table or view name: {0}, '#' is reserved")]
+    ReservedName(String),
+
+    #[error("Duplicate common table expression: {0}")]
+    DuplicateCte(String),
+
+    #[error("Recursion limit reached in: {0}")]
+    RecursionLimit(String),
+
     #[error("Underlying executor error: {0}")]
     ExecutorError(ExecutorError),
 }
@@ -1,8 +1,9 @@
 use std::boxed::Box;
 use std::string::String;
+use std::sync::Arc;
 use std::vec::Vec;
 
-use super::super::executor_layer::{expression::Expression, schema::*};
+use super::super::executor_layer::{expression::Expression, operator::SetOperator, schema::*};
 
 use super::raw_expression::RawExpression;
 
@@ -19,6 +20,27 @@ pub enum RawPlan {
         raw_expressions: Vec<(String, RawExpression)>,
         source: Box<RawPlan>,
     },
+    Distinct {
+        source: Box<RawPlan>,
+    },
+    SetOperation {
+        op: SetOperator,
+        left: Box<RawPlan>,
+        right: Box<RawPlan>,
+    },
+    //common table expressions are visible as tables inside the source and the later ctes
+    With {
+        ctes: Vec<RawCte>,
+        source: Box<RawPlan>,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RawCte {
+    pub name: String,
+    pub plan: RawPlan,
+    //step plan that refers to the cte itself and whether duplicate rows are kept
+    pub recursion: Option<(RawPlan, bool)>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -37,6 +59,22 @@ pub enum LogicalPlan {
         source: Box<LogicalPlan>,
         message_type: MessageType,
     },
+    Distinct {
+        source: Box<LogicalPlan>,
+        message_type: MessageType,
+    },
+    //both sides return the columns of message_type
+    SetOperation {
+        op: SetOperator,
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        message_type: MessageType,
+    },
+    //rows computed during planning, like materialized ctes
+    Values {
+        rows: Arc<Vec<Message>>,
+        message_type: MessageType,
+    },
 }
 
 impl LogicalPlan {
@@ -56,6 +94,20 @@ impl LogicalPlan {
                 source: _,
                 message_type,
             } => message_type,
+            LogicalPlan::Distinct {
+                source: _,
+                message_type,
+            } => message_type,
+            LogicalPlan::SetOperation {
+                op: _,
+                left: _,
+                right: _,
+                message_type,
+            } => message_type,
+            LogicalPlan::Values {
+                rows: _,
+                message_type,
+            } => message_type,
         }
     }
 }
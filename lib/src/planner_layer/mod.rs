@@ -1,15 +1,28 @@
+pub mod dependencies;
 pub mod error;
 pub mod logical_plan;
 pub mod query_planner;
 pub mod raw_expression;
+pub mod type_diff;
 
 #[cfg(test)]
 mod tests {
     use super::super::{
-        executor_layer::{expression::*, schema::*, table_manager::TableManager},
+        executor_layer::{
+            error::ExecutorError, expression::*, operator::PlanCursor, pattern::PatternKind,
+            schema::*, table_manager::TableManager,
+        },
+        parser_layer::{ast, fetch_types::FetchedType, query::QueryParser},
         storage_layer::paged_storage::PagedStorage,
     };
-    use super::{logical_plan::*, query_planner::QueryPlanner, raw_expression::*};
+    use super::{
+        dependencies::Referent,
+        error::PlannerError,
+        logical_plan::*,
+        query_planner::{QueryPlanner, RelationKind},
+        raw_expression::*,
+        type_diff::TypeChange,
+    };
 
     pub mod utility {
         use super::*;
@@ -233,10 +246,3038 @@ mod tests {
         let mut physical_plan = query_planner.build_physical_plan(&logical_plan);
         physical_plan.open().unwrap();
 
-        let retreived_messages: Vec<Message> = physical_plan.collect();
+        let retreived_messages: Vec<Message> = physical_plan
+            .collect::<Result<Vec<Message>, ExecutorError>>()
+            .unwrap();
 
         assert_eq!(expected, retreived_messages);
 
         utility::cleanup(path);
     }
+
+    #[test]
+    fn numeric_coercion_test() {
+        let path = "temp_path11";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let message_type = MessageType {
+            name: "Numbers".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "Unsigned".to_owned(),
+                    column_type: DBType::UInt,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "Float".to_owned(),
+                    column_type: DBType::Double,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "Text".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+            ],
+        };
+
+        let messages = vec![
+            Message {
+                type_name: None,
+                fields: vec![
+                    DBValue::UInt(3u32),
+                    DBValue::Double(0.5f32),
+                    DBValue::String("12".to_owned()),
+                ],
+            },
+            Message {
+                type_name: None,
+                fields: vec![
+                    DBValue::UInt(10u32),
+                    DBValue::Double(1.5f32),
+                    DBValue::String("not a number".to_owned()),
+                ],
+            },
+        ];
+
+        query_planner
+            .table_manager
+            .create_table("Numbers".to_owned(), message_type.clone())
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages("Numbers".to_owned(), messages.into_iter())
+            .unwrap();
+
+        //Unsigned > 5 && Unsigned + Float > 0
+        let filter = RawPlan::Filter {
+            raw_expression: RawExpression::BinaryOp {
+                op: BinaryOperator::And,
+                left: Box::new(RawExpression::BinaryOp {
+                    op: BinaryOperator::GreaterThan,
+                    left: Box::new(RawExpression::ColumnRef("Unsigned".to_owned())),
+                    right: Box::new(RawExpression::Literal(DBValue::Int(5i32))),
+                }),
+                right: Box::new(RawExpression::BinaryOp {
+                    op: BinaryOperator::GreaterThan,
+                    left: Box::new(RawExpression::BinaryOp {
+                        op: BinaryOperator::Add,
+                        left: Box::new(RawExpression::ColumnRef("Unsigned".to_owned())),
+                        right: Box::new(RawExpression::ColumnRef("Float".to_owned())),
+                    }),
+                    right: Box::new(RawExpression::Literal(DBValue::Int(0i32))),
+                }),
+            },
+            source: Box::new(RawPlan::Scan {
+                table_name: "Numbers".to_owned(),
+            }),
+        };
+
+        let projection = RawPlan::Projection {
+            raw_expressions: vec![(
+                "Sum".to_owned(),
+                RawExpression::BinaryOp {
+                    op: BinaryOperator::Add,
+                    left: Box::new(RawExpression::ColumnRef("Unsigned".to_owned())),
+                    right: Box::new(RawExpression::ColumnRef("Float".to_owned())),
+                },
+            )],
+            source: Box::new(filter),
+        };
+
+        let logical_plan = query_planner.build_logical_plan(&projection).unwrap();
+        assert_eq!(
+            logical_plan.get_message_type().columns[0].column_type,
+            DBType::Double
+        );
+
+        let mut physical_plan = query_planner.build_physical_plan(&logical_plan);
+        physical_plan.open().unwrap();
+        let retreived_messages: Vec<Message> = physical_plan
+            .collect::<Result<Vec<Message>, ExecutorError>>()
+            .unwrap();
+        assert_eq!(
+            retreived_messages,
+            vec![Message {
+                type_name: None,
+                fields: vec![DBValue::Double(11.5f32)],
+            }]
+        );
+
+        //CAST(Text AS Int) fails on the second row
+        let cast = RawPlan::Projection {
+            raw_expressions: vec![(
+                "Parsed".to_owned(),
+                RawExpression::Cast {
+                    expr: Box::new(RawExpression::ColumnRef("Text".to_owned())),
+                    db_type: DBType::Int,
+                },
+            )],
+            source: Box::new(RawPlan::Scan {
+                table_name: "Numbers".to_owned(),
+            }),
+        };
+
+        let logical_plan = query_planner.build_logical_plan(&cast).unwrap();
+        let mut physical_plan = query_planner.build_physical_plan(&logical_plan);
+        physical_plan.open().unwrap();
+
+        let first = physical_plan.next().unwrap().unwrap();
+        assert_eq!(first.fields, vec![DBValue::Int(12i32)]);
+        assert!(matches!(
+            physical_plan.next(),
+            Some(Err(ExecutorError::ConversionError(_)))
+        ));
+
+        //negative literals do not fit into unsigned columns
+        let cast_literal = RawExpression::Cast {
+            expr: Box::new(RawExpression::Literal(DBValue::Int(-1i32))),
+            db_type: DBType::UInt,
+        };
+        assert!(
+            query_planner
+                .build_expression(&cast_literal, &message_type)
+                .is_err()
+        );
+
+        //literals that fit keep the column unsigned, other mixes of Unsigned and Int are
+        //compared exactly and converted to Int with a check in arithmetic
+        let large = Message {
+            type_name: None,
+            fields: vec![
+                DBValue::UInt(u32::MAX),
+                DBValue::Double(0f32),
+                DBValue::String(String::new()),
+            ],
+        };
+        let small = Message {
+            type_name: None,
+            fields: vec![
+                DBValue::UInt(3u32),
+                DBValue::Double(0f32),
+                DBValue::String(String::new()),
+            ],
+        };
+        let build = |op: BinaryOperator, literal: i32| {
+            query_planner
+                .build_expression(
+                    &RawExpression::BinaryOp {
+                        op,
+                        left: Box::new(RawExpression::ColumnRef("Unsigned".to_owned())),
+                        right: Box::new(RawExpression::Literal(DBValue::Int(literal))),
+                    },
+                    &message_type,
+                )
+                .unwrap()
+        };
+        let compare = |literal: i32| build(BinaryOperator::GreaterThan, literal);
+        let fitting = compare(5);
+        assert_eq!(
+            fitting,
+            Expression::BinaryOp {
+                op: BinaryOperator::GreaterThan,
+                left: Box::new(Expression::ColumnRef(0)),
+                right: Box::new(Expression::Literal(DBValue::UInt(5u32))),
+            }
+        );
+        assert_eq!(fitting.evaluate(&large).unwrap(), DBValue::Bool(true));
+        let negative = compare(-1);
+        assert_eq!(
+            negative,
+            Expression::BinaryOp {
+                op: BinaryOperator::GreaterThan,
+                left: Box::new(Expression::ColumnRef(0)),
+                right: Box::new(Expression::Literal(DBValue::Int(-1i32))),
+            }
+        );
+        assert_eq!(negative.evaluate(&large).unwrap(), DBValue::Bool(true));
+        assert_eq!(
+            BinaryOperator::Equals
+                .apply(DBValue::UInt(16777217u32), DBValue::Int(16777216i32))
+                .unwrap(),
+            DBValue::Bool(false)
+        );
+        assert_eq!(DBType::UInt.common_type(&DBType::Int), Some(DBType::Int));
+
+        //Unsigned - 5 stays unsigned and fails instead of wrapping around, Unsigned + -1 is
+        //computed as Int and fails when the column does not fit
+        let subtract = build(BinaryOperator::Subtract, 5);
+        assert!(matches!(
+            subtract.evaluate(&small),
+            Err(ExecutorError::ArithmeticError(_))
+        ));
+        let add = build(BinaryOperator::Add, -1);
+        assert_eq!(add.evaluate(&small).unwrap(), DBValue::Int(2i32));
+        assert!(matches!(
+            add.evaluate(&large),
+            Err(ExecutorError::ConversionError(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn scalar_functions_test() {
+        let path = "temp_path12";
+        utility::cleanup(path);
+
+        let query_planner = utility::create_query_planner(path);
+
+        let message_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "age".to_owned(),
+                    column_type: DBType::UInt,
+                    dependencies: vec![],
+                },
+            ],
+        };
+
+        let message = Message {
+            type_name: None,
+            fields: vec![DBValue::String("Johnny".to_owned()), DBValue::UInt(16u32)],
+        };
+
+        let call = |name: &str, args: Vec<RawExpression>| RawExpression::FunctionCall {
+            name: name.to_owned(),
+            args,
+        };
+        let column = |name: &str| RawExpression::ColumnRef(name.to_owned());
+        let int = |i: i32| RawExpression::Literal(DBValue::Int(i));
+        let string = |s: &str| RawExpression::Literal(DBValue::String(s.to_owned()));
+
+        let evaluate = |raw_expression: &RawExpression| {
+            let expression = query_planner
+                .build_expression(raw_expression, &message_type)
+                .unwrap();
+            let db_type = query_planner
+                .deduce_expression_type(&expression, &message_type)
+                .unwrap();
+            (db_type, expression.evaluate(&message).unwrap())
+        };
+
+        assert_eq!(
+            evaluate(&call("length", vec![column("name")])),
+            (DBType::Int, DBValue::Int(6))
+        );
+        assert_eq!(
+            evaluate(&call(
+                "upper",
+                vec![call("substr", vec![column("name"), int(2), int(3)])]
+            )),
+            (DBType::String, DBValue::String("OHN".to_owned()))
+        );
+        assert_eq!(
+            evaluate(&call("concat", vec![column("name"), string("!")])),
+            (DBType::String, DBValue::String("Johnny!".to_owned()))
+        );
+        assert_eq!(
+            evaluate(&call("starts_with", vec![column("name"), string("Jo")])),
+            (DBType::Bool, DBValue::Bool(true))
+        );
+        //unsigned argument is widened to Double
+        assert_eq!(
+            evaluate(&call("sqrt", vec![column("age")])),
+            (DBType::Double, DBValue::Double(4f32))
+        );
+        assert_eq!(
+            evaluate(&call("pow", vec![int(2), int(10)])),
+            (DBType::Double, DBValue::Double(1024f32))
+        );
+        assert_eq!(
+            evaluate(&call("abs", vec![int(-5)])),
+            (DBType::Int, DBValue::Int(5))
+        );
+
+        //the branch that is not taken is never evaluated
+        let condition = RawExpression::BinaryOp {
+            op: BinaryOperator::GreaterThan,
+            left: Box::new(column("age")),
+            right: Box::new(int(18)),
+        };
+        assert_eq!(
+            evaluate(&call(
+                "if",
+                vec![
+                    condition,
+                    call("sqrt", vec![int(-1)]),
+                    RawExpression::Literal(DBValue::Double(0.5f32)),
+                ]
+            )),
+            (DBType::Double, DBValue::Double(0.5f32))
+        );
+        //arguments are converted to their common type, the first one is returned
+        assert_eq!(
+            evaluate(&call(
+                "coalesce",
+                vec![
+                    column("age"),
+                    RawExpression::Literal(DBValue::Double(0.5f32))
+                ]
+            )),
+            (DBType::Double, DBValue::Double(16f32))
+        );
+        assert!(matches!(
+            query_planner.build_expression(
+                &call("coalesce", vec![column("age"), column("name")]),
+                &message_type
+            ),
+            Err(PlannerError::WrongFunctionArguments(_))
+        ));
+
+        assert!(matches!(
+            query_planner.build_expression(&call("unknown", vec![]), &message_type),
+            Err(PlannerError::UnknownFunction(_))
+        ));
+        assert!(matches!(
+            query_planner.build_expression(&call("length", vec![column("age")]), &message_type),
+            Err(PlannerError::WrongFunctionArguments(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn user_defined_functions_test() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use super::super::executor_layer::function::{ScalarFunction, Signature};
+
+        let path = "temp_path13";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let message_type = MessageType {
+            name: "Point".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "x".to_owned(),
+                    column_type: DBType::Double,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "y".to_owned(),
+                    column_type: DBType::Double,
+                    dependencies: vec![],
+                },
+            ],
+        };
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        query_planner
+            .register_function(
+                "manhattan",
+                vec![DBType::Double, DBType::Double],
+                DBType::Double,
+                move |args| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    match (&args[0], &args[1]) {
+                        (DBValue::Double(x), DBValue::Double(y)) => {
+                            Ok(DBValue::Double(x.abs() + y.abs()))
+                        }
+                        _ => panic!("Incorrect manhattan arguments"),
+                    }
+                },
+            )
+            .unwrap();
+
+        //built-in and already registered names are rejected
+        assert!(matches!(
+            query_planner.register_function("length", vec![], DBType::Int, |_| Ok(DBValue::Int(0))),
+            Err(PlannerError::DuplicateFunction(_))
+        ));
+
+        query_planner
+            .register_scalar_function(
+                ScalarFunction::new(
+                    "next_id",
+                    Signature::Exact {
+                        arguments: vec![],
+                        return_type: DBType::UInt,
+                    },
+                    {
+                        let next = AtomicUsize::new(0);
+                        move |_| Ok(DBValue::UInt(next.fetch_add(1, Ordering::SeqCst) as u32))
+                    },
+                )
+                .nondeterministic(),
+            )
+            .unwrap();
+
+        let call = |name: &str, args: Vec<RawExpression>| RawExpression::FunctionCall {
+            name: name.to_owned(),
+            args,
+        };
+
+        let message = Message {
+            type_name: None,
+            fields: vec![DBValue::Double(-1.5f32), DBValue::Double(2f32)],
+        };
+
+        let on_columns = query_planner
+            .build_expression(
+                &call(
+                    "manhattan",
+                    vec![
+                        RawExpression::ColumnRef("x".to_owned()),
+                        RawExpression::ColumnRef("y".to_owned()),
+                    ],
+                ),
+                &message_type,
+            )
+            .unwrap();
+        assert_eq!(
+            query_planner
+                .deduce_expression_type(&on_columns, &message_type)
+                .unwrap(),
+            DBType::Double
+        );
+        assert_eq!(
+            on_columns.evaluate(&message).unwrap(),
+            DBValue::Double(3.5f32)
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        //literal arguments are widened and the call is folded during planning
+        let folded = query_planner
+            .build_expression(
+                &call(
+                    "manhattan",
+                    vec![
+                        RawExpression::Literal(DBValue::Int(3)),
+                        RawExpression::Literal(DBValue::Int(-4)),
+                    ],
+                ),
+                &message_type,
+            )
+            .unwrap();
+        assert_eq!(folded, Expression::Literal(DBValue::Double(7f32)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        folded.evaluate(&message).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let not_folded = query_planner
+            .build_expression(&call("next_id", vec![]), &message_type)
+            .unwrap();
+        assert_eq!(not_folded.evaluate(&message).unwrap(), DBValue::UInt(0));
+        assert_eq!(not_folded.evaluate(&message).unwrap(), DBValue::UInt(1));
+
+        assert!(matches!(
+            query_planner.build_expression(
+                &call(
+                    "manhattan",
+                    vec![RawExpression::Literal(DBValue::String("x".to_owned()))]
+                ),
+                &message_type
+            ),
+            Err(PlannerError::WrongFunctionArguments(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn pattern_matching_test() {
+        let path = "temp_path14";
+        utility::cleanup(path);
+
+        let query_planner = utility::create_query_planner(path);
+
+        let message_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "age".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![],
+                },
+            ],
+        };
+
+        let pattern_match =
+            |column: &str, pattern: &str, kind: PatternKind| RawExpression::PatternMatch {
+                expr: Box::new(RawExpression::ColumnRef(column.to_owned())),
+                pattern: Box::new(RawExpression::Literal(DBValue::String(pattern.to_owned()))),
+                kind,
+            };
+
+        let matches = |name: &str, pattern: &str, kind: PatternKind| {
+            let message = Message {
+                type_name: None,
+                fields: vec![DBValue::String(name.to_owned()), DBValue::Int(0)],
+            };
+            query_planner
+                .build_expression(&pattern_match("name", pattern, kind), &message_type)
+                .unwrap()
+                .evaluate(&message)
+                .unwrap()
+                == DBValue::Bool(true)
+        };
+
+        assert!(matches("John", "Jo%", PatternKind::Like));
+        assert!(matches("John", "J_hn", PatternKind::Like));
+        assert!(!matches("John", "jo%", PatternKind::Like));
+        assert!(matches("John", "jo%", PatternKind::ILike));
+        assert!(!matches("Johnny", "J_hn", PatternKind::Like));
+        assert!(matches("100%", "100\\%", PatternKind::Like));
+        assert!(!matches("1000", "100\\%", PatternKind::Like));
+        assert!(matches("a.b", "a.b", PatternKind::Like));
+        assert!(!matches("axb", "a.b", PatternKind::Like));
+        assert!(matches("Johnny", "^J.*n+y$", PatternKind::Regex));
+        assert!(matches("Johnny", "hn", PatternKind::Regex));
+        assert!(!matches("Jane", "^Jo", PatternKind::Regex));
+
+        assert!(matches!(
+            query_planner.build_expression(
+                &pattern_match("name", "(unclosed", PatternKind::Regex),
+                &message_type
+            ),
+            Err(PlannerError::InvalidPattern(_))
+        ));
+        assert!(matches!(
+            query_planner.build_expression(
+                &pattern_match("age", "1%", PatternKind::Like),
+                &message_type
+            ),
+            Err(PlannerError::WrongOperandTypes)
+        ));
+        //pattern must be known during planning
+        assert!(matches!(
+            query_planner.build_expression(
+                &RawExpression::PatternMatch {
+                    expr: Box::new(RawExpression::ColumnRef("name".to_owned())),
+                    pattern: Box::new(RawExpression::ColumnRef("name".to_owned())),
+                    kind: PatternKind::Like,
+                },
+                &message_type
+            ),
+            Err(PlannerError::InvalidPattern(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn match_arms_test() {
+        let path = "temp_path15";
+        utility::cleanup(path);
+
+        let query_planner = utility::create_query_planner(path);
+
+        let enum_type = EnumType {
+            name: "ComplexEnum".to_owned(),
+            variants: vec![
+                EnumVariantType {
+                    name: "First".to_owned(),
+                    content: vec![("a".to_owned(), DBType::Int), ("b".to_owned(), DBType::Int)],
+                },
+                EnumVariantType {
+                    name: "Second".to_owned(),
+                    content: vec![("c".to_owned(), DBType::Int)],
+                },
+                EnumVariantType {
+                    name: "Third".to_owned(),
+                    content: vec![],
+                },
+            ],
+        };
+
+        let message_type = MessageType {
+            name: "EnumWrapper".to_owned(),
+            columns: vec![Column {
+                column_name: "field".to_owned(),
+                column_type: DBType::EnumType(enum_type),
+                dependencies: vec![],
+            }],
+        };
+
+        let arm = |variant: Option<&str>,
+                   bindings: Option<Vec<(&str, &str)>>,
+                   guard: Option<RawExpression>,
+                   expression: RawExpression| RawMatchArm {
+            variant: variant.map(|name| ("ComplexEnum".to_owned(), name.to_owned())),
+            bindings: bindings.map(|pairs| {
+                pairs
+                    .into_iter()
+                    .map(|(field, alias)| (field.to_owned(), alias.to_owned()))
+                    .collect()
+            }),
+            guard,
+            expression,
+        };
+
+        let column = |name: &str| Box::new(RawExpression::ColumnRef(name.to_owned()));
+        let int = |value: i32| RawExpression::Literal(DBValue::Int(value));
+
+        let match_expression = |arms: Vec<RawMatchArm>| RawExpression::UnaryOp {
+            op: RawUnaryOperator::EnumMatch(arms),
+            expr: column("field"),
+        };
+
+        let value = |choice: usize, values: Vec<i32>| Message {
+            type_name: None,
+            fields: vec![DBValue::EnumValue(EnumValue {
+                type_name: None,
+                choice,
+                values: values.into_iter().map(DBValue::Int).collect(),
+            })],
+        };
+
+        //MATCH field {
+        //    ComplexEnum::First { a: x, b } if x > b => x - b,
+        //    ComplexEnum::Second => c,
+        //    _ => 0,
+        //}
+        let arms = vec![
+            arm(
+                Some("First"),
+                Some(vec![("a", "x"), ("b", "b")]),
+                Some(RawExpression::BinaryOp {
+                    op: BinaryOperator::GreaterThan,
+                    left: column("x"),
+                    right: column("b"),
+                }),
+                RawExpression::BinaryOp {
+                    op: BinaryOperator::Subtract,
+                    left: column("x"),
+                    right: column("b"),
+                },
+            ),
+            arm(
+                Some("Second"),
+                None,
+                None,
+                RawExpression::ColumnRef("c".to_owned()),
+            ),
+            arm(None, None, None, int(0)),
+        ];
+
+        let expression = query_planner
+            .build_expression(&match_expression(arms.clone()), &message_type)
+            .unwrap();
+        assert_eq!(
+            query_planner
+                .deduce_expression_type(&expression, &message_type)
+                .unwrap(),
+            DBType::Int
+        );
+        assert_eq!(
+            expression.evaluate(&value(0, vec![5, 3])).unwrap(),
+            DBValue::Int(2)
+        );
+        assert_eq!(
+            expression.evaluate(&value(0, vec![3, 5])).unwrap(),
+            DBValue::Int(0)
+        );
+        assert_eq!(
+            expression.evaluate(&value(1, vec![7])).unwrap(),
+            DBValue::Int(7)
+        );
+        assert_eq!(
+            expression.evaluate(&value(2, vec![])).unwrap(),
+            DBValue::Int(0)
+        );
+
+        //arms may be written in any order
+        let reordered = vec![
+            arm(Some("Third"), None, None, int(3)),
+            arm(Some("Second"), None, None, int(2)),
+            arm(Some("First"), None, None, int(1)),
+        ];
+        let expression = query_planner
+            .build_expression(&match_expression(reordered), &message_type)
+            .unwrap();
+        assert_eq!(
+            expression.evaluate(&value(1, vec![7])).unwrap(),
+            DBValue::Int(2)
+        );
+
+        //guarded arm does not cover its variant
+        let non_exhaustive = vec![
+            arms[0].clone(),
+            arm(Some("Second"), None, None, int(2)),
+            arm(Some("Third"), None, None, int(3)),
+        ];
+        assert!(matches!(
+            query_planner.build_expression(&match_expression(non_exhaustive), &message_type),
+            Err(PlannerError::NonExhaustiveMatch(missing)) if missing == "First"
+        ));
+
+        let after_wildcard = vec![
+            arm(None, None, None, int(0)),
+            arm(Some("Third"), None, None, int(3)),
+        ];
+        assert!(matches!(
+            query_planner.build_expression(&match_expression(after_wildcard), &message_type),
+            Err(PlannerError::UnreachableMatchArm(name)) if name == "ComplexEnum::Third"
+        ));
+
+        let duplicate = vec![
+            arm(Some("Third"), None, None, int(3)),
+            arm(Some("Third"), None, None, int(4)),
+            arm(None, None, None, int(0)),
+        ];
+        assert!(matches!(
+            query_planner.build_expression(&match_expression(duplicate), &message_type),
+            Err(PlannerError::UnreachableMatchArm(_))
+        ));
+
+        //only bound fields are visible when bindings are written explicitly
+        let unbound = vec![
+            arm(
+                Some("First"),
+                Some(vec![("a", "x")]),
+                None,
+                RawExpression::ColumnRef("b".to_owned()),
+            ),
+            arm(None, None, None, int(0)),
+        ];
+        assert!(matches!(
+            query_planner.build_expression(&match_expression(unbound), &message_type),
+            Err(PlannerError::ColumnNotFound(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn variant_predicate_test() {
+        let path = "temp_path16";
+        utility::cleanup(path);
+
+        let query_planner = utility::create_query_planner(path);
+
+        let status_type = EnumType {
+            name: "Status".to_owned(),
+            variants: vec![
+                EnumVariantType {
+                    name: "Admin".to_owned(),
+                    content: vec![],
+                },
+                EnumVariantType {
+                    name: "User".to_owned(),
+                    content: vec![("level".to_owned(), DBType::Int)],
+                },
+            ],
+        };
+
+        let message_type = MessageType {
+            name: "Account".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "status".to_owned(),
+                    column_type: DBType::EnumType(status_type),
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+            ],
+        };
+
+        let is_variant =
+            |column: &str, type_name: &str, variant_name: &str| RawExpression::UnaryOp {
+                op: RawUnaryOperator::IsVariant(type_name.to_owned(), variant_name.to_owned()),
+                expr: Box::new(RawExpression::ColumnRef(column.to_owned())),
+            };
+
+        let admin = Message {
+            type_name: None,
+            fields: vec![
+                DBValue::EnumValue(EnumValue {
+                    type_name: None,
+                    choice: 0usize,
+                    values: vec![],
+                }),
+                DBValue::String("John".to_owned()),
+            ],
+        };
+        let user = Message {
+            type_name: None,
+            fields: vec![
+                DBValue::EnumValue(EnumValue {
+                    type_name: None,
+                    choice: 1usize,
+                    values: vec![DBValue::Int(3)],
+                }),
+                DBValue::String("Jane".to_owned()),
+            ],
+        };
+
+        let expression = query_planner
+            .build_expression(&is_variant("status", "Status", "Admin"), &message_type)
+            .unwrap();
+        assert_eq!(
+            expression,
+            Expression::UnaryOp {
+                op: UnaryOperator::IsVariant(0usize),
+                expr: Box::new(Expression::ColumnRef(0usize)),
+            }
+        );
+        assert_eq!(
+            query_planner
+                .deduce_expression_type(&expression, &message_type)
+                .unwrap(),
+            DBType::Bool
+        );
+        assert_eq!(expression.evaluate(&admin).unwrap(), DBValue::Bool(true));
+        assert_eq!(expression.evaluate(&user).unwrap(), DBValue::Bool(false));
+
+        //IS NOT is planned as a negation
+        let negated = query_planner
+            .build_expression(
+                &RawExpression::UnaryOp {
+                    op: RawUnaryOperator::Not,
+                    expr: Box::new(is_variant("status", "Status", "Admin")),
+                },
+                &message_type,
+            )
+            .unwrap();
+        assert_eq!(negated.evaluate(&user).unwrap(), DBValue::Bool(true));
+
+        assert!(matches!(
+            query_planner.build_expression(&is_variant("status", "Status", "Guest"), &message_type),
+            Err(PlannerError::EnumVariantNotFound(_, _))
+        ));
+        assert!(matches!(
+            query_planner.build_expression(&is_variant("status", "Role", "Admin"), &message_type),
+            Err(PlannerError::WrongOperandTypes)
+        ));
+        assert!(matches!(
+            query_planner.build_expression(&is_variant("name", "Status", "Admin"), &message_type),
+            Err(PlannerError::WrongOperandTypes)
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn constructors_test() {
+        let path = "temp_path17";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let column = |name: &str, column_type: DBType| Column {
+            column_name: name.to_owned(),
+            column_type,
+            dependencies: vec![],
+        };
+
+        let person_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![column("name", DBType::String), column("age", DBType::Int)],
+        };
+        let summary_type = MessageType {
+            name: "Summary".to_owned(),
+            columns: vec![
+                column("label", DBType::String),
+                column("age", DBType::Double),
+            ],
+        };
+        let size_type = EnumType {
+            name: "Size".to_owned(),
+            variants: vec![
+                EnumVariantType {
+                    name: "Small".to_owned(),
+                    content: vec![],
+                },
+                EnumVariantType {
+                    name: "Big".to_owned(),
+                    content: vec![("value".to_owned(), DBType::UInt)],
+                },
+            ],
+        };
+
+        query_planner
+            .add_message_type("Person".to_owned(), person_type.clone())
+            .unwrap();
+        query_planner
+            .add_message_type("Summary".to_owned(), summary_type.clone())
+            .unwrap();
+        query_planner
+            .add_enum_type("Size".to_owned(), size_type.clone())
+            .unwrap();
+
+        let column_ref = |name: &str| Box::new(RawExpression::ColumnRef(name.to_owned()));
+
+        let person = Message {
+            type_name: None,
+            fields: vec![DBValue::String("John".to_owned()), DBValue::Int(25)],
+        };
+
+        //[Summary { name ++ "!", age + 1 }], age is widened to Double
+        let summary = RawExpression::MessageConstructor {
+            type_name: "Summary".to_owned(),
+            fields: vec![
+                RawExpression::BinaryOp {
+                    op: BinaryOperator::Concat,
+                    left: column_ref("name"),
+                    right: Box::new(RawExpression::Literal(DBValue::String("!".to_owned()))),
+                },
+                RawExpression::BinaryOp {
+                    op: BinaryOperator::Add,
+                    left: column_ref("age"),
+                    right: Box::new(RawExpression::Literal(DBValue::Int(1))),
+                },
+            ],
+        };
+
+        let expression = query_planner
+            .build_expression(&summary, &person_type)
+            .unwrap();
+        assert_eq!(
+            query_planner
+                .deduce_expression_type(&expression, &person_type)
+                .unwrap(),
+            DBType::MessageType(summary_type)
+        );
+        assert_eq!(
+            expression.evaluate(&person).unwrap(),
+            DBValue::Message(Message {
+                type_name: Some("Summary".to_owned()),
+                fields: vec![DBValue::String("John!".to_owned()), DBValue::Double(26f32)],
+            })
+        );
+
+        //[Size::Big { CAST(age AS Unsigned) }]
+        let big = RawExpression::EnumConstructor {
+            type_name: "Size".to_owned(),
+            variant_name: "Big".to_owned(),
+            fields: vec![RawExpression::Cast {
+                expr: column_ref("age"),
+                db_type: DBType::UInt,
+            }],
+        };
+        let expression = query_planner.build_expression(&big, &person_type).unwrap();
+        assert_eq!(
+            query_planner
+                .deduce_expression_type(&expression, &person_type)
+                .unwrap(),
+            DBType::EnumType(size_type)
+        );
+        assert_eq!(
+            expression.evaluate(&person).unwrap(),
+            DBValue::EnumValue(EnumValue {
+                type_name: Some("Size".to_owned()),
+                choice: 1usize,
+                values: vec![DBValue::UInt(25u32)],
+            })
+        );
+
+        //Int column can not be narrowed to UInt implicitly
+        let narrowed = RawExpression::EnumConstructor {
+            type_name: "Size".to_owned(),
+            variant_name: "Big".to_owned(),
+            fields: vec![RawExpression::ColumnRef("age".to_owned())],
+        };
+        assert!(matches!(
+            query_planner.build_expression(&narrowed, &person_type),
+            Err(PlannerError::MismatchedFieldTypes(_))
+        ));
+
+        let wrong_count = RawExpression::MessageConstructor {
+            type_name: "Summary".to_owned(),
+            fields: vec![RawExpression::ColumnRef("name".to_owned())],
+        };
+        assert!(matches!(
+            query_planner.build_expression(&wrong_count, &person_type),
+            Err(PlannerError::MismatchedFieldTypes(_))
+        ));
+
+        let unknown = RawExpression::MessageConstructor {
+            type_name: "Unknown".to_owned(),
+            fields: vec![],
+        };
+        assert!(matches!(
+            query_planner.build_expression(&unknown, &person_type),
+            Err(PlannerError::UnexistingMessageType(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn named_fields_test() {
+        let path = "temp_path18";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let column = |name: &str, column_type: DBType| Column {
+            column_name: name.to_owned(),
+            column_type,
+            dependencies: vec![],
+        };
+
+        let person_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                column("name", DBType::String),
+                column("age", DBType::UInt),
+                column("admin", DBType::Bool),
+            ],
+        };
+        query_planner
+            .add_message_type("Person".to_owned(), person_type.clone())
+            .unwrap();
+
+        let named = |fields: Vec<(&str, ast::Value)>| {
+            let (names, values): (Vec<String>, Vec<ast::Value>) = fields
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .unzip();
+            ast::Value::Message(ast::Message {
+                type_name: "Person".to_owned(),
+                values,
+                field_names: Some(names),
+            })
+        };
+
+        //fields are reordered and literals are coerced to the column types
+        let value = query_planner
+            .from_parsed_value(&named(vec![
+                ("admin", ast::Value::Bool(false)),
+                ("name", ast::Value::String("John".to_owned())),
+                ("age", ast::Value::Int(26)),
+            ]))
+            .unwrap();
+        assert_eq!(
+            value,
+            DBValue::Message(Message {
+                type_name: Some("Person".to_owned()),
+                fields: vec![
+                    DBValue::String("John".to_owned()),
+                    DBValue::UInt(26u32),
+                    DBValue::Bool(false),
+                ],
+            })
+        );
+
+        //positional form is kept
+        let positional = ast::Value::Message(ast::Message {
+            type_name: "Person".to_owned(),
+            values: vec![
+                ast::Value::String("John".to_owned()),
+                ast::Value::Int(26),
+                ast::Value::Bool(false),
+            ],
+            field_names: None,
+        });
+        assert_eq!(query_planner.from_parsed_value(&positional).unwrap(), value);
+
+        assert!(matches!(
+            query_planner.from_parsed_value(&named(vec![
+                ("name", ast::Value::String("John".to_owned())),
+                ("age", ast::Value::Int(26)),
+            ])),
+            Err(PlannerError::MissingField(_, field)) if field == "admin"
+        ));
+        assert!(matches!(
+            query_planner.from_parsed_value(&named(vec![
+                ("name", ast::Value::String("John".to_owned())),
+                ("name", ast::Value::String("Jane".to_owned())),
+                ("age", ast::Value::Int(26)),
+                ("admin", ast::Value::Bool(false)),
+            ])),
+            Err(PlannerError::DuplicateField(_, field)) if field == "name"
+        ));
+        assert!(matches!(
+            query_planner.from_parsed_value(&named(vec![
+                ("name", ast::Value::String("John".to_owned())),
+                ("surname", ast::Value::String("Doe".to_owned())),
+                ("age", ast::Value::Int(26)),
+                ("admin", ast::Value::Bool(false)),
+            ])),
+            Err(PlannerError::UnknownField(_, field)) if field == "surname"
+        ));
+
+        //constructors accept named fields as well
+        let constructor = ast::Expression::MessageConstructor {
+            type_name: "Person".to_owned(),
+            fields: vec![
+                ast::Expression::Literal(ast::Value::Bool(true)),
+                ast::Expression::ColumnRef("name".to_owned()),
+                ast::Expression::ColumnRef("age".to_owned()),
+            ],
+            field_names: Some(vec![
+                "admin".to_owned(),
+                "name".to_owned(),
+                "age".to_owned(),
+            ]),
+        };
+        let raw_expression = query_planner.from_parsed_expression(&constructor).unwrap();
+        let expression = query_planner
+            .build_expression(&raw_expression, &person_type)
+            .unwrap();
+        let person = Message {
+            type_name: None,
+            fields: vec![
+                DBValue::String("Jane".to_owned()),
+                DBValue::UInt(20u32),
+                DBValue::Bool(false),
+            ],
+        };
+        assert_eq!(
+            expression.evaluate(&person).unwrap(),
+            DBValue::Message(Message {
+                type_name: Some("Person".to_owned()),
+                fields: vec![
+                    DBValue::String("Jane".to_owned()),
+                    DBValue::UInt(20u32),
+                    DBValue::Bool(true),
+                ],
+            })
+        );
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn insert_select_test() {
+        let path = "temp_path19";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let person_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "age".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![],
+                },
+            ],
+        };
+
+        //more than one insertion batch
+        let messages = (0..1500).map(|i| Message {
+            type_name: Some("Person".to_owned()),
+            fields: vec![DBValue::String(format!("person{}", i)), DBValue::Int(i)],
+        });
+
+        for table_name in ["people", "adults"] {
+            query_planner
+                .table_manager
+                .create_table(table_name.to_owned(), person_type.clone())
+                .unwrap();
+        }
+        query_planner
+            .table_manager
+            .insert_messages("people".to_owned(), messages)
+            .unwrap();
+
+        let projection = |columns: Vec<&str>, condition: Option<RawExpression>| {
+            let scan = RawPlan::Scan {
+                table_name: "people".to_owned(),
+            };
+            let source = match condition {
+                Some(raw_expression) => RawPlan::Filter {
+                    raw_expression,
+                    source: Box::new(scan),
+                },
+                None => scan,
+            };
+            RawPlan::Projection {
+                raw_expressions: columns
+                    .into_iter()
+                    .map(|column| {
+                        (
+                            column.to_owned(),
+                            RawExpression::ColumnRef(column.to_owned()),
+                        )
+                    })
+                    .collect(),
+                source: Box::new(source),
+            }
+        };
+
+        //INSERT INTO adults SELECT name, age FROM people WHERE age >= 1000;
+        let adults = projection(
+            vec!["name", "age"],
+            Some(RawExpression::BinaryOp {
+                op: BinaryOperator::GreaterOrEqual,
+                left: Box::new(RawExpression::ColumnRef("age".to_owned())),
+                right: Box::new(RawExpression::Literal(DBValue::Int(1000))),
+            }),
+        );
+        let logical_plan = query_planner.build_logical_plan(&adults).unwrap();
+        assert_eq!(
+            query_planner
+                .insert_from_plan("adults", &logical_plan)
+                .unwrap(),
+            500
+        );
+
+        let inserted: Vec<Message> = query_planner
+            .table_manager
+            .iter("adults".to_owned())
+            .unwrap()
+            .collect();
+        assert_eq!(inserted.len(), 500);
+        assert_eq!(
+            inserted[0],
+            Message {
+                type_name: Some("Person".to_owned()),
+                fields: vec![DBValue::String("person1000".to_owned()), DBValue::Int(1000)],
+            }
+        );
+
+        //rows inserted by the query are not visible to its own scan
+        let everyone = query_planner
+            .build_logical_plan(&projection(vec!["name", "age"], None))
+            .unwrap();
+        assert_eq!(
+            query_planner.insert_from_plan("people", &everyone).unwrap(),
+            1500
+        );
+        assert_eq!(
+            query_planner
+                .table_manager
+                .iter("people".to_owned())
+                .unwrap()
+                .count(),
+            3000
+        );
+
+        let swapped = query_planner
+            .build_logical_plan(&projection(vec!["age", "name"], None))
+            .unwrap();
+        assert!(matches!(
+            query_planner.insert_from_plan("adults", &swapped),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+
+        let names = query_planner
+            .build_logical_plan(&projection(vec!["name"], None))
+            .unwrap();
+        assert!(matches!(
+            query_planner.insert_from_plan("adults", &names),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn create_table_as_test() {
+        let path = "temp_path20";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let person_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "age".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![],
+                },
+            ],
+        };
+        query_planner
+            .add_message_type("Person".to_owned(), person_type.clone())
+            .unwrap();
+        query_planner
+            .table_manager
+            .create_table("people".to_owned(), person_type)
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages(
+                "people".to_owned(),
+                (0..10).map(|i| Message {
+                    type_name: Some("Person".to_owned()),
+                    fields: vec![DBValue::String(format!("person{}", i)), DBValue::Int(i)],
+                }),
+            )
+            .unwrap();
+
+        let select = |query_planner: &QueryPlanner, columns: Vec<(&str, RawExpression)>| {
+            let raw_plan = RawPlan::Projection {
+                raw_expressions: columns
+                    .into_iter()
+                    .map(|(alias, raw_expression)| (alias.to_owned(), raw_expression))
+                    .collect(),
+                source: Box::new(RawPlan::Scan {
+                    table_name: "people".to_owned(),
+                }),
+            };
+            query_planner.build_logical_plan(&raw_plan).unwrap()
+        };
+        let column_ref = |name: &str| RawExpression::ColumnRef(name.to_owned());
+
+        //CREATE TABLE names AS SELECT name, age * 2 AS double_age FROM people;
+        let names = select(
+            &query_planner,
+            vec![
+                ("name", column_ref("name")),
+                (
+                    "double_age",
+                    RawExpression::BinaryOp {
+                        op: BinaryOperator::Multiply,
+                        left: Box::new(column_ref("age")),
+                        right: Box::new(RawExpression::Literal(DBValue::Int(2))),
+                    },
+                ),
+            ],
+        );
+        let result = query_planner.create_table_as("names", None, &names);
+
+        assert_eq!(result.unwrap(), ("names_row".to_owned(), 10usize));
+        let names_type = query_planner
+            .get_message_type(&"names_row".to_owned())
+            .unwrap();
+        assert_eq!(names_type.name, "names_row");
+        assert_eq!(names_type.columns[1].column_name, "double_age");
+        assert_eq!(
+            query_planner
+                .table_manager
+                .schema("names".to_owned())
+                .unwrap(),
+            names_type
+        );
+        let rows: Vec<Message> = query_planner
+            .table_manager
+            .iter("names".to_owned())
+            .unwrap()
+            .collect();
+        assert_eq!(
+            rows[3],
+            Message {
+                type_name: Some("names_row".to_owned()),
+                fields: vec![DBValue::String("person3".to_owned()), DBValue::Int(6)],
+            }
+        );
+
+        //generated names do not collide with existing types
+        let ages = select(&query_planner, vec![("age", column_ref("age"))]);
+        query_planner
+            .add_message_type("ages_row".to_owned(), names_type)
+            .unwrap();
+        assert_eq!(
+            query_planner
+                .create_table_as("ages", None, &ages)
+                .unwrap()
+                .0,
+            "ages_row_1"
+        );
+
+        assert_eq!(
+            query_planner
+                .create_table_as("adults", Some("Adult".to_owned()), &ages)
+                .unwrap()
+                .0,
+            "Adult"
+        );
+        assert!(matches!(
+            query_planner.create_table_as("other", Some("Person".to_owned()), &ages),
+            Err(PlannerError::DuplicateMessageType(_))
+        ));
+
+        let duplicate_columns = select(
+            &query_planner,
+            vec![("age", column_ref("age")), ("age", column_ref("name"))],
+        );
+        assert!(matches!(
+            query_planner.create_table_as("duplicate", None, &duplicate_columns),
+            Err(PlannerError::DuplicateField(_, _))
+        ));
+
+        //nothing is created if the query fails while the rows are inserted
+        let parsed = select(
+            &query_planner,
+            vec![(
+                "number",
+                RawExpression::Cast {
+                    expr: Box::new(column_ref("name")),
+                    db_type: DBType::Int,
+                },
+            )],
+        );
+        assert!(matches!(
+            query_planner.create_table_as("numbers", None, &parsed),
+            Err(PlannerError::ExecutorError(ExecutorError::ConversionError(
+                _
+            )))
+        ));
+        for table in ["numbers", "numbers#creation"] {
+            assert!(
+                query_planner
+                    .table_manager
+                    .schema(table.to_owned())
+                    .is_err()
+            );
+        }
+        assert!(
+            query_planner
+                .get_message_type(&"numbers_row".to_owned())
+                .is_err()
+        );
+        assert!(matches!(
+            query_planner.create_table_as("names", None, &ages),
+            Err(PlannerError::ExecutorError(
+                ExecutorError::TableAlreadyExists
+            ))
+        ));
+
+        //scratch tables left by a stopped process are not listed and are replaced
+        let stale_type = query_planner
+            .table_manager
+            .schema("names".to_owned())
+            .unwrap();
+        query_planner
+            .table_manager
+            .create_table("seniors#creation".to_owned(), stale_type)
+            .unwrap();
+        let relations = query_planner.relations().len();
+        query_planner
+            .create_table_as("seniors", None, &ages)
+            .unwrap();
+        assert_eq!(query_planner.relations().len(), relations + 1);
+        assert!(
+            query_planner
+                .table_manager
+                .schema("seniors#creation".to_owned())
+                .is_err()
+        );
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn subqueries_test() {
+        let path = "temp_path21";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let column = |name: &str, column_type: DBType| Column {
+            column_name: name.to_owned(),
+            column_type,
+            dependencies: vec![],
+        };
+        let person_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![column("name", DBType::String), column("age", DBType::Int)],
+        };
+        let pet_type = MessageType {
+            name: "Pet".to_owned(),
+            columns: vec![
+                column("owner", DBType::String),
+                column("species", DBType::String),
+                column("legs", DBType::Int),
+            ],
+        };
+        for (table, message_type) in [("people", person_type), ("pets", pet_type)] {
+            query_planner
+                .table_manager
+                .create_table(table.to_owned(), message_type)
+                .unwrap();
+        }
+
+        let people = [("Ann", 25), ("Bob", 40), ("Cid", 35)];
+        let pets = [
+            ("Ann", "cat", 4),
+            ("Bob", "dog", 4),
+            ("Bob", "fish", 0),
+            ("Eve", "bird", 2),
+        ];
+        query_planner
+            .table_manager
+            .insert_messages(
+                "people".to_owned(),
+                people.iter().map(|(name, age)| Message {
+                    type_name: Some("Person".to_owned()),
+                    fields: vec![DBValue::String(name.to_string()), DBValue::Int(*age)],
+                }),
+            )
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages(
+                "pets".to_owned(),
+                pets.iter().map(|(owner, species, legs)| Message {
+                    type_name: Some("Pet".to_owned()),
+                    fields: vec![
+                        DBValue::String(owner.to_string()),
+                        DBValue::String(species.to_string()),
+                        DBValue::Int(*legs),
+                    ],
+                }),
+            )
+            .unwrap();
+
+        let parser = QueryParser::new();
+        let plan = |query: &str| match parser.parse(query).unwrap() {
+            ast::Query::Select(select) => {
+                query_planner.build_logical_plan(&query_planner.from_parsed_select(&select)?)
+            }
+            _ => unreachable!(),
+        };
+        let run = |plan: &LogicalPlan| {
+            let mut operator = query_planner.build_physical_plan(plan);
+            operator.open()?;
+            operator
+                .map(|message| message.map(|message| message.fields))
+                .collect::<Result<Vec<Vec<DBValue>>, ExecutorError>>()
+        };
+        let filter = |plan: &LogicalPlan| match plan {
+            LogicalPlan::Projection { source, .. } => match source.as_ref() {
+                LogicalPlan::Filter { expression, .. } => expression.clone(),
+                _ => panic!("No filter"),
+            },
+            _ => panic!("No projection"),
+        };
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| vec![DBValue::String(name.to_string())])
+                .collect::<Vec<Vec<DBValue>>>()
+        };
+
+        //uncorrelated IN is read once into a set
+        let cat_owners = plan(
+            r#"SELECT name FROM people WHERE name IN (SELECT owner FROM pets WHERE species == "cat");"#,
+        )
+        .unwrap();
+        assert!(matches!(filter(&cat_owners), Expression::InSet { .. }));
+        assert_eq!(run(&cat_owners).unwrap(), names(&["Ann"]));
+
+        //correlated EXISTS with an equality is turned into a semi-join
+        let dog_owners = plan(
+            r#"SELECT name FROM people WHERE EXISTS (SELECT species FROM pets WHERE owner == name && species == "dog");"#,
+        )
+        .unwrap();
+        assert!(matches!(filter(&dog_owners), Expression::InSet { .. }));
+        assert_eq!(run(&dog_owners).unwrap(), names(&["Bob"]));
+
+        let no_pets = plan(
+            "SELECT name FROM people WHERE !EXISTS (SELECT owner FROM pets WHERE owner == name);",
+        )
+        .unwrap();
+        assert_eq!(run(&no_pets).unwrap(), names(&["Cid"]));
+
+        //keys that mix inner and outer columns are not evaluated on the outer row alone
+        let cat_named = plan(
+            r#"SELECT name FROM people WHERE EXISTS (SELECT species FROM pets WHERE owner ++ "cat" == name ++ species);"#,
+        )
+        .unwrap();
+        assert!(matches!(filter(&cat_named), Expression::Exists(_)));
+        assert_eq!(run(&cat_named).unwrap(), names(&["Ann"]));
+
+        //other correlated conditions are evaluated for every row
+        let many_legs = plan(
+            "SELECT name FROM people WHERE EXISTS (SELECT owner FROM pets WHERE owner == name && legs * 10 > age);",
+        )
+        .unwrap();
+        assert!(matches!(filter(&many_legs), Expression::Exists(_)));
+        assert_eq!(run(&many_legs).unwrap(), names(&["Ann"]));
+
+        let legged_pets = plan(
+            r#"SELECT name, (SELECT species FROM pets WHERE owner == name && legs > 0) AS pet FROM people WHERE name != "Cid";"#,
+        )
+        .unwrap();
+        assert_eq!(
+            run(&legged_pets).unwrap(),
+            vec![
+                vec![
+                    DBValue::String("Ann".to_owned()),
+                    DBValue::String("cat".to_owned())
+                ],
+                vec![
+                    DBValue::String("Bob".to_owned()),
+                    DBValue::String("dog".to_owned())
+                ],
+            ]
+        );
+
+        //scalar subqueries must return exactly one row
+        let all_pets = plan(
+            r#"SELECT name, (SELECT species FROM pets WHERE owner == name) AS pet FROM people WHERE name == "Bob";"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            run(&all_pets),
+            Err(ExecutorError::SubqueryError(_))
+        ));
+
+        //uncorrelated scalar subqueries are evaluated during planning
+        let older = plan(
+            r#"SELECT name FROM people WHERE age > (SELECT legs FROM pets WHERE species == "cat") * 9;"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            filter(&older),
+            Expression::BinaryOp {
+                op: BinaryOperator::GreaterThan,
+                ..
+            }
+        ));
+        assert_eq!(run(&older).unwrap(), names(&["Bob"]));
+
+        //correlated and uncorrelated IN match values the same way, NaN included
+        let not_a_number = plan(
+            "SELECT name FROM people WHERE CAST(age AS Float) * 0.0 / 0.0 IN (SELECT CAST(legs AS Float) * 0.0 / 0.0 AS nan FROM pets WHERE owner == name);",
+        )
+        .unwrap();
+        assert!(!matches!(filter(&not_a_number), Expression::InSet { .. }));
+        assert_eq!(run(&not_a_number).unwrap(), names(&["Ann", "Bob"]));
+        let not_a_number = plan(
+            "SELECT name FROM people WHERE CAST(age AS Float) * 0.0 / 0.0 IN (SELECT CAST(legs AS Float) * 0.0 / 0.0 AS nan FROM pets);",
+        )
+        .unwrap();
+        assert!(matches!(filter(&not_a_number), Expression::InSet { .. }));
+        assert_eq!(run(&not_a_number).unwrap(), names(&["Ann", "Bob", "Cid"]));
+
+        assert!(matches!(
+            plan("SELECT name FROM people WHERE name IN (SELECT owner, species FROM pets);"),
+            Err(PlannerError::InvalidSubquery(_))
+        ));
+        assert!(matches!(
+            plan("SELECT name FROM people WHERE age IN (SELECT owner FROM pets);"),
+            Err(PlannerError::WrongOperandTypes)
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn cte_test() {
+        let path = "temp_path22";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let column = |name: &str, column_type: DBType| Column {
+            column_name: name.to_owned(),
+            column_type,
+            dependencies: vec![],
+        };
+        let tables = [
+            (
+                "nodes",
+                vec![
+                    column("id", DBType::Int),
+                    column("parent", DBType::Int),
+                    column("name", DBType::String),
+                ],
+                vec![
+                    (1, 0, "root"),
+                    (2, 1, "a"),
+                    (3, 1, "b"),
+                    (4, 2, "c"),
+                    (5, 0, "other"),
+                ],
+            ),
+            (
+                "edges",
+                vec![
+                    column("src", DBType::Int),
+                    column("dst", DBType::Int),
+                    column("label", DBType::String),
+                ],
+                vec![(1, 2, ""), (2, 3, ""), (3, 1, "")],
+            ),
+        ];
+        for (table, columns, rows) in tables {
+            query_planner
+                .table_manager
+                .create_table(
+                    table.to_owned(),
+                    MessageType {
+                        name: table.to_owned(),
+                        columns,
+                    },
+                )
+                .unwrap();
+            query_planner
+                .table_manager
+                .insert_messages(
+                    table.to_owned(),
+                    rows.into_iter().map(|(first, second, name)| Message {
+                        type_name: Some(table.to_owned()),
+                        fields: vec![
+                            DBValue::Int(first),
+                            DBValue::Int(second),
+                            DBValue::String(name.to_owned()),
+                        ],
+                    }),
+                )
+                .unwrap();
+        }
+
+        let parser = QueryParser::new();
+        let plan = |query: &str| match parser.parse(query).unwrap() {
+            ast::Query::Select(select) => {
+                query_planner.build_logical_plan(&query_planner.from_parsed_select(&select)?)
+            }
+            _ => unreachable!(),
+        };
+        let run = |query: &str| {
+            let logical_plan = plan(query)?;
+            let mut operator = query_planner.build_physical_plan(&logical_plan);
+            operator.open()?;
+            Ok::<Vec<DBValue>, PlannerError>(
+                operator
+                    .map(|message| message.map(|mut message| message.fields.remove(0)))
+                    .collect::<Result<Vec<DBValue>, ExecutorError>>()?,
+            )
+        };
+        let strings = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| DBValue::String(value.to_string()))
+                .collect::<Vec<DBValue>>()
+        };
+
+        //a cte used once is planned in place of the scan
+        let kids = "WITH kids AS (SELECT id, name FROM nodes WHERE parent == 1) SELECT name FROM kids WHERE id > 2;";
+        match plan(kids).unwrap() {
+            LogicalPlan::Projection { source, .. } => assert!(matches!(
+                *source,
+                LogicalPlan::Filter { source, .. } if matches!(*source, LogicalPlan::Projection { .. })
+            )),
+            _ => panic!("No projection"),
+        }
+        assert_eq!(run(kids).unwrap(), strings(&["b"]));
+
+        //used twice, so it is computed once
+        assert_eq!(
+            run("WITH roots AS (SELECT id FROM nodes WHERE parent == 0) SELECT name FROM nodes WHERE id IN (SELECT id FROM roots) || parent IN (SELECT id FROM roots);")
+                .unwrap(),
+            strings(&["root", "a", "b", "other"])
+        );
+
+        //ctes see the earlier ones, a cte may shadow a table
+        assert_eq!(
+            run("WITH nodes AS (SELECT id, name FROM nodes WHERE parent == 0), first AS (SELECT name FROM nodes WHERE id == 1) SELECT name FROM first;")
+                .unwrap(),
+            strings(&["root"])
+        );
+
+        let tree = "WITH RECURSIVE tree AS (SELECT id, name FROM nodes WHERE id == 1 UNION ALL SELECT id, name FROM nodes WHERE parent IN (SELECT id FROM tree)) SELECT name FROM tree;";
+        assert_eq!(run(tree).unwrap(), strings(&["root", "a", "b", "c"]));
+
+        //UNION skips rows that were already found, so cycles are fine
+        assert_eq!(
+            run("WITH RECURSIVE reach AS (SELECT dst FROM edges WHERE src == 1 UNION SELECT dst FROM edges WHERE src IN (SELECT dst FROM reach)) SELECT dst FROM reach;")
+                .unwrap(),
+            vec![DBValue::Int(2), DBValue::Int(3), DBValue::Int(1)]
+        );
+        assert!(matches!(
+            run(
+                "WITH RECURSIVE reach AS (SELECT dst FROM edges WHERE src == 1 UNION ALL SELECT dst FROM edges WHERE src IN (SELECT dst FROM reach)) SELECT dst FROM reach;"
+            ),
+            Err(PlannerError::RecursionLimit(_))
+        ));
+        assert!(matches!(
+            run(
+                "WITH RECURSIVE reach AS (SELECT dst FROM edges WHERE src == 1 UNION SELECT label FROM edges WHERE src IN (SELECT dst FROM reach)) SELECT dst FROM reach;"
+            ),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+
+        assert!(matches!(
+            run("WITH a AS (SELECT id FROM nodes), a AS (SELECT id FROM nodes) SELECT id FROM a;"),
+            Err(PlannerError::DuplicateCte(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn set_operations_test() {
+        let path = "temp_path23";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let scores_type = |score_type: DBType| MessageType {
+            name: "Score".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "score".to_owned(),
+                    column_type: score_type,
+                    dependencies: vec![],
+                },
+            ],
+        };
+        let int_rows = [("x", 1), ("y", 2), ("y", 2), ("z", 3)];
+        let double_rows = [("y", 2.0), ("w", 4.0)];
+        for table in ["ints", "copy"] {
+            query_planner
+                .table_manager
+                .create_table(table.to_owned(), scores_type(DBType::Int))
+                .unwrap();
+        }
+        query_planner
+            .table_manager
+            .create_table("doubles".to_owned(), scores_type(DBType::Double))
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages(
+                "ints".to_owned(),
+                int_rows
+                    .iter()
+                    .chain([("many", 0)].iter().cycle().take(2000))
+                    .enumerate()
+                    .map(|(i, (name, score))| Message {
+                        type_name: None,
+                        fields: vec![
+                            DBValue::String(name.to_string()),
+                            //the last rows have 1500 distinct scores
+                            DBValue::Int(if *name == "many" {
+                                i as i32 % 1500 + 10
+                            } else {
+                                *score
+                            }),
+                        ],
+                    }),
+            )
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages(
+                "doubles".to_owned(),
+                double_rows.iter().map(|(name, score)| Message {
+                    type_name: None,
+                    fields: vec![DBValue::String(name.to_string()), DBValue::Double(*score)],
+                }),
+            )
+            .unwrap();
+
+        let parser = QueryParser::new();
+        let plan = |query_planner: &QueryPlanner, query: &str| match parser.parse(query).unwrap() {
+            ast::Query::Select(select) => {
+                query_planner.build_logical_plan(&query_planner.from_parsed_select(&select)?)
+            }
+            _ => unreachable!(),
+        };
+        let run = |query_planner: &QueryPlanner, query: &str| {
+            let logical_plan = plan(query_planner, query)?;
+            let mut operator = query_planner.build_physical_plan(&logical_plan);
+            operator.open()?;
+            Ok::<Vec<Vec<DBValue>>, PlannerError>(
+                operator
+                    .map(|message| message.map(|message| message.fields))
+                    .collect::<Result<Vec<Vec<DBValue>>, ExecutorError>>()?,
+            )
+        };
+        let row =
+            |name: &str, score: f32| vec![DBValue::String(name.to_owned()), DBValue::Double(score)];
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| vec![DBValue::String(name.to_string())])
+                .collect::<Vec<Vec<DBValue>>>()
+        };
+
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT DISTINCT name FROM ints WHERE score < 10;"
+            )
+            .unwrap(),
+            names(&["x", "y", "z"])
+        );
+
+        //the Int column is widened to Double
+        let union_all = plan(
+            &query_planner,
+            "SELECT name, score FROM ints WHERE score < 10 UNION ALL SELECT name, score FROM doubles;",
+        )
+        .unwrap();
+        assert_eq!(
+            union_all.get_message_type().columns[1].column_type,
+            DBType::Double
+        );
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT name, score FROM ints WHERE score < 10 UNION ALL SELECT name, score FROM doubles;"
+            )
+            .unwrap()
+            .len(),
+            6
+        );
+
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT name, score FROM ints WHERE score < 10 UNION SELECT name, score FROM doubles;"
+            )
+            .unwrap(),
+            vec![row("x", 1.0), row("y", 2.0), row("z", 3.0), row("w", 4.0)]
+        );
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT name, score FROM ints INTERSECT SELECT name, score FROM doubles;"
+            )
+            .unwrap(),
+            vec![row("y", 2.0)]
+        );
+        assert_eq!(
+            run(
+                &query_planner,
+                r#"SELECT name FROM ints EXCEPT SELECT name FROM doubles EXCEPT SELECT name FROM ints WHERE name == "many";"#
+            )
+            .unwrap(),
+            names(&["x", "z"])
+        );
+
+        //set operations in subqueries are read as a whole
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT name FROM ints WHERE name IN (SELECT name FROM doubles UNION SELECT name FROM ints WHERE score == 1);"
+            )
+            .unwrap(),
+            names(&["x", "y", "y"])
+        );
+
+        assert!(matches!(
+            plan(
+                &query_planner,
+                "SELECT name FROM ints UNION SELECT score FROM doubles;"
+            ),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+        assert!(matches!(
+            plan(
+                &query_planner,
+                "SELECT name FROM ints UNION SELECT name, score FROM doubles;"
+            ),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+
+        //DISTINCT can not be resumed between insert batches, so it is read at once
+        let distinct = plan(&query_planner, "SELECT DISTINCT name, score FROM ints;").unwrap();
+        assert_eq!(
+            query_planner.insert_from_plan("copy", &distinct).unwrap(),
+            1503
+        );
+
+        //UNION ALL is resumed from both sides, the right one still starts from the rows the
+        //table had when the query started
+        let doubled = plan(
+            &query_planner,
+            "SELECT name, score FROM copy UNION ALL SELECT name, score FROM copy;",
+        )
+        .unwrap();
+        let mut physical_plan = query_planner.build_physical_plan(&doubled);
+        physical_plan.open().unwrap();
+        assert!(matches!(
+            physical_plan.cursor(),
+            Some(PlanCursor::Append(_, _))
+        ));
+        drop(physical_plan);
+        assert_eq!(
+            query_planner.insert_from_plan("copy", &doubled).unwrap(),
+            3006
+        );
+        assert_eq!(
+            query_planner
+                .table_manager
+                .iter("copy".to_owned())
+                .unwrap()
+                .count(),
+            4509
+        );
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn view_test() {
+        let path = "temp_path24";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let person_type = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "age".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![],
+                },
+            ],
+        };
+        query_planner
+            .table_manager
+            .create_table("people".to_owned(), person_type)
+            .unwrap();
+        let insert = |query_planner: &mut QueryPlanner, ages: std::ops::Range<i32>| {
+            query_planner
+                .table_manager
+                .insert_messages(
+                    "people".to_owned(),
+                    ages.map(|i| Message {
+                        type_name: None,
+                        fields: vec![DBValue::String(format!("person{}", i)), DBValue::Int(i)],
+                    }),
+                )
+                .unwrap();
+        };
+        insert(&mut query_planner, 0..30);
+
+        let parser = QueryParser::new();
+        let create =
+            |query_planner: &mut QueryPlanner, query: &str| match parser.parse(query).unwrap() {
+                ast::Query::CreateView {
+                    name,
+                    select,
+                    materialized,
+                } => query_planner.create_view(&name, &select, materialized),
+                _ => unreachable!(),
+            };
+        let run = |query_planner: &QueryPlanner, query: &str| match parser.parse(query).unwrap() {
+            ast::Query::Select(select) => {
+                let logical_plan = query_planner
+                    .build_logical_plan(&query_planner.from_parsed_select(&select)?)?;
+                let mut operator = query_planner.build_physical_plan(&logical_plan);
+                operator.open()?;
+                Ok::<Vec<Vec<DBValue>>, PlannerError>(
+                    operator
+                        .map(|message| message.map(|message| message.fields))
+                        .collect::<Result<Vec<Vec<DBValue>>, ExecutorError>>()?,
+                )
+            }
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            create(
+                &mut query_planner,
+                "CREATE VIEW adults AS SELECT name, age * 2 AS double_age FROM people WHERE age >= 18;"
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(
+            create(
+                &mut query_planner,
+                "CREATE MATERIALIZED VIEW seniors AS SELECT name, age FROM people WHERE age >= 25;"
+            )
+            .unwrap(),
+            5
+        );
+        assert!(matches!(
+            create(
+                &mut query_planner,
+                "CREATE VIEW adults AS SELECT name FROM people;"
+            ),
+            Err(PlannerError::DuplicateView(_))
+        ));
+        assert!(matches!(
+            create(
+                &mut query_planner,
+                "CREATE VIEW people AS SELECT name FROM people;"
+            ),
+            Err(PlannerError::ExecutorError(
+                ExecutorError::TableAlreadyExists
+            ))
+        ));
+
+        //views are expanded inside other queries, with their own column names
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT name FROM adults WHERE double_age > 56 && name IN (SELECT name FROM seniors);"
+            )
+            .unwrap(),
+            vec![vec![DBValue::String("person29".to_owned())]]
+        );
+        //ctes of the outer query are not visible inside the view
+        assert_eq!(
+            run(
+                &query_planner,
+                "WITH people AS (SELECT name, age FROM people WHERE age < 5) SELECT name FROM adults;"
+            )
+            .unwrap()
+            .len(),
+            12
+        );
+
+        //plain views see new rows right away, materialized ones after a refresh
+        insert(&mut query_planner, 30..32);
+        assert_eq!(
+            run(&query_planner, "SELECT name FROM adults;")
+                .unwrap()
+                .len(),
+            14
+        );
+        assert_eq!(
+            run(&query_planner, "SELECT name FROM seniors;")
+                .unwrap()
+                .len(),
+            5
+        );
+        assert_eq!(
+            query_planner.refresh_materialized_view("seniors").unwrap(),
+            7
+        );
+        assert_eq!(
+            run(&query_planner, "SELECT name FROM seniors;")
+                .unwrap()
+                .len(),
+            7
+        );
+        assert!(matches!(
+            query_planner.refresh_materialized_view("adults"),
+            Err(PlannerError::NotMaterialized(_))
+        ));
+
+        //views are kept in the catalog
+        drop(query_planner);
+        let mut query_planner = utility::create_query_planner(path);
+        assert_eq!(
+            run(&query_planner, "SELECT name FROM adults;")
+                .unwrap()
+                .len(),
+            14
+        );
+
+        //table statements do not change views
+        let message_type = query_planner.get_view("seniors").unwrap().message_type;
+        for name in ["adults", "seniors"] {
+            assert!(matches!(
+                query_planner.create_table(name, message_type.clone()),
+                Err(PlannerError::ViewNotTable(_))
+            ));
+            assert!(matches!(
+                query_planner.drop_table(name),
+                Err(PlannerError::ViewNotTable(_))
+            ));
+            assert!(matches!(
+                query_planner.insert_messages(name, vec![]),
+                Err(PlannerError::ViewNotTable(_))
+            ));
+        }
+        let logical_plan = LogicalPlan::Scan {
+            table_name: "seniors".to_owned(),
+            message_type,
+        };
+        assert!(matches!(
+            query_planner.insert_from_plan("seniors", &logical_plan),
+            Err(PlannerError::ViewNotTable(_))
+        ));
+        assert_eq!(
+            run(&query_planner, "SELECT name FROM seniors;")
+                .unwrap()
+                .len(),
+            7
+        );
+
+        query_planner.drop_view("seniors").unwrap();
+        assert!(
+            query_planner
+                .table_manager
+                .schema("seniors".to_owned())
+                .is_err()
+        );
+        assert!(matches!(
+            query_planner.drop_view("seniors"),
+            Err(PlannerError::UnexistingView(_))
+        ));
+
+        //views whose messages are already gone are still removed from the catalog
+        create(
+            &mut query_planner,
+            "CREATE MATERIALIZED VIEW seniors AS SELECT name, age FROM people WHERE age >= 25;",
+        )
+        .unwrap();
+        query_planner
+            .table_manager
+            .drop_table("seniors".to_owned())
+            .unwrap();
+        query_planner.drop_view("seniors").unwrap();
+        assert!(query_planner.get_view("seniors").is_err());
+
+        //a failing query creates nothing and keeps the old messages of a refreshed view
+        assert!(matches!(
+            create(
+                &mut query_planner,
+                "CREATE MATERIALIZED VIEW ratios AS SELECT name, 100 / age AS ratio FROM people;"
+            ),
+            Err(PlannerError::ExecutorError(ExecutorError::ArithmeticError(
+                _
+            )))
+        ));
+        assert!(query_planner.get_view("ratios").is_err());
+        assert!(
+            query_planner
+                .table_manager
+                .schema("ratios".to_owned())
+                .is_err()
+        );
+        assert_eq!(
+            create(
+                &mut query_planner,
+                "CREATE MATERIALIZED VIEW ratios AS SELECT name, 100 / (40 - age) AS ratio FROM people WHERE age >= 28;"
+            )
+            .unwrap(),
+            4
+        );
+        insert(&mut query_planner, 40..41);
+        assert!(matches!(
+            query_planner.refresh_materialized_view("ratios"),
+            Err(PlannerError::ExecutorError(ExecutorError::ArithmeticError(
+                _
+            )))
+        ));
+        assert_eq!(
+            run(&query_planner, "SELECT name FROM ratios;")
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(query_planner.relations().len(), 3);
+
+        //'#' is reserved for scratch tables
+        assert!(matches!(
+            create(
+                &mut query_planner,
+                "CREATE MATERIALIZED VIEW `ratios#refresh` AS SELECT name FROM people;"
+            ),
+            Err(PlannerError::ReservedName(_))
+        ));
+        let message_type = query_planner.get_view("ratios").unwrap().message_type;
+        assert!(matches!(
+            query_planner.create_table("people#migration", message_type),
+            Err(PlannerError::ReservedName(_))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn alter_table_test() {
+        let path = "temp_path25";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let column = |name: &str, column_type: DBType| Column {
+            column_name: name.to_owned(),
+            column_type,
+            dependencies: vec![],
+        };
+        let user_type = |columns: Vec<Column>| MessageType {
+            name: "User".to_owned(),
+            columns,
+        };
+        let first = user_type(vec![
+            column("name", DBType::String),
+            column("age", DBType::Int),
+        ]);
+        query_planner
+            .add_message_type("User".to_owned(), first.clone())
+            .unwrap();
+        query_planner
+            .table_manager
+            .create_table("users".to_owned(), first.clone())
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages(
+                "users".to_owned(),
+                (0..30).map(|i| Message {
+                    type_name: Some("User".to_owned()),
+                    fields: vec![DBValue::String(format!("user{}", i)), DBValue::Int(i)],
+                }),
+            )
+            .unwrap();
+
+        let second = user_type(vec![
+            column("name", DBType::String),
+            column("age", DBType::Double),
+            column("adult", DBType::Bool),
+        ]);
+        assert_eq!(
+            query_planner
+                .replace_message_type("User".to_owned(), second.clone())
+                .unwrap(),
+            2
+        );
+        assert_eq!(query_planner.message_type_version(&first), Some(1));
+        assert_eq!(query_planner.message_type_version(&second), Some(2));
+
+        let parser = QueryParser::new();
+        let alter =
+            |query_planner: &mut QueryPlanner, query: &str| match parser.parse(query).unwrap() {
+                ast::Query::AlterTableType {
+                    table,
+                    type_name,
+                    using,
+                } => query_planner.alter_table_type(&table, &type_name, using.as_ref()),
+                _ => unreachable!(),
+            };
+        let rows = |query_planner: &QueryPlanner| {
+            query_planner
+                .table_manager
+                .iter("users".to_owned())
+                .unwrap()
+                .collect::<Vec<Message>>()
+        };
+
+        assert!(matches!(
+            alter(&mut query_planner, "ALTER TABLE users SET TYPE User;"),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+        //the conversion fails on the first row, nothing is changed
+        assert!(
+            alter(
+                &mut query_planner,
+                "ALTER TABLE users SET TYPE User USING [User { name, age, CAST(name AS Int) > 0 }];"
+            )
+            .is_err()
+        );
+        assert_eq!(
+            query_planner
+                .table_manager
+                .schema("users".to_owned())
+                .unwrap(),
+            first
+        );
+        assert!(
+            query_planner
+                .table_manager
+                .schema("users#migration".to_owned())
+                .is_err()
+        );
+
+        assert_eq!(
+            alter(
+                &mut query_planner,
+                "ALTER TABLE users SET TYPE User USING [User { adult: age >= 18, name: name, age: age }];"
+            )
+            .unwrap(),
+            30
+        );
+        let altered = rows(&query_planner);
+        assert_eq!(altered.len(), 30);
+        assert_eq!(
+            altered[20],
+            Message {
+                type_name: Some("User".to_owned()),
+                fields: vec![
+                    DBValue::String("user20".to_owned()),
+                    DBValue::Double(20.0),
+                    DBValue::Bool(true),
+                ],
+            }
+        );
+        assert_eq!(
+            query_planner.message_type_version(
+                &query_planner
+                    .table_manager
+                    .schema("users".to_owned())
+                    .unwrap()
+            ),
+            Some(2)
+        );
+
+        //columns are matched by name without USING
+        let reordered = MessageType {
+            name: "Person".to_owned(),
+            columns: vec![
+                column("adult", DBType::Bool),
+                column("name", DBType::String),
+            ],
+        };
+        query_planner
+            .add_message_type("Person".to_owned(), reordered)
+            .unwrap();
+        alter(&mut query_planner, "ALTER TABLE users SET TYPE Person;").unwrap();
+        assert_eq!(
+            rows(&query_planner)[3].fields,
+            vec![DBValue::Bool(false), DBValue::String("user3".to_owned())]
+        );
+
+        //any expression of the new type may be used
+        alter(
+            &mut query_planner,
+            "ALTER TABLE users SET TYPE User USING if(adult, [User { name, 18, true }], [User { name, 0, false }]);",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&query_planner)[25].fields,
+            vec![
+                DBValue::String("user25".to_owned()),
+                DBValue::Double(18.0),
+                DBValue::Bool(true),
+            ]
+        );
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn fetch_types_diff_test() {
+        let path = "temp_path26";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let status = |variants: &[&str]| EnumType {
+            name: "Status".to_owned(),
+            variants: variants
+                .iter()
+                .map(|name| EnumVariantType {
+                    name: name.to_string(),
+                    content: vec![],
+                })
+                .collect(),
+        };
+        let user = |status: &EnumType| MessageType {
+            name: "User".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "status".to_owned(),
+                    column_type: DBType::EnumType(status.clone()),
+                    dependencies: vec![],
+                },
+            ],
+        };
+        let fetched = |status: &EnumType| {
+            vec![
+                FetchedType::EnumType(status.clone()),
+                FetchedType::MessageType(user(status)),
+            ]
+        };
+        let message = |choice: usize| Message {
+            type_name: Some("User".to_owned()),
+            fields: vec![
+                DBValue::String("John".to_owned()),
+                DBValue::EnumValue(EnumValue {
+                    type_name: Some("Status".to_owned()),
+                    choice,
+                    values: vec![],
+                }),
+            ],
+        };
+
+        let first = status(&["Admin", "User"]);
+        assert_eq!(
+            query_planner
+                .fetch_types(&fetched(&first), false)
+                .unwrap()
+                .changes,
+            vec![
+                TypeChange::AddedType("Status".to_owned()),
+                TypeChange::AddedType("User".to_owned()),
+            ]
+        );
+        query_planner
+            .table_manager
+            .create_table("users".to_owned(), user(&first))
+            .unwrap();
+        query_planner
+            .table_manager
+            .insert_messages("users".to_owned(), [message(1)].into_iter())
+            .unwrap();
+
+        //fetching the same file again changes nothing
+        assert!(
+            query_planner
+                .fetch_types(&fetched(&first), false)
+                .unwrap()
+                .is_empty()
+        );
+
+        //appended variants are compatible, stored tables are updated as well
+        let second = status(&["Admin", "User", "Guest"]);
+        assert!(matches!(
+            query_planner.fetch_types(&fetched(&second), false),
+            Err(PlannerError::ChangedTypes(names)) if names == ["Status", "User"]
+        ));
+        let diff = query_planner.diff_types(&fetched(&second));
+        assert_eq!(
+            diff.changes,
+            vec![TypeChange::AddedVariant {
+                type_name: "Status".to_owned(),
+                variant: "Guest".to_owned(),
+            }]
+        );
+        assert_eq!(diff.breaking().count(), 0);
+        query_planner.fetch_types(&fetched(&second), true).unwrap();
+        assert_eq!(
+            query_planner
+                .table_manager
+                .schema("users".to_owned())
+                .unwrap(),
+            user(&second)
+        );
+        assert_eq!(query_planner.message_type_version(&user(&second)), Some(2));
+        query_planner
+            .table_manager
+            .insert_messages("users".to_owned(), [message(2)].into_iter())
+            .unwrap();
+
+        //breaking changes register a new version, stored tables keep theirs until they are
+        //altered
+        let third = status(&["Guest", "Admin"]);
+        let diff = query_planner.fetch_types(&fetched(&third), true).unwrap();
+        assert_eq!(
+            diff.changes,
+            vec![
+                TypeChange::RemovedVariant {
+                    type_name: "Status".to_owned(),
+                    variant: "User".to_owned(),
+                },
+                TypeChange::ReorderedVariants("Status".to_owned()),
+            ]
+        );
+        assert_eq!(
+            query_planner.get_enum_type(&"Status".to_owned()).unwrap(),
+            third
+        );
+        assert_eq!(
+            query_planner
+                .table_manager
+                .schema("users".to_owned())
+                .unwrap(),
+            user(&second)
+        );
+        assert_eq!(query_planner.message_type_version(&user(&second)), Some(2));
+        assert_eq!(query_planner.message_type_version(&user(&third)), Some(3));
+        assert!(matches!(
+            query_planner.insert_messages("users", vec![message(1)]),
+            Err(PlannerError::IncompatibleSchema(_))
+        ));
+
+        let alter = match QueryParser::new()
+            .parse("ALTER TABLE users SET TYPE User USING [User { name, [Status::Admin {}] }];")
+            .unwrap()
+        {
+            ast::Query::AlterTableType {
+                table,
+                type_name,
+                using,
+            } => query_planner.alter_table_type(&table, &type_name, using.as_ref()),
+            _ => unreachable!(),
+        };
+        assert_eq!(alter.unwrap(), 2);
+        assert_eq!(
+            query_planner
+                .table_manager
+                .schema("users".to_owned())
+                .unwrap(),
+            user(&third)
+        );
+
+        //types that are not stored anywhere may change freely
+        query_planner
+            .table_manager
+            .drop_table("users".to_owned())
+            .unwrap();
+        let mut changed_user = user(&third);
+        changed_user.columns[0].column_type = DBType::Int;
+        changed_user.columns.push(Column {
+            column_name: "age".to_owned(),
+            column_type: DBType::Int,
+            dependencies: vec![0],
+        });
+        let diff = query_planner
+            .fetch_types(
+                &[
+                    FetchedType::EnumType(third.clone()),
+                    FetchedType::MessageType(changed_user.clone()),
+                ],
+                true,
+            )
+            .unwrap();
+        assert_eq!(
+            diff.changes,
+            vec![
+                TypeChange::ChangedFieldType {
+                    type_name: "User".to_owned(),
+                    field: "name".to_owned(),
+                },
+                TypeChange::AddedField {
+                    type_name: "User".to_owned(),
+                    field: "age".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            query_planner.get_message_type(&"User".to_owned()).unwrap(),
+            changed_user
+        );
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn catalog_test() {
+        let path = "temp_path27";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let pair_type = MessageType {
+            name: "Pair".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "n".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "v".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![0],
+                },
+            ],
+        };
+        let status_type = EnumType {
+            name: "Status".to_owned(),
+            variants: vec![
+                EnumVariantType {
+                    name: "A".to_owned(),
+                    content: vec![],
+                },
+                EnumVariantType {
+                    name: "B".to_owned(),
+                    content: vec![("x".to_owned(), DBType::Int)],
+                },
+            ],
+        };
+        query_planner
+            .add_message_type("Pair".to_owned(), pair_type.clone())
+            .unwrap();
+        query_planner
+            .add_enum_type("Status".to_owned(), status_type)
+            .unwrap();
+        query_planner
+            .table_manager
+            .create_table("pairs".to_owned(), pair_type)
+            .unwrap();
+
+        let parser = QueryParser::new();
+        let select = |query: &str| match parser.parse(query).unwrap() {
+            ast::Query::Select(select) => select,
+            _ => unreachable!(),
+        };
+        query_planner
+            .create_view(
+                "positive",
+                &select("SELECT n FROM pairs WHERE v > 0;"),
+                false,
+            )
+            .unwrap();
+        query_planner
+            .create_view("copy", &select("SELECT n, v FROM pairs;"), true)
+            .unwrap();
+
+        let run = |query_planner: &QueryPlanner, query: &str| {
+            let logical_plan = query_planner
+                .build_logical_plan(&query_planner.from_parsed_select(&select(query))?)?;
+            let mut operator = query_planner.build_physical_plan(&logical_plan);
+            operator.open()?;
+            Ok::<Vec<Vec<DBValue>>, PlannerError>(
+                operator
+                    .map(|message| message.map(|message| message.fields))
+                    .collect::<Result<Vec<Vec<DBValue>>, ExecutorError>>()?,
+            )
+        };
+        let strings = |rows: &[&[&str]]| {
+            rows.iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|value| DBValue::String(value.to_string()))
+                        .collect()
+                })
+                .collect::<Vec<Vec<DBValue>>>()
+        };
+
+        assert_eq!(
+            query_planner
+                .relations()
+                .iter()
+                .map(|(name, kind, _)| (name.as_str(), *kind))
+                .collect::<Vec<(&str, RelationKind)>>(),
+            vec![
+                ("copy", RelationKind::MaterializedView),
+                ("pairs", RelationKind::Table),
+                ("positive", RelationKind::View),
+            ]
+        );
+        assert_eq!(
+            run(&query_planner, "SELECT name, kind FROM sys.tables;").unwrap(),
+            strings(&[
+                &["copy", "materialized view"],
+                &["pairs", "table"],
+                &["positive", "view"],
+            ])
+        );
+        assert_eq!(
+            run(
+                &query_planner,
+                r#"SELECT name, type_name, dependencies FROM sys.columns WHERE table_name == "pairs";"#
+            )
+            .unwrap(),
+            strings(&[&["n", "Int", ""], &["v", "Int", "n"]])
+        );
+        assert_eq!(
+            run(
+                &query_planner,
+                "SELECT name, kind, definition FROM sys.types WHERE version == 1;"
+            )
+            .unwrap(),
+            strings(&[
+                &[
+                    "Pair",
+                    "message",
+                    "message Pair {\n    n Int;\n    v Int n;\n}"
+                ],
+                &[
+                    "Status",
+                    "enum",
+                    "enum Status {\n    A\n    B { x Int; }\n}"
+                ],
+            ])
+        );
+
+        assert_eq!(
+            query_planner.describe_relation("sys.columns").unwrap().0,
+            RelationKind::System
+        );
+        assert!(matches!(
+            query_planner.describe_relation("missing"),
+            Err(PlannerError::ExecutorError(ExecutorError::TableNotFound))
+        ));
+        assert!(matches!(
+            query_planner.describe_type(&"Missing".to_owned()),
+            Err(PlannerError::UnexistingType(_))
+        ));
+        assert!(matches!(
+            run(&query_planner, "SELECT name FROM sys.missing;"),
+            Err(PlannerError::ExecutorError(ExecutorError::TableNotFound))
+        ));
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn drop_type_test() {
+        let path = "temp_path28";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let column = |name: &str, column_type: DBType| Column {
+            column_name: name.to_owned(),
+            column_type,
+            dependencies: vec![],
+        };
+        let status_type = EnumType {
+            name: "Status".to_owned(),
+            variants: vec![EnumVariantType {
+                name: "Admin".to_owned(),
+                content: vec![],
+            }],
+        };
+        let user_type = MessageType {
+            name: "User".to_owned(),
+            columns: vec![
+                column("name", DBType::String),
+                column("status", DBType::EnumType(status_type.clone())),
+            ],
+        };
+        let wrapper_type = MessageType {
+            name: "Wrapper".to_owned(),
+            columns: vec![column("user", DBType::MessageType(user_type.clone()))],
+        };
+        let other_type = MessageType {
+            name: "Other".to_owned(),
+            columns: vec![column("x", DBType::Int)],
+        };
+        query_planner
+            .add_enum_type("Status".to_owned(), status_type)
+            .unwrap();
+        for message_type in [user_type.clone(), wrapper_type, other_type.clone()] {
+            query_planner
+                .add_message_type(message_type.name.clone(), message_type)
+                .unwrap();
+        }
+        query_planner
+            .table_manager
+            .create_table("users".to_owned(), user_type)
+            .unwrap();
+        query_planner
+            .table_manager
+            .create_table("others".to_owned(), other_type)
+            .unwrap();
+        //views depend on the types of their columns and on the relations they read, ctes are
+        //not relations
+        for query in [
+            "CREATE VIEW statuses AS SELECT status FROM users;",
+            "CREATE VIEW names AS SELECT name FROM users;",
+            "CREATE MATERIALIZED VIEW counted AS SELECT x FROM others WHERE EXISTS (SELECT name FROM names);",
+            "CREATE VIEW shadowed AS WITH users AS (SELECT x FROM others) SELECT x FROM users;",
+        ] {
+            match QueryParser::new().parse(query).unwrap() {
+                ast::Query::CreateView {
+                    name,
+                    select,
+                    materialized,
+                } => query_planner
+                    .create_view(&name, &select, materialized)
+                    .unwrap(),
+                _ => unreachable!(),
+            };
+        }
+
+        let graph = query_planner.dependency_graph();
+        assert_eq!(graph.referents("Wrapper"), &[]);
+        assert_eq!(
+            graph.referents("Other"),
+            &[Referent::Table("others".to_owned())]
+        );
+
+        assert!(matches!(
+            query_planner.drop_type(&"Status".to_owned(), false),
+            Err(PlannerError::TypeInUse(_, referents))
+                if referents == ["message type User", "table users", "view statuses"]
+        ));
+        assert!(matches!(
+            query_planner.drop_type(&"Missing".to_owned(), false),
+            Err(PlannerError::UnexistingType(_))
+        ));
+        assert!(query_planner.get_enum_type(&"Status".to_owned()).is_ok());
+
+        assert!(
+            query_planner
+                .drop_type(&"Wrapper".to_owned(), false)
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut dropped = query_planner
+            .drop_type(&"Status".to_owned(), true)
+            .unwrap()
+            .iter()
+            .map(|referent| referent.to_string())
+            .collect::<Vec<String>>();
+        dropped.sort();
+        assert_eq!(
+            dropped,
+            [
+                "message type User",
+                "table users",
+                "view counted",
+                "view names",
+                "view statuses"
+            ]
+        );
+        assert!(query_planner.describe_type(&"User".to_owned()).is_err());
+        assert!(query_planner.describe_relation("users").is_err());
+        assert!(query_planner.get_view("counted").is_err());
+        assert_eq!(
+            query_planner
+                .relations()
+                .into_iter()
+                .map(|(name, _, _)| name)
+                .collect::<Vec<String>>(),
+            ["others", "shadowed"]
+        );
+
+        utility::cleanup(path);
+    }
+
+    #[test]
+    fn did_you_mean_test() {
+        let path = "temp_path29";
+        utility::cleanup(path);
+
+        let mut query_planner = utility::create_query_planner(path);
+
+        let user_type = MessageType {
+            name: "User".to_owned(),
+            columns: vec![
+                Column {
+                    column_name: "name".to_owned(),
+                    column_type: DBType::String,
+                    dependencies: vec![],
+                },
+                Column {
+                    column_name: "age".to_owned(),
+                    column_type: DBType::Int,
+                    dependencies: vec![],
+                },
+            ],
+        };
+        let status_type = EnumType {
+            name: "Status".to_owned(),
+            variants: vec![
+                EnumVariantType {
+                    name: "Admin".to_owned(),
+                    content: vec![("level".to_owned(), DBType::Int)],
+                },
+                EnumVariantType {
+                    name: "Guest".to_owned(),
+                    content: vec![],
+                },
+            ],
+        };
+        query_planner
+            .add_message_type("User".to_owned(), user_type.clone())
+            .unwrap();
+        query_planner
+            .add_enum_type("Status".to_owned(), status_type)
+            .unwrap();
+        query_planner
+            .table_manager
+            .create_table("users".to_owned(), user_type)
+            .unwrap();
+
+        let parser = QueryParser::new();
+        let suggest = |query: &str| {
+            let query = parser.parse(query).unwrap();
+            let ast::Query::Select(select) = &query else {
+                unreachable!()
+            };
+            let error = query_planner
+                .from_parsed_select(select)
+                .and_then(|raw_plan| query_planner.build_logical_plan(&raw_plan))
+                .unwrap_err();
+            query_planner.did_you_mean(&query, &error)
+        };
+
+        assert_eq!(suggest("SELECT name FROM usres;"), Some("users".to_owned()));
+        assert_eq!(
+            suggest(
+                "WITH adults AS (SELECT name FROM users WHERE age > 17) SELECT name FROM adult;"
+            ),
+            Some("adults".to_owned())
+        );
+        assert_eq!(suggest("SELECT nmae FROM users;"), Some("name".to_owned()));
+        assert_eq!(
+            suggest("SELECT [Status::Admni {1}] AS s FROM users;"),
+            Some("Admin".to_owned())
+        );
+        assert_eq!(suggest("SELECT name FROM accounts;"), None);
+
+        assert_eq!(
+            query_planner.did_you_mean(
+                &ast::Query::DescribeType("Usr".to_owned()),
+                &query_planner.describe_type(&"Usr".to_owned()).unwrap_err()
+            ),
+            Some("User".to_owned())
+        );
+
+        utility::cleanup(path);
+    }
 }
@@ -1,31 +1,184 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::ops::Deref;
 use std::string::String;
+use std::sync::Arc;
 
 use super::super::executor_layer::{
-    expression::*, operator::*, schema::*, table_manager::TableManager,
+    error::ExecutorError,
+    expression::*,
+    function::{FunctionRegistry, ScalarFunction, Signature},
+    operator::*,
+    pattern::{Pattern, PatternKind},
+    physical_plan::PhysicalPlan,
+    schema::*,
+    subquery::{Subquery, ValueSet},
+    table_manager::TableManager,
 };
-use super::super::parser_layer::ast;
+use super::super::parser_layer::{ast, fetch_types::FetchedType, suggestions::closest};
 use super::super::storage_layer::{
     indices::PLANNER_STATE_INDEX,
     utils::{load, save},
 };
 
+use super::dependencies::{DependencyGraph, Referent};
 use super::error::PlannerError;
 use super::logical_plan::*;
 use super::raw_expression::*;
+use super::type_diff::*;
 
 use bincode::{Decode, Encode};
 
+//number of messages buffered by INSERT INTO ... SELECT before they are written
+const INSERT_BATCH_SIZE: usize = 1024;
+
+//outer and inner side of inner == outer in a decorrelated EXISTS
+type SemiJoinKeys = (Expression, Expression);
+
+//recursive ctes fail after this many steps, e.g. UNION ALL over cyclic data
+const RECURSION_LIMIT: usize = 1000;
+
+const SYSTEM_TABLES: [&str; 3] = ["sys.tables", "sys.columns", "sys.types"];
+
+//Tables and views a query reads from, including its ctes and subqueries. Names of ctes in
+//scope are not relations
+fn read_relations(select: &ast::SelectQuery, scope: &[String], relations: &mut Vec<String>) {
+    let mut scope = scope.to_vec();
+    for cte in select.ctes.iter() {
+        if cte.recursive {
+            scope.push(cte.name.clone());
+            read_relations(&cte.query, &scope, relations);
+        } else {
+            read_relations(&cte.query, &scope, relations);
+            scope.push(cte.name.clone());
+        }
+    }
+
+    if !scope.contains(&select.table) && !relations.contains(&select.table) {
+        relations.push(select.table.clone());
+    }
+    for (expression, _) in select.fields.iter() {
+        expression_relations(expression, &scope, relations);
+    }
+    if let Some(condition) = &select.condition {
+        expression_relations(condition, &scope, relations);
+    }
+    for (_, query) in select.compound.iter() {
+        read_relations(query, &scope, relations);
+    }
+}
+
+fn expression_relations(
+    expression: &ast::Expression,
+    scope: &[String],
+    relations: &mut Vec<String>,
+) {
+    let mut visit =
+        |expression: &ast::Expression| expression_relations(expression, scope, relations);
+    match expression {
+        ast::Expression::Literal(_) | ast::Expression::ColumnRef(_) => {}
+        ast::Expression::BinaryOp { op: _, left, right } => {
+            visit(left);
+            visit(right);
+        }
+        ast::Expression::Between { expr, low, high } => {
+            visit(expr);
+            visit(low);
+            visit(high);
+        }
+        ast::Expression::InList { expr, list } => {
+            visit(expr);
+            list.iter().for_each(visit);
+        }
+        ast::Expression::UnaryOp { op: _, expr }
+        | ast::Expression::IsVariant { expr, .. }
+        | ast::Expression::Cast { expr, .. } => visit(expr),
+        ast::Expression::MessageConstructor { fields, .. }
+        | ast::Expression::EnumConstructor { fields, .. }
+        | ast::Expression::FunctionCall { args: fields, .. } => fields.iter().for_each(visit),
+        ast::Expression::InSubquery { expr, query } => {
+            visit(expr);
+            read_relations(query, scope, relations);
+        }
+        ast::Expression::Exists(query) | ast::Expression::ScalarSubquery(query) => {
+            read_relations(query, scope, relations)
+        }
+    }
+}
+
+//tables a query reads from directly and the names of its ctes
+fn query_tables(select: &ast::SelectQuery, tables: &mut Vec<String>, ctes: &mut Vec<String>) {
+    for cte in select.ctes.iter() {
+        ctes.push(cte.name.clone());
+        query_tables(&cte.query, tables, ctes);
+    }
+    tables.push(select.table.clone());
+    for (_, query) in select.compound.iter() {
+        query_tables(query, tables, ctes);
+    }
+}
+
 #[derive(Debug, Encode, Decode, Default)]
 pub struct QueryPlannerState {
     message_types: HashMap<String, MessageType>,
     enum_types: HashMap<String, EnumType>,
+    views: HashMap<String, View>,
+    //replaced versions of message types, oldest first. Tables keep the version they were
+    //created with until they are altered
+    previous_versions: HashMap<String, Vec<MessageType>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelationKind {
+    Table,
+    View,
+    MaterializedView,
+    System,
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelationKind::Table => write!(f, "table"),
+            RelationKind::View => write!(f, "view"),
+            RelationKind::MaterializedView => write!(f, "materialized view"),
+            RelationKind::System => write!(f, "system table"),
+        }
+    }
+}
+
+//Views keep the query they were created with. Plain views are planned in place of every scan,
+//materialized ones are stored in a table with the same name
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct View {
+    pub query: ast::SelectQuery,
+    //columns of the query when the view was created, the query has to keep them
+    pub message_type: MessageType,
+    pub materialized: bool,
 }
 
 pub struct QueryPlanner {
     pub table_manager: TableManager,
     state: QueryPlannerState,
+    //functions are not persisted, built-ins are registered on every start
+    functions: FunctionRegistry,
+    //ctes visible to the plan that is being built, later ones shadow earlier ones and tables
+    ctes: RefCell<Vec<(String, CteBinding)>>,
+}
+
+#[derive(Debug, Clone)]
+enum CteBinding {
+    //referenced once, planned in place of the scan with the ctes that were visible at
+    //the definition
+    Inline {
+        raw_plan: RawPlan,
+        visible: usize,
+    },
+    Materialized {
+        rows: Arc<Vec<Message>>,
+        message_type: MessageType,
+    },
 }
 
 impl QueryPlanner {
@@ -34,10 +187,14 @@ impl QueryPlanner {
             Some(state) => Ok(Self {
                 table_manager,
                 state,
+                functions: FunctionRegistry::default(),
+                ctes: RefCell::new(vec![]),
             }),
             None => Ok(Self {
                 table_manager,
                 state: QueryPlannerState::default(),
+                functions: FunctionRegistry::default(),
+                ctes: RefCell::new(vec![]),
             }),
         }
     }
@@ -61,6 +218,67 @@ impl QueryPlanner {
         Ok(())
     }
 
+    //Returns the number of the new version, the first registered version is 1
+    pub fn replace_message_type(
+        &mut self,
+        type_name: String,
+        message_type: MessageType,
+    ) -> Result<usize, PlannerError> {
+        if !self.state.message_types.contains_key(&type_name) {
+            return Err(PlannerError::UnexistingMessageType(type_name));
+        }
+
+        let version = self.register_version(type_name, message_type);
+        save(
+            self.table_manager.paged_storage.marble(),
+            &self.state,
+            PLANNER_STATE_INDEX,
+        )?;
+
+        Ok(version)
+    }
+
+    //the current definition becomes a previous version if it differs from the new one
+    fn register_version(&mut self, type_name: String, message_type: MessageType) -> usize {
+        if let Some(previous) = self
+            .state
+            .message_types
+            .insert(type_name.clone(), message_type.clone())
+            .filter(|previous| *previous != message_type)
+        {
+            self.state
+                .previous_versions
+                .entry(type_name.clone())
+                .or_default()
+                .push(previous);
+        }
+        self.state
+            .previous_versions
+            .get(&type_name)
+            .map_or(0, |versions| versions.len())
+            + 1
+    }
+
+    //Version of a message type, e.g. of the schema of a table. None if it was never registered
+    pub fn message_type_version(&self, message_type: &MessageType) -> Option<usize> {
+        if self.state.message_types.get(&message_type.name) == Some(message_type) {
+            return Some(
+                self.state
+                    .previous_versions
+                    .get(&message_type.name)
+                    .map_or(0, |versions| versions.len())
+                    + 1,
+            );
+        }
+
+        self.state
+            .previous_versions
+            .get(&message_type.name)?
+            .iter()
+            .rposition(|version| version == message_type)
+            .map(|index| index + 1)
+    }
+
     pub fn get_message_type(&self, type_name: &String) -> Result<MessageType, PlannerError> {
         match self.state.message_types.get(type_name) {
             Some(message_type) => Ok(message_type.clone()),
@@ -94,6 +312,254 @@ impl QueryPlanner {
         }
     }
 
+    fn registered_type(&self, type_name: &String) -> Option<DBType> {
+        match self.state.message_types.get(type_name) {
+            Some(message_type) => Some(DBType::MessageType(message_type.clone())),
+            None => self
+                .state
+                .enum_types
+                .get(type_name)
+                .cloned()
+                .map(DBType::EnumType),
+        }
+    }
+
+    //Registered message and enum types by name, fetched files may refer to them
+    pub fn known_types(&self) -> HashMap<String, DBType> {
+        self.state
+            .message_types
+            .iter()
+            .map(|(name, message_type)| (name.clone(), DBType::MessageType(message_type.clone())))
+            .chain(
+                self.state
+                    .enum_types
+                    .iter()
+                    .map(|(name, enum_type)| (name.clone(), DBType::EnumType(enum_type.clone()))),
+            )
+            .collect()
+    }
+
+    fn fetched_types(types: &[FetchedType]) -> Vec<(String, DBType)> {
+        types
+            .iter()
+            .map(|fetched| match fetched {
+                FetchedType::MessageType(message_type) => (
+                    message_type.name.clone(),
+                    DBType::MessageType(message_type.clone()),
+                ),
+                FetchedType::EnumType(enum_type) => {
+                    (enum_type.name.clone(), DBType::EnumType(enum_type.clone()))
+                }
+            })
+            .collect()
+    }
+
+    //Compares fetched definitions with the registered types of the same names
+    pub fn diff_types(&self, types: &[FetchedType]) -> TypeDiff {
+        let mut diff = TypeDiff::default();
+        for (type_name, new_type) in Self::fetched_types(types) {
+            let changes = match (self.registered_type(&type_name), &new_type) {
+                (None, _) => vec![TypeChange::AddedType(type_name)],
+                (Some(DBType::MessageType(old)), DBType::MessageType(new)) => {
+                    diff_message_types(&old, new)
+                }
+                (Some(DBType::EnumType(old)), DBType::EnumType(new)) => diff_enum_types(&old, new),
+                _ => vec![TypeChange::ChangedKind(type_name)],
+            };
+            diff.changes.extend(changes);
+        }
+        diff
+    }
+
+    //Registers fetched types all at once. Types that exist with other definitions are only
+    //replaced with `replace`, replaced message types keep their previous versions. Tables, views
+    //and other types that use replaced types are updated to the new definitions, except tables
+    //and views affected by breaking changes: they keep their old versions until they are altered
+    pub fn fetch_types(
+        &mut self,
+        types: &[FetchedType],
+        replace: bool,
+    ) -> Result<TypeDiff, PlannerError> {
+        let mut changed = HashMap::<String, (DBType, DBType)>::new();
+        for (type_name, new_type) in Self::fetched_types(types) {
+            if let Some(registered) = self
+                .registered_type(&type_name)
+                .filter(|registered| *registered != new_type)
+            {
+                changed.insert(type_name, (registered, new_type));
+            }
+        }
+
+        if !replace && !changed.is_empty() {
+            let mut names = changed.into_keys().collect::<Vec<String>>();
+            names.sort();
+            return Err(PlannerError::ChangedTypes(names));
+        }
+
+        let diff = self.diff_types(types);
+        let breaking = diff
+            .breaking()
+            .map(|change| change.type_name().to_owned())
+            .collect::<HashSet<String>>();
+        let upgrade = |message_type: &MessageType| {
+            let mut used = HashSet::<String>::new();
+            referenced_types(&DBType::MessageType(message_type.clone()), &mut used);
+            match used.is_disjoint(&breaking) {
+                true => upgrade_message_type(message_type, &changed),
+                false => message_type.clone(),
+            }
+        };
+
+        for (type_name, new_type) in Self::fetched_types(types) {
+            match new_type {
+                DBType::MessageType(message_type) => {
+                    self.state.enum_types.remove(&type_name);
+                    self.register_version(type_name, message_type);
+                }
+                DBType::EnumType(enum_type) => {
+                    self.state.message_types.remove(&type_name);
+                    self.state.enum_types.insert(type_name, enum_type);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        //registered types that were not fetched may still contain replaced ones
+        let mut names = self
+            .state
+            .message_types
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        names.sort();
+        for type_name in names {
+            let upgraded = upgrade_message_type(&self.state.message_types[&type_name], &changed);
+            self.register_version(type_name, upgraded);
+        }
+        for enum_type in self.state.enum_types.values_mut() {
+            if let DBType::EnumType(upgraded) =
+                upgrade_type(&DBType::EnumType(enum_type.clone()), &changed)
+            {
+                *enum_type = upgraded;
+            }
+        }
+        for view in self.state.views.values_mut() {
+            view.message_type = upgrade(&view.message_type);
+        }
+        self.table_manager.update_schemas(upgrade)?;
+
+        save(
+            self.table_manager.paged_storage.marble(),
+            &self.state,
+            PLANNER_STATE_INDEX,
+        )?;
+
+        Ok(diff)
+    }
+
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for message_type in self.state.message_types.values() {
+            graph.add_message_type(message_type);
+        }
+        for enum_type in self.state.enum_types.values() {
+            graph.add_enum_type(enum_type);
+        }
+        for (name, kind, message_type) in self.relations() {
+            match self.state.views.get(&name) {
+                Some(view) => {
+                    let mut relations = vec![];
+                    read_relations(&view.query, &[], &mut relations);
+                    graph.add_view(&name, &message_type, &relations);
+                }
+                None if kind == RelationKind::Table => graph.add_table(&name, &message_type),
+                None => {}
+            }
+        }
+        graph
+    }
+
+    //Without `cascade` the type must not be used by anything. Otherwise everything that uses it,
+    //directly or through other types, is dropped as well and returned
+    pub fn drop_type(
+        &mut self,
+        type_name: &String,
+        cascade: bool,
+    ) -> Result<Vec<Referent>, PlannerError> {
+        self.describe_type(type_name)?;
+
+        let graph = self.dependency_graph();
+        if !cascade && !graph.referents(type_name).is_empty() {
+            let mut referents = graph
+                .referents(type_name)
+                .iter()
+                .map(|referent| referent.to_string())
+                .collect::<Vec<String>>();
+            referents.sort();
+            return Err(PlannerError::TypeInUse(type_name.clone(), referents));
+        }
+
+        let dropped = graph.all_referents(type_name);
+        for referent in dropped.iter() {
+            match referent {
+                Referent::MessageType(name) => {
+                    self.state.message_types.remove(name);
+                    self.state.previous_versions.remove(name);
+                }
+                Referent::EnumType(name) => {
+                    self.state.enum_types.remove(name);
+                }
+                Referent::Table(name) => self.table_manager.drop_table(name.clone())?,
+                Referent::View(name) => self.drop_view(name)?,
+            }
+        }
+
+        self.state.message_types.remove(type_name);
+        self.state.previous_versions.remove(type_name);
+        self.state.enum_types.remove(type_name);
+        save(
+            self.table_manager.paged_storage.marble(),
+            &self.state,
+            PLANNER_STATE_INDEX,
+        )?;
+
+        Ok(dropped)
+    }
+
+    //Registers a deterministic function callable from queries, arguments of the call are
+    //type checked against the declared signature
+    pub fn register_function<F>(
+        &mut self,
+        name: &str,
+        arguments: Vec<DBType>,
+        return_type: DBType,
+        body: F,
+    ) -> Result<(), PlannerError>
+    where
+        F: Fn(&[DBValue]) -> Result<DBValue, ExecutorError> + Send + Sync + 'static,
+    {
+        self.register_scalar_function(ScalarFunction::new(
+            name,
+            Signature::Exact {
+                arguments,
+                return_type,
+            },
+            body,
+        ))
+    }
+
+    pub fn register_scalar_function(
+        &mut self,
+        function: ScalarFunction,
+    ) -> Result<(), PlannerError> {
+        if self.functions.contains(&function.name) {
+            return Err(PlannerError::DuplicateFunction(function.name));
+        }
+
+        self.functions.register(function);
+        Ok(())
+    }
+
     pub fn from_parsed_value(&self, parsed_value: &ast::Value) -> Result<DBValue, PlannerError> {
         match parsed_value {
             ast::Value::Int(i) => Ok(DBValue::Int(*i)),
@@ -105,10 +571,18 @@ impl QueryPlanner {
 
                 let try_convert_fields: Result<Vec<DBValue>, PlannerError> =
                     m.values.iter().map(|v| self.from_parsed_value(v)).collect();
+                let fields =
+                    Self::order_named_fields(&message_type, try_convert_fields?, &m.field_names)?;
 
                 let message = Message {
                     type_name: Some(m.type_name.clone()),
-                    fields: try_convert_fields?,
+                    fields: self.coerce_literals(
+                        fields,
+                        message_type
+                            .columns
+                            .iter()
+                            .map(|column| &column.column_type),
+                    )?,
                 };
 
                 if !message_type.match_message(&message) {
@@ -143,7 +617,13 @@ impl QueryPlanner {
                 let enum_value = EnumValue {
                     type_name: Some(e.type_name.clone()),
                     choice,
-                    values: try_convert_fields?,
+                    values: self.coerce_literals(
+                        try_convert_fields?,
+                        enum_type.variants[choice]
+                            .content
+                            .iter()
+                            .map(|(_, db_type)| db_type),
+                    )?,
                 };
 
                 if !enum_type.match_enum(&enum_value) {
@@ -168,17 +648,39 @@ impl QueryPlanner {
                 let raw_left = self.from_parsed_expression(&left)?;
                 let raw_right = self.from_parsed_expression(&right)?;
 
+                let pattern_kind = match op {
+                    ast::BinaryOperator::Like => Some(PatternKind::Like),
+                    ast::BinaryOperator::ILike => Some(PatternKind::ILike),
+                    ast::BinaryOperator::RegexMatch => Some(PatternKind::Regex),
+                    _ => None,
+                };
+
+                if let Some(kind) = pattern_kind {
+                    return Ok(RawExpression::PatternMatch {
+                        expr: Box::new(raw_left),
+                        pattern: Box::new(raw_right),
+                        kind,
+                    });
+                }
+
                 let binop = match op {
                     ast::BinaryOperator::Add => BinaryOperator::Add,
                     ast::BinaryOperator::Subtract => BinaryOperator::Subtract,
                     ast::BinaryOperator::Multiply => BinaryOperator::Multiply,
                     ast::BinaryOperator::Divide => BinaryOperator::Divide,
+                    ast::BinaryOperator::Modulo => BinaryOperator::Modulo,
+                    ast::BinaryOperator::Concat => BinaryOperator::Concat,
                     ast::BinaryOperator::Equals => BinaryOperator::Equals,
                     ast::BinaryOperator::NotEquals => BinaryOperator::NotEquals,
                     ast::BinaryOperator::LessThan => BinaryOperator::LessThan,
                     ast::BinaryOperator::GreaterThan => BinaryOperator::GreaterThan,
+                    ast::BinaryOperator::LessOrEqual => BinaryOperator::LessOrEqual,
+                    ast::BinaryOperator::GreaterOrEqual => BinaryOperator::GreaterOrEqual,
                     ast::BinaryOperator::And => BinaryOperator::And,
                     ast::BinaryOperator::Or => BinaryOperator::Or,
+                    ast::BinaryOperator::Like
+                    | ast::BinaryOperator::ILike
+                    | ast::BinaryOperator::RegexMatch => unreachable!(),
                 };
 
                 Ok(RawExpression::BinaryOp {
@@ -187,6 +689,112 @@ impl QueryPlanner {
                     right: Box::new(raw_right),
                 })
             }
+            //BETWEEN is rewritten as low <= expr && expr <= high
+            ast::Expression::Between { expr, low, high } => {
+                let raw_expr = self.from_parsed_expression(expr)?;
+                let raw_low = self.from_parsed_expression(low)?;
+                let raw_high = self.from_parsed_expression(high)?;
+
+                Ok(RawExpression::BinaryOp {
+                    op: BinaryOperator::And,
+                    left: Box::new(RawExpression::BinaryOp {
+                        op: BinaryOperator::LessOrEqual,
+                        left: Box::new(raw_low),
+                        right: Box::new(raw_expr.clone()),
+                    }),
+                    right: Box::new(RawExpression::BinaryOp {
+                        op: BinaryOperator::LessOrEqual,
+                        left: Box::new(raw_expr),
+                        right: Box::new(raw_high),
+                    }),
+                })
+            }
+            ast::Expression::InList { expr, list } => {
+                let raw_expr = self.from_parsed_expression(expr)?;
+                let raw_list: Result<Vec<RawExpression>, PlannerError> = list
+                    .iter()
+                    .map(|item| self.from_parsed_expression(item))
+                    .collect();
+
+                Ok(RawExpression::InList {
+                    expr: Box::new(raw_expr),
+                    list: raw_list?,
+                })
+            }
+            ast::Expression::InSubquery { expr, query } => Ok(RawExpression::InSubquery {
+                expr: Box::new(self.from_parsed_expression(expr)?),
+                plan: Box::new(self.from_parsed_select(query)?),
+            }),
+            ast::Expression::Exists(query) => Ok(RawExpression::Exists(Box::new(
+                self.from_parsed_select(query)?,
+            ))),
+            ast::Expression::ScalarSubquery(query) => Ok(RawExpression::ScalarSubquery(Box::new(
+                self.from_parsed_select(query)?,
+            ))),
+            ast::Expression::IsVariant {
+                expr,
+                type_name,
+                variant_name,
+                negated,
+            } => {
+                let is_variant = RawExpression::UnaryOp {
+                    op: RawUnaryOperator::IsVariant(type_name.clone(), variant_name.clone()),
+                    expr: Box::new(self.from_parsed_expression(expr)?),
+                };
+
+                if *negated {
+                    return Ok(RawExpression::UnaryOp {
+                        op: RawUnaryOperator::Not,
+                        expr: Box::new(is_variant),
+                    });
+                }
+                Ok(is_variant)
+            }
+            ast::Expression::MessageConstructor {
+                type_name,
+                fields,
+                field_names,
+            } => {
+                let message_type = self.get_message_type(type_name)?;
+
+                Ok(RawExpression::MessageConstructor {
+                    type_name: type_name.clone(),
+                    fields: Self::order_named_fields(
+                        &message_type,
+                        self.convert_parsed_expressions(fields)?,
+                        field_names,
+                    )?,
+                })
+            }
+            ast::Expression::EnumConstructor {
+                type_name,
+                variant_name,
+                fields,
+            } => Ok(RawExpression::EnumConstructor {
+                type_name: type_name.clone(),
+                variant_name: variant_name.clone(),
+                fields: self.convert_parsed_expressions(fields)?,
+            }),
+            ast::Expression::FunctionCall { name, args } => {
+                let raw_args: Result<Vec<RawExpression>, PlannerError> = args
+                    .iter()
+                    .map(|arg| self.from_parsed_expression(arg))
+                    .collect();
+
+                Ok(RawExpression::FunctionCall {
+                    name: name.clone(),
+                    args: raw_args?,
+                })
+            }
+            ast::Expression::Cast { expr, type_name } => {
+                let db_type = Self::primitive_type(type_name)
+                    .ok_or_else(|| PlannerError::InvalidCast(type_name.clone()))?;
+
+                Ok(RawExpression::Cast {
+                    expr: Box::new(self.from_parsed_expression(expr)?),
+                    db_type,
+                })
+            }
             ast::Expression::UnaryOp { op, expr } => {
                 let unop = match op {
                     ast::UnaryOperator::Negate => RawUnaryOperator::Negate,
@@ -194,45 +802,40 @@ impl QueryPlanner {
                     ast::UnaryOperator::MessageField(field) => {
                         RawUnaryOperator::MessageField(field.clone())
                     }
-                    ast::UnaryOperator::EnumMatch(cases) => {
-                        if cases.is_empty() {
+                    ast::UnaryOperator::EnumMatch(arms) => {
+                        if arms.is_empty() {
                             return Err(PlannerError::EmptyMatchCases);
                         };
 
-                        let enum_type_name = cases[0].0.split("::").next().unwrap();
-                        let enum_type = self.get_enum_type(&enum_type_name.to_owned())?;
-
-                        if enum_type.variants.len() != cases.len() {
-                            return Err(PlannerError::IllFormedMatchStatement);
-                        }
-
-                        let mut map = HashMap::<String, usize>::new();
-
-                        for (i, case) in cases.iter().enumerate() {
-                            let mut tokens = case.0.split("::");
-
-                            let type_name = tokens.next().unwrap().to_owned();
-                            if type_name != enum_type_name {
-                                return Err(PlannerError::IllFormedMatchStatement);
-                            }
-
-                            let variant_name = tokens.next().unwrap().to_owned();
-                            if map.contains_key(&variant_name) {
-                                return Err(PlannerError::IllFormedMatchStatement);
-                            }
-                            map.insert(variant_name, i);
-                        }
-
-                        let mut match_cases = Vec::<RawExpression>::new();
-                        for variant in enum_type.variants.iter() {
-                            if let Some(index) = map.get(&variant.name) {
-                                match_cases.push(self.from_parsed_expression(&cases[*index].1)?);
-                            } else {
-                                return Err(PlannerError::IllFormedMatchStatement);
-                            }
+                        //arms are checked against the enum type once it is known
+                        let mut raw_arms = Vec::<RawMatchArm>::new();
+                        for arm in arms.iter() {
+                            let (variant, bindings) = match &arm.pattern {
+                                ast::MatchPattern::Wildcard => (None, None),
+                                ast::MatchPattern::Variant {
+                                    type_name,
+                                    variant_name,
+                                    bindings,
+                                } => (
+                                    Some((type_name.clone(), variant_name.clone())),
+                                    bindings.clone(),
+                                ),
+                            };
+
+                            let guard = match &arm.guard {
+                                Some(guard) => Some(self.from_parsed_expression(guard)?),
+                                None => None,
+                            };
+
+                            raw_arms.push(RawMatchArm {
+                                variant,
+                                bindings,
+                                guard,
+                                expression: self.from_parsed_expression(&arm.expression)?,
+                            });
                         }
 
-                        RawUnaryOperator::EnumMatch(match_cases)
+                        RawUnaryOperator::EnumMatch(raw_arms)
                     }
                 };
 
@@ -246,6 +849,62 @@ impl QueryPlanner {
         }
     }
 
+    //[Type { field: value, ... }] fields are put in the order of the type columns, positional
+    //fields are returned as is
+    fn order_named_fields<T>(
+        message_type: &MessageType,
+        values: Vec<T>,
+        field_names: &Option<Vec<String>>,
+    ) -> Result<Vec<T>, PlannerError> {
+        let field_names = match field_names {
+            Some(field_names) => field_names,
+            None => return Ok(values),
+        };
+
+        let mut named_values = HashMap::<&String, T>::new();
+        for (name, value) in field_names.iter().zip(values) {
+            if !message_type
+                .columns
+                .iter()
+                .any(|column| column.column_name == *name)
+            {
+                return Err(PlannerError::UnknownField(
+                    message_type.name.clone(),
+                    name.clone(),
+                ));
+            }
+            if named_values.insert(name, value).is_some() {
+                return Err(PlannerError::DuplicateField(
+                    message_type.name.clone(),
+                    name.clone(),
+                ));
+            }
+        }
+
+        message_type
+            .columns
+            .iter()
+            .map(|column| {
+                named_values.remove(&column.column_name).ok_or_else(|| {
+                    PlannerError::MissingField(
+                        message_type.name.clone(),
+                        column.column_name.clone(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn convert_parsed_expressions(
+        &self,
+        expressions: &[ast::Expression],
+    ) -> Result<Vec<RawExpression>, PlannerError> {
+        expressions
+            .iter()
+            .map(|expression| self.from_parsed_expression(expression))
+            .collect()
+    }
+
     pub fn build_expression(
         &self,
         raw_expression: &RawExpression,
@@ -259,6 +918,26 @@ impl QueryPlanner {
             RawExpression::BinaryOp { op, left, right } => {
                 let left_expression = self.build_expression(left.deref(), message_type)?;
                 let right_expression = self.build_expression(right.deref(), message_type)?;
+
+                let left_type = self.deduce_expression_type(&left_expression, message_type)?;
+                let right_type = self.deduce_expression_type(&right_expression, message_type)?;
+                let left_operand_type =
+                    Self::operand_type(&left_expression, &left_type, &right_type);
+                let right_operand_type =
+                    Self::operand_type(&right_expression, &right_type, &left_type);
+                let exact = op.is_comparison()
+                    && Self::mixed_integers(&left_operand_type, &right_operand_type);
+                let (left_expression, right_expression) = match left_operand_type
+                    .common_type(&right_operand_type)
+                    .filter(|_| !exact)
+                {
+                    Some(common_type) => (
+                        Self::coerce(left_expression, &left_type, &common_type)?,
+                        Self::coerce(right_expression, &right_type, &common_type)?,
+                    ),
+                    None => (left_expression, right_expression),
+                };
+
                 Ok(Expression::BinaryOp {
                     op: *op,
                     left: Box::new(left_expression),
@@ -292,25 +971,38 @@ impl QueryPlanner {
                             Err(PlannerError::WrongOperandTypes)
                         }
                     }
-                    RawUnaryOperator::EnumMatch(raw_expressions) => {
+                    RawUnaryOperator::IsVariant(type_name, variant_name) => {
                         let deduced_type =
                             self.deduce_expression_type(&expression, message_type)?;
-                        if let DBType::EnumType(enum_type) = deduced_type {
-                            if raw_expressions.len() != enum_type.variants.len() {
-                                return Err(PlannerError::WrongOperandTypes);
-                            }
-
-                            let result: Result<Vec<Expression>, PlannerError> = raw_expressions
-                                .iter()
-                                .zip(enum_type.variants.iter())
-                                .map(|(raw_expression, variant)| {
-                                    let variant_message_type: MessageType = variant.into();
-                                    self.build_expression(raw_expression, &variant_message_type)
+                        match deduced_type {
+                            DBType::EnumType(enum_type) if enum_type.name == *type_name => {
+                                let choice = enum_type
+                                    .variants
+                                    .iter()
+                                    .position(|variant| variant.name == *variant_name)
+                                    .ok_or_else(|| {
+                                        PlannerError::EnumVariantNotFound(
+                                            type_name.clone(),
+                                            variant_name.clone(),
+                                        )
+                                    })?;
+
+                                Ok(Expression::UnaryOp {
+                                    op: UnaryOperator::IsVariant(choice),
+                                    expr: Box::new(expression),
                                 })
-                                .collect();
-
+                            }
+                            _ => Err(PlannerError::WrongOperandTypes),
+                        }
+                    }
+                    RawUnaryOperator::EnumMatch(raw_arms) => {
+                        let deduced_type =
+                            self.deduce_expression_type(&expression, message_type)?;
+                        if let DBType::EnumType(enum_type) = deduced_type {
                             Ok(Expression::UnaryOp {
-                                op: UnaryOperator::EnumMatch(result?),
+                                op: UnaryOperator::EnumMatch(
+                                    self.build_match_arms(raw_arms, &enum_type)?,
+                                ),
                                 expr: Box::new(expression),
                             })
                         } else {
@@ -319,51 +1011,831 @@ impl QueryPlanner {
                     }
                 }
             }
-        }
-    }
+            RawExpression::InList { expr, list } => {
+                let expression = self.build_expression(expr.deref(), message_type)?;
+                let result: Result<Vec<Expression>, PlannerError> = list
+                    .iter()
+                    .map(|raw_expression| self.build_expression(raw_expression, message_type))
+                    .collect();
+                let list = result?;
+
+                //every item is coerced to the widest numeric type among the list and the value,
+                //unless no item needs a conversion other than between Int and UInt
+                let expression_type = self.deduce_expression_type(&expression, message_type)?;
+                let mut types = vec![];
+                let mut common_type = Some(expression_type.clone());
+                let mut exact = true;
+                for item in list.iter() {
+                    let item_type = self.deduce_expression_type(item, message_type)?;
+                    let operand_type = Self::operand_type(item, &item_type, &expression_type);
+                    common_type = common_type.and_then(|t| t.common_type(&operand_type));
+                    exact &= item_type == expression_type
+                        || Self::mixed_integers(&item_type, &expression_type);
+                    types.push(item_type);
+                }
+                if exact {
+                    common_type = None;
+                }
 
-    //returns Some(column_index) only if expression is a chain of unary operators on top of a
-    //column ref
-    fn get_leaf_ref(expression: &Expression) -> Option<usize> {
-        match expression {
-            Expression::ColumnRef(column_index) => Some(*column_index),
-            Expression::UnaryOp { op: _, expr } => Self::get_leaf_ref(expr.deref()),
-            _ => None,
-        }
-    }
+                match common_type {
+                    Some(common_type) => Ok(Expression::InList {
+                        expr: Box::new(Self::coerce(expression, &expression_type, &common_type)?),
+                        list: list
+                            .into_iter()
+                            .zip(types.iter())
+                            .map(|(item, item_type)| Self::coerce(item, item_type, &common_type))
+                            .collect::<Result<Vec<Expression>, PlannerError>>()?,
+                    }),
+                    None => Ok(Expression::InList {
+                        expr: Box::new(expression),
+                        list,
+                    }),
+                }
+            }
+            RawExpression::Cast { expr, db_type } => {
+                let expression = self.build_expression(expr.deref(), message_type)?;
+                let source_type = self.deduce_expression_type(&expression, message_type)?;
 
-    fn is_complex_type(db_type: &DBType) -> bool {
-        matches!(db_type, DBType::MessageType(_) | DBType::EnumType(_))
-    }
+                if Self::is_complex_type(&source_type) || Self::is_complex_type(db_type) {
+                    return Err(PlannerError::InvalidCast(format!("{:?}", db_type)));
+                }
 
-    pub fn get_column_index(
-        column_name: &String,
-        message_type: &MessageType,
-    ) -> Result<usize, PlannerError> {
-        for i in 0usize..message_type.columns.len() {
-            if column_name == &message_type.columns[i].column_name {
-                return Ok(i);
+                Self::coerce(expression, &source_type, db_type)
             }
-        }
-        Err(PlannerError::ColumnNotFound(column_name.clone()))
-    }
+            RawExpression::MessageConstructor { type_name, fields } => {
+                let constructed_type = self.get_message_type(type_name)?;
+                let fields = self.build_constructor_fields(
+                    fields,
+                    constructed_type
+                        .columns
+                        .iter()
+                        .map(|column| &column.column_type),
+                    type_name,
+                    message_type,
+                )?;
 
-    pub fn build_physical_plan(
-        &self,
-        logical_plan: &LogicalPlan,
-    ) -> Box<dyn PhysicalOperator + '_> {
-        match logical_plan {
-            LogicalPlan::Scan {
-                table_name,
-                message_type: _,
-            } => Box::new(TableScan {
-                table_manager: &self.table_manager,
-                table_name: table_name.clone(),
-                iterator: None,
-            }),
-            LogicalPlan::Filter {
-                expression,
-                source,
+                Ok(Expression::MessageConstructor {
+                    message_type: constructed_type,
+                    fields,
+                })
+            }
+            RawExpression::EnumConstructor {
+                type_name,
+                variant_name,
+                fields,
+            } => {
+                let enum_type = self.get_enum_type(type_name)?;
+                let choice = enum_type
+                    .variants
+                    .iter()
+                    .position(|variant| variant.name == *variant_name)
+                    .ok_or_else(|| {
+                        PlannerError::EnumVariantNotFound(type_name.clone(), variant_name.clone())
+                    })?;
+                let fields = self.build_constructor_fields(
+                    fields,
+                    enum_type.variants[choice]
+                        .content
+                        .iter()
+                        .map(|(_, db_type)| db_type),
+                    type_name,
+                    message_type,
+                )?;
+
+                Ok(Expression::EnumConstructor {
+                    enum_type,
+                    choice,
+                    fields,
+                })
+            }
+            RawExpression::FunctionCall { name, args } => {
+                let function = self
+                    .functions
+                    .get(name)
+                    .ok_or_else(|| PlannerError::UnknownFunction(name.clone()))?
+                    .clone();
+
+                let args = args
+                    .iter()
+                    .map(|arg| self.build_expression(arg, message_type))
+                    .collect::<Result<Vec<Expression>, PlannerError>>()?;
+                let arg_types = args
+                    .iter()
+                    .map(|arg| self.deduce_expression_type(arg, message_type))
+                    .collect::<Result<Vec<DBType>, PlannerError>>()?;
+
+                let (parameter_types, _) = function
+                    .signature
+                    .resolve(&arg_types)
+                    .ok_or_else(|| PlannerError::WrongFunctionArguments(name.clone()))?;
+
+                let mut coerced_args = Vec::<Expression>::with_capacity(args.len());
+                for ((arg, arg_type), parameter_type) in args
+                    .into_iter()
+                    .zip(arg_types.iter())
+                    .zip(parameter_types.iter())
+                {
+                    //arguments may only be widened, except for numeric literals that are
+                    //converted with a check
+                    let is_numeric_literal =
+                        matches!(arg, Expression::Literal(_)) && arg_type.numeric_rank().is_some();
+                    if arg_type.common_type(parameter_type).as_ref() != Some(parameter_type)
+                        && !(is_numeric_literal && parameter_type.numeric_rank().is_some())
+                    {
+                        return Err(PlannerError::WrongFunctionArguments(name.clone()));
+                    }
+                    coerced_args.push(Self::coerce(arg, arg_type, parameter_type)?);
+                }
+
+                let expression = Expression::FunctionCall {
+                    function,
+                    args: coerced_args,
+                };
+
+                Ok(Self::fold_constant_call(expression))
+            }
+            RawExpression::Exists(plan) => {
+                let (subquery, scope, semi_join) =
+                    self.build_subquery(plan, message_type, false)?;
+
+                if subquery.filter.is_none() {
+                    return Ok(Expression::Literal(DBValue::Bool(
+                        !subquery.rows.is_empty(),
+                    )));
+                }
+
+                //EXISTS (SELECT ... WHERE inner == outer) becomes outer IN (SELECT inner ...)
+                if let Some((outer_key, inner_key)) = semi_join {
+                    let outer_type = self.deduce_expression_type(&outer_key, message_type)?;
+                    let inner_type = self.deduce_expression_type(&inner_key, &scope)?;
+
+                    if let Some(common_type) = outer_type.common_type(&inner_type) {
+                        let inner_key = Self::coerce(inner_key, &inner_type, &common_type)?;
+                        return Ok(Expression::InSet {
+                            expr: Box::new(Self::coerce(outer_key, &outer_type, &common_type)?),
+                            values: Self::collect_values(&subquery.rows, &inner_key)?,
+                        });
+                    }
+                }
+
+                Ok(Expression::Exists(Box::new(subquery)))
+            }
+            RawExpression::InSubquery { expr, plan } => {
+                let expression = self.build_expression(expr.deref(), message_type)?;
+                let expression_type = self.deduce_expression_type(&expression, message_type)?;
+                let (mut subquery, scope, _) = self.build_subquery(plan, message_type, true)?;
+                let projection = subquery.projection.take().unwrap();
+                let projection_type = self.deduce_expression_type(&projection, &scope)?;
+
+                let common_type = expression_type
+                    .common_type(&projection_type)
+                    .ok_or(PlannerError::WrongOperandTypes)?;
+                let expression = Self::coerce(expression, &expression_type, &common_type)?;
+                let projection = Self::coerce(projection, &projection_type, &common_type)?;
+
+                if subquery.outer_refs.is_empty() {
+                    return Ok(Expression::InSet {
+                        expr: Box::new(expression),
+                        values: Self::collect_values(&subquery.rows, &projection)?,
+                    });
+                }
+
+                subquery.projection = Some(projection);
+                Ok(Expression::InSubquery {
+                    expr: Box::new(expression),
+                    subquery: Box::new(subquery),
+                })
+            }
+            RawExpression::ScalarSubquery(plan) => {
+                let (subquery, scope, _) = self.build_subquery(plan, message_type, true)?;
+                let db_type =
+                    self.deduce_expression_type(subquery.projection.as_ref().unwrap(), &scope)?;
+
+                //a failing subquery is kept so the error is raised only if it is evaluated
+                if subquery.outer_refs.is_empty() {
+                    let no_outer = Message {
+                        type_name: None,
+                        fields: vec![],
+                    };
+                    if let Ok(value) = subquery.scalar(&no_outer) {
+                        return Ok(Expression::Literal(value));
+                    }
+                }
+
+                Ok(Expression::ScalarSubquery {
+                    subquery: Box::new(subquery),
+                    db_type,
+                })
+            }
+            RawExpression::PatternMatch {
+                expr,
+                pattern,
+                kind,
+            } => {
+                let expression = self.build_expression(expr.deref(), message_type)?;
+                if self.deduce_expression_type(&expression, message_type)? != DBType::String {
+                    return Err(PlannerError::WrongOperandTypes);
+                }
+
+                //constant patterns such as concat("Jo", "%") are folded by now
+                let source = match self.build_expression(pattern.deref(), message_type)? {
+                    Expression::Literal(DBValue::String(source)) => source,
+                    _ => {
+                        return Err(PlannerError::InvalidPattern(
+                            "pattern must be a constant string".to_owned(),
+                        ));
+                    }
+                };
+
+                let pattern = Pattern::new(&source, *kind)
+                    .map_err(|e| PlannerError::InvalidPattern(e.to_string()))?;
+
+                Ok(Expression::PatternMatch {
+                    expr: Box::new(expression),
+                    pattern,
+                })
+            }
+        }
+    }
+
+    //returns Some(column_index) only if expression is a chain of unary operators on top of a
+    //column ref
+    fn get_leaf_ref(expression: &Expression) -> Option<usize> {
+        match expression {
+            Expression::ColumnRef(column_index) => Some(*column_index),
+            Expression::UnaryOp { op: _, expr } => Self::get_leaf_ref(expr.deref()),
+            _ => None,
+        }
+    }
+
+    //Subqueries are planned from their source, condition and selected expressions. Columns of
+    //the source come first in the scope and shadow the outer columns with the same name.
+    //Conjuncts of the condition that do not use outer columns are applied once while the rows are
+    //read, the rest is evaluated for every outer row. A single correlated inner == outer conjunct
+    //is also returned as (outer key, inner key) so EXISTS can be turned into a semi-join, if the
+    //outer key uses only outer columns
+    fn build_subquery(
+        &self,
+        raw_plan: &RawPlan,
+        outer: &MessageType,
+        projected: bool,
+    ) -> Result<(Subquery, MessageType, Option<SemiJoinKeys>), PlannerError> {
+        let (raw_expressions, source) = match raw_plan {
+            RawPlan::With { ctes, source } => {
+                return self.with_ctes(ctes, source, |planner| {
+                    planner.build_subquery(source, outer, projected)
+                });
+            }
+            RawPlan::Projection {
+                raw_expressions,
+                source,
+            } => (Some(raw_expressions), source.deref()),
+            //DISTINCT and set operations are read as a whole, without outer columns
+            _ => (None, raw_plan),
+        };
+        let (raw_condition, source) = match (raw_expressions, source) {
+            (
+                Some(_),
+                RawPlan::Filter {
+                    raw_expression,
+                    source,
+                },
+            ) => (Some(raw_expression), source.deref()),
+            _ => (None, source),
+        };
+
+        let mut logical_plan = self.build_logical_plan(source)?;
+        let inner_type = logical_plan.get_message_type().clone();
+        let inner_len = inner_type.columns.len();
+        if projected && raw_expressions.map_or(inner_len, |raw| raw.len()) != 1 {
+            return Err(PlannerError::InvalidSubquery(
+                "subquery must select exactly one column".to_owned(),
+            ));
+        }
+        let scope = MessageType {
+            name: inner_type.name.clone(),
+            columns: inner_type
+                .columns
+                .iter()
+                .cloned()
+                .chain(outer.columns.iter().map(|column| {
+                    Column {
+                        column_name: column.column_name.clone(),
+                        column_type: column.column_type.clone(),
+                        dependencies: column
+                            .dependencies
+                            .iter()
+                            .map(|dep| dep + inner_len)
+                            .collect(),
+                    }
+                }))
+                .collect(),
+        };
+        let is_correlated = |expression: &Expression| {
+            let mut refs = vec![];
+            Self::column_refs(expression, &mut refs);
+            refs.iter().any(|index| *index >= inner_len)
+        };
+        //uses outer columns and nothing else, so it can be evaluated on the outer row alone
+        let is_outer_only = |expression: &Expression| {
+            let mut refs = vec![];
+            Self::column_refs(expression, &mut refs);
+            !refs.is_empty() && refs.iter().all(|index| *index >= inner_len)
+        };
+
+        let mut conjuncts = vec![];
+        if let Some(raw_condition) = raw_condition {
+            Self::split_conjuncts(raw_condition, &mut conjuncts);
+        }
+
+        let mut uncorrelated = vec![];
+        let mut correlated = vec![];
+        let mut raw_correlated = vec![];
+        for raw_conjunct in conjuncts {
+            let conjunct = self.build_expression(raw_conjunct, &scope)?;
+            if self.deduce_expression_type(&conjunct, &scope)? != DBType::Bool {
+                return Err(PlannerError::WrongOperandTypes);
+            }
+
+            if is_correlated(&conjunct) {
+                correlated.push(conjunct);
+                raw_correlated.push(raw_conjunct);
+            } else {
+                uncorrelated.push(conjunct);
+            }
+        }
+
+        //EXISTS only checks for rows, the selected expressions are still checked
+        let mut projections = match raw_expressions {
+            Some(raw_expressions) => raw_expressions
+                .iter()
+                .map(|(_, raw_expression)| self.build_expression(raw_expression, &scope))
+                .collect::<Result<Vec<Expression>, PlannerError>>()?,
+            None => (0..inner_len).map(Expression::ColumnRef).collect(),
+        };
+        let projection = if projected { projections.pop() } else { None };
+
+        let mut semi_join = None;
+        if let [
+            RawExpression::BinaryOp {
+                op: BinaryOperator::Equals,
+                left,
+                right,
+            },
+        ] = raw_correlated.as_slice()
+        {
+            let left_expression = self.build_expression(left, &scope)?;
+            let right_expression = self.build_expression(right, &scope)?;
+            //a key mixing inner and outer columns stays a correlated EXISTS
+            semi_join = match (
+                is_correlated(&left_expression),
+                is_outer_only(&left_expression),
+                is_correlated(&right_expression),
+                is_outer_only(&right_expression),
+            ) {
+                (false, _, true, true) => {
+                    Some((self.build_expression(right, outer)?, left_expression))
+                }
+                (true, true, false, _) => {
+                    Some((self.build_expression(left, outer)?, right_expression))
+                }
+                _ => None,
+            };
+        }
+
+        let mut outer_refs = vec![];
+        for expression in correlated.iter().chain(projection.iter()) {
+            Self::column_refs(expression, &mut outer_refs);
+        }
+        outer_refs.retain(|index| *index >= inner_len);
+        outer_refs.iter_mut().for_each(|index| *index -= inner_len);
+        outer_refs.sort();
+        outer_refs.dedup();
+
+        if let Some(expression) = Self::conjunction(uncorrelated) {
+            logical_plan = LogicalPlan::Filter {
+                expression,
+                source: Box::new(logical_plan),
+                message_type: inner_type,
+            };
+        }
+        let rows = self.collect_rows(&logical_plan)?;
+
+        let subquery = Subquery {
+            rows: Arc::new(rows),
+            filter: Self::conjunction(correlated),
+            projection,
+            outer_refs,
+        };
+        Ok((subquery, scope, semi_join))
+    }
+
+    fn split_conjuncts<'a>(
+        raw_expression: &'a RawExpression,
+        conjuncts: &mut Vec<&'a RawExpression>,
+    ) {
+        match raw_expression {
+            RawExpression::BinaryOp {
+                op: BinaryOperator::And,
+                left,
+                right,
+            } => {
+                Self::split_conjuncts(left, conjuncts);
+                Self::split_conjuncts(right, conjuncts);
+            }
+            _ => conjuncts.push(raw_expression),
+        }
+    }
+
+    fn conjunction(expressions: Vec<Expression>) -> Option<Expression> {
+        expressions
+            .into_iter()
+            .reduce(|left, right| Expression::BinaryOp {
+                op: BinaryOperator::And,
+                left: Box::new(left),
+                right: Box::new(right),
+            })
+    }
+
+    fn collect_values(rows: &[Message], expression: &Expression) -> Result<ValueSet, PlannerError> {
+        Ok(ValueSet::new(
+            rows.iter()
+                .map(|row| expression.evaluate(row))
+                .collect::<Result<Vec<DBValue>, ExecutorError>>()?,
+        ))
+    }
+
+    //indices of the row columns an expression reads, match arm bodies only see the variant fields
+    fn column_refs(expression: &Expression, refs: &mut Vec<usize>) {
+        match expression {
+            Expression::Literal(_) => {}
+            Expression::ColumnRef(index) => refs.push(*index),
+            Expression::BinaryOp { op: _, left, right } => {
+                Self::column_refs(left, refs);
+                Self::column_refs(right, refs);
+            }
+            Expression::UnaryOp { op: _, expr } => Self::column_refs(expr, refs),
+            Expression::InList { expr, list } => {
+                Self::column_refs(expr, refs);
+                list.iter().for_each(|item| Self::column_refs(item, refs));
+            }
+            Expression::Cast { expr, db_type: _ }
+            | Expression::PatternMatch { expr, pattern: _ }
+            | Expression::InSet { expr, values: _ } => Self::column_refs(expr, refs),
+            Expression::FunctionCall {
+                function: _,
+                args: fields,
+            }
+            | Expression::MessageConstructor {
+                message_type: _,
+                fields,
+            }
+            | Expression::EnumConstructor {
+                enum_type: _,
+                choice: _,
+                fields,
+            } => fields
+                .iter()
+                .for_each(|field| Self::column_refs(field, refs)),
+            Expression::Exists(subquery)
+            | Expression::ScalarSubquery {
+                subquery,
+                db_type: _,
+            } => refs.extend(subquery.outer_refs.iter()),
+            Expression::InSubquery { expr, subquery } => {
+                Self::column_refs(expr, refs);
+                refs.extend(subquery.outer_refs.iter());
+            }
+        }
+    }
+
+    fn is_complex_type(db_type: &DBType) -> bool {
+        matches!(db_type, DBType::MessageType(_) | DBType::EnumType(_))
+    }
+
+    //Deterministic calls with literal arguments are replaced with their result. Calls that fail
+    //are kept as is so the error is raised only if the call is actually evaluated. Messages and
+    //enums are not folded since literals of these types must carry a type name
+    fn fold_constant_call(expression: Expression) -> Expression {
+        let foldable = match &expression {
+            Expression::FunctionCall { function, args } => {
+                function.deterministic
+                    && args.iter().all(|arg| matches!(arg, Expression::Literal(_)))
+            }
+            _ => false,
+        };
+
+        if !foldable {
+            return expression;
+        }
+
+        let empty_message = Message {
+            type_name: None,
+            fields: vec![],
+        };
+
+        match expression.evaluate(&empty_message) {
+            Ok(DBValue::Message(_)) | Ok(DBValue::EnumValue(_)) | Err(_) => expression,
+            Ok(value) => Expression::Literal(value),
+        }
+    }
+
+    //type names accepted by CAST, same as the ones used in dbuf files
+    fn primitive_type(type_name: &str) -> Option<DBType> {
+        match type_name {
+            "Bool" => Some(DBType::Bool),
+            "Int" => Some(DBType::Int),
+            "Unsigned" => Some(DBType::UInt),
+            "Float" => Some(DBType::Double),
+            "String" => Some(DBType::String),
+            _ => None,
+        }
+    }
+
+    //Int and UInt are compared without conversions, neither holds all values of the other
+    fn mixed_integers(left: &DBType, right: &DBType) -> bool {
+        matches!(
+            (left, right),
+            (DBType::Int, DBType::UInt) | (DBType::UInt, DBType::Int)
+        )
+    }
+
+    //integer literals that fit into the integer type of the other operand take its type, so
+    //`unsigned_column > 5` compares unsigned values instead of widening the column
+    fn operand_type(expression: &Expression, expression_type: &DBType, other: &DBType) -> DBType {
+        match expression {
+            Expression::Literal(value @ (DBValue::Int(_) | DBValue::UInt(_)))
+                if matches!(other, DBType::Int | DBType::UInt)
+                    && cast_value(value.clone(), other).is_ok() =>
+            {
+                other.clone()
+            }
+            _ => expression_type.clone(),
+        }
+    }
+
+    //wraps expression into a cast node, literals are converted right away
+    fn coerce(
+        expression: Expression,
+        from: &DBType,
+        to: &DBType,
+    ) -> Result<Expression, PlannerError> {
+        if from == to {
+            return Ok(expression);
+        }
+
+        match expression {
+            Expression::Literal(value) => Ok(Expression::Literal(cast_value(value, to)?)),
+            _ => Ok(Expression::Cast {
+                expr: Box::new(expression),
+                db_type: to.clone(),
+            }),
+        }
+    }
+
+    //field expressions have to match declared field types, numeric fields may be widened and
+    //numeric literals are converted with a range check
+    fn build_constructor_fields<'a, T: ExactSizeIterator<Item = &'a DBType>>(
+        &self,
+        raw_fields: &[RawExpression],
+        field_types: T,
+        type_name: &str,
+        message_type: &MessageType,
+    ) -> Result<Vec<Expression>, PlannerError> {
+        if raw_fields.len() != field_types.len() {
+            return Err(PlannerError::MismatchedFieldTypes(type_name.to_owned()));
+        }
+
+        raw_fields
+            .iter()
+            .zip(field_types)
+            .map(|(raw_field, field_type)| {
+                let field = self.build_expression(raw_field, message_type)?;
+                let deduced_type = self.deduce_expression_type(&field, message_type)?;
+                let widened = deduced_type.common_type(field_type).as_ref() == Some(field_type);
+                let numeric_literal = matches!(field, Expression::Literal(_))
+                    && deduced_type.numeric_rank().is_some()
+                    && field_type.numeric_rank().is_some();
+                if !widened && !numeric_literal {
+                    return Err(PlannerError::MismatchedFieldTypes(type_name.to_owned()));
+                }
+                Self::coerce(field, &deduced_type, field_type)
+            })
+            .collect()
+    }
+
+    //columns visible inside a match arm, aliases default to the variant field names
+    fn match_arm_scope(
+        variant: Option<&EnumVariantType>,
+        bindings: &[usize],
+        aliases: Option<&[String]>,
+    ) -> MessageType {
+        let columns = match variant {
+            Some(variant) => bindings
+                .iter()
+                .enumerate()
+                .map(|(i, index)| {
+                    let (field_name, field_type) = &variant.content[*index];
+                    Column {
+                        column_name: aliases
+                            .map_or_else(|| field_name.clone(), |aliases| aliases[i].clone()),
+                        column_type: field_type.clone(),
+                        dependencies: vec![],
+                    }
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        MessageType {
+            name: variant.map_or_else(|| "_".to_owned(), |variant| variant.name.clone()),
+            columns,
+        }
+    }
+
+    //arms are kept in the written order, unreachable arms and uncovered variants are errors
+    fn build_match_arms(
+        &self,
+        raw_arms: &[RawMatchArm],
+        enum_type: &EnumType,
+    ) -> Result<Vec<MatchArm>, PlannerError> {
+        //variants already handled by an arm without a guard
+        let mut covered = vec![false; enum_type.variants.len()];
+        let mut arms = Vec::<MatchArm>::with_capacity(raw_arms.len());
+        let mut types = Vec::<DBType>::with_capacity(raw_arms.len());
+
+        for raw_arm in raw_arms.iter() {
+            let (choice, bindings, aliases, arm_name) = match &raw_arm.variant {
+                None => (None, vec![], vec![], "_".to_owned()),
+                Some((type_name, variant_name)) => {
+                    if *type_name != enum_type.name {
+                        return Err(PlannerError::IllFormedMatchStatement);
+                    }
+
+                    let choice = enum_type
+                        .variants
+                        .iter()
+                        .position(|variant| variant.name == *variant_name)
+                        .ok_or_else(|| {
+                            PlannerError::EnumVariantNotFound(
+                                type_name.clone(),
+                                variant_name.clone(),
+                            )
+                        })?;
+                    let content = &enum_type.variants[choice].content;
+
+                    let (bindings, aliases) = match &raw_arm.bindings {
+                        None => (
+                            (0..content.len()).collect(),
+                            content.iter().map(|(name, _)| name.clone()).collect(),
+                        ),
+                        Some(pairs) => {
+                            let mut bindings = Vec::<usize>::new();
+                            let mut aliases = Vec::<String>::new();
+                            for (field, alias) in pairs.iter() {
+                                let index = content
+                                    .iter()
+                                    .position(|(name, _)| name == field)
+                                    .ok_or_else(|| PlannerError::ColumnNotFound(field.clone()))?;
+                                if aliases.contains(alias) {
+                                    return Err(PlannerError::IllFormedMatchStatement);
+                                }
+                                bindings.push(index);
+                                aliases.push(alias.clone());
+                            }
+                            (bindings, aliases)
+                        }
+                    };
+
+                    (
+                        Some(choice),
+                        bindings,
+                        aliases,
+                        format!("{}::{}", type_name, variant_name),
+                    )
+                }
+            };
+
+            let reachable = match choice {
+                Some(choice) => !covered[choice],
+                None => covered.iter().any(|covered| !covered),
+            };
+            if !reachable {
+                return Err(PlannerError::UnreachableMatchArm(arm_name));
+            }
+
+            let variant = choice.map(|choice| &enum_type.variants[choice]);
+            let scope = Self::match_arm_scope(variant, &bindings, Some(&aliases));
+
+            let guard = match &raw_arm.guard {
+                Some(raw_guard) => {
+                    let guard = self.build_expression(raw_guard, &scope)?;
+                    if self.deduce_expression_type(&guard, &scope)? != DBType::Bool {
+                        return Err(PlannerError::WrongOperandTypes);
+                    }
+                    Some(guard)
+                }
+                None => {
+                    match choice {
+                        Some(choice) => covered[choice] = true,
+                        None => covered.fill(true),
+                    }
+                    None
+                }
+            };
+
+            let expression = self.build_expression(&raw_arm.expression, &scope)?;
+            types.push(self.deduce_expression_type(&expression, &scope)?);
+            arms.push(MatchArm {
+                choice,
+                bindings,
+                guard,
+                expression,
+            });
+        }
+
+        let missing: Vec<&str> = enum_type
+            .variants
+            .iter()
+            .zip(covered.iter())
+            .filter(|(_, covered)| !**covered)
+            .map(|(variant, _)| variant.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(PlannerError::NonExhaustiveMatch(missing.join(", ")));
+        }
+
+        //arm results are coerced to a common type
+        let (first, rest) = types.split_first().ok_or(PlannerError::EmptyMatchCases)?;
+        let common_type = rest
+            .iter()
+            .try_fold(first.clone(), |acc, db_type| acc.common_type(db_type))
+            .ok_or(PlannerError::AmbiguousMatchType)?;
+
+        arms.into_iter()
+            .zip(types.iter())
+            .map(|(arm, db_type)| {
+                Ok(MatchArm {
+                    expression: Self::coerce(arm.expression, db_type, &common_type)?,
+                    ..arm
+                })
+            })
+            .collect()
+    }
+
+    //numeric literals in message and enum fields are converted to the declared field types
+    fn coerce_literals<'a, T: Iterator<Item = &'a DBType>>(
+        &self,
+        values: Vec<DBValue>,
+        types: T,
+    ) -> Result<Vec<DBValue>, PlannerError> {
+        let mut types = types;
+        let mut coerced = Vec::<DBValue>::with_capacity(values.len());
+
+        for value in values.into_iter() {
+            match types.next() {
+                Some(db_type) => {
+                    let value_type = self.deduce_literal_type(&value)?;
+                    if value_type != *db_type
+                        && value_type.numeric_rank().is_some()
+                        && db_type.numeric_rank().is_some()
+                    {
+                        coerced.push(cast_value(value, db_type)?);
+                    } else {
+                        coerced.push(value);
+                    }
+                }
+                None => coerced.push(value),
+            }
+        }
+
+        Ok(coerced)
+    }
+
+    pub fn get_column_index(
+        column_name: &String,
+        message_type: &MessageType,
+    ) -> Result<usize, PlannerError> {
+        for i in 0usize..message_type.columns.len() {
+            if column_name == &message_type.columns[i].column_name {
+                return Ok(i);
+            }
+        }
+        Err(PlannerError::ColumnNotFound(column_name.clone()))
+    }
+
+    pub fn build_physical_plan(
+        &self,
+        logical_plan: &LogicalPlan,
+    ) -> Box<dyn PhysicalOperator + '_> {
+        match logical_plan {
+            LogicalPlan::Scan {
+                table_name,
+                message_type: _,
+            } => Box::new(TableScan::new(&self.table_manager, table_name.clone())),
+            LogicalPlan::Filter {
+                expression,
+                source,
                 message_type: _,
             } => {
                 let boxed = self.build_physical_plan(source.deref());
@@ -372,26 +1844,1145 @@ impl QueryPlanner {
                     source: boxed,
                 })
             }
-            LogicalPlan::Projection {
-                expressions,
+            LogicalPlan::Projection {
+                expressions,
+                source,
+                message_type: _,
+            } => {
+                let boxed = self.build_physical_plan(source.deref());
+                Box::new(Projection {
+                    expressions: expressions.iter().map(|expr| expr.1.clone()).collect(),
+                    source: boxed,
+                })
+            }
+            LogicalPlan::Distinct {
+                source,
+                message_type: _,
+            } => Box::new(Distinct::new(self.build_physical_plan(source))),
+            LogicalPlan::SetOperation {
+                op,
+                left,
+                right,
+                message_type: _,
+            } => {
+                let left = self.build_physical_plan(left);
+                let right = self.build_physical_plan(right);
+                match op {
+                    SetOperator::UnionAll => Box::new(Append { left, right }),
+                    SetOperator::Union => Box::new(Distinct::new(Box::new(Append { left, right }))),
+                    SetOperator::Intersect => {
+                        Box::new(Distinct::new(Box::new(SetFilter::new(left, right, true))))
+                    }
+                    SetOperator::Except => {
+                        Box::new(Distinct::new(Box::new(SetFilter::new(left, right, false))))
+                    }
+                }
+            }
+            LogicalPlan::Values {
+                rows,
+                message_type: _,
+            } => Box::new(Values::new(rows.clone())),
+        }
+    }
+
+    pub fn from_parsed_select(&self, select: &ast::SelectQuery) -> Result<RawPlan, PlannerError> {
+        let mut source = RawPlan::Scan {
+            table_name: select.table.clone(),
+        };
+
+        if let Some(condition) = &select.condition {
+            source = RawPlan::Filter {
+                raw_expression: self.from_parsed_expression(condition)?,
+                source: Box::new(source),
+            };
+        }
+
+        let raw_expressions = select
+            .fields
+            .iter()
+            .map(|(expression, alias)| {
+                Ok((alias.clone(), self.from_parsed_expression(expression)?))
+            })
+            .collect::<Result<Vec<(String, RawExpression)>, PlannerError>>()?;
+
+        let mut plan = RawPlan::Projection {
+            raw_expressions,
+            source: Box::new(source),
+        };
+        if select.distinct {
+            plan = RawPlan::Distinct {
+                source: Box::new(plan),
+            };
+        }
+
+        for (op, query) in select.compound.iter() {
+            let op = match op {
+                ast::SetOperator::Union => SetOperator::Union,
+                ast::SetOperator::UnionAll => SetOperator::UnionAll,
+                ast::SetOperator::Intersect => SetOperator::Intersect,
+                ast::SetOperator::Except => SetOperator::Except,
+            };
+            plan = RawPlan::SetOperation {
+                op,
+                left: Box::new(plan),
+                right: Box::new(self.from_parsed_select(query)?),
+            };
+        }
+
+        if select.ctes.is_empty() {
+            return Ok(plan);
+        }
+
+        let ctes = select
+            .ctes
+            .iter()
+            .map(|cte| {
+                let cte_plan = self.from_parsed_select(&cte.query)?;
+
+                //the last UNION of a recursive cte is the step if it refers to the cte
+                match &cte_plan {
+                    RawPlan::SetOperation { op, left, right }
+                        if cte.recursive
+                            && matches!(op, SetOperator::Union | SetOperator::UnionAll)
+                            && Self::count_references(right, &cte.name) > 0 =>
+                    {
+                        return Ok(RawCte {
+                            name: cte.name.clone(),
+                            plan: left.deref().clone(),
+                            recursion: Some((right.deref().clone(), *op == SetOperator::UnionAll)),
+                        });
+                    }
+                    _ => {}
+                }
+
+                Ok(RawCte {
+                    name: cte.name.clone(),
+                    plan: cte_plan,
+                    recursion: None,
+                })
+            })
+            .collect::<Result<Vec<RawCte>, PlannerError>>()?;
+
+        Ok(RawPlan::With {
+            ctes,
+            source: Box::new(plan),
+        })
+    }
+
+    //Runs f with the ctes visible as tables. Ctes referenced more than once and recursive ones
+    //are computed here, the rest are planned in place of every reference
+    fn with_ctes<T>(
+        &self,
+        ctes: &[RawCte],
+        source: &RawPlan,
+        f: impl FnOnce(&Self) -> Result<T, PlannerError>,
+    ) -> Result<T, PlannerError> {
+        let visible = self.ctes.borrow().len();
+        let result = self.bind_ctes(ctes, source).and_then(|_| f(self));
+        self.ctes.borrow_mut().truncate(visible);
+        result
+    }
+
+    fn bind_ctes(&self, ctes: &[RawCte], source: &RawPlan) -> Result<(), PlannerError> {
+        for (i, cte) in ctes.iter().enumerate() {
+            if ctes[..i].iter().any(|other| other.name == cte.name) {
+                return Err(PlannerError::DuplicateCte(cte.name.clone()));
+            }
+
+            let binding = match &cte.recursion {
+                Some((step, all)) => self.materialize_recursive(cte, step, *all)?,
+                None => {
+                    let references = Self::count_references(source, &cte.name)
+                        + ctes[i + 1..]
+                            .iter()
+                            .map(|later| {
+                                Self::count_references(&later.plan, &cte.name)
+                                    + later.recursion.as_ref().map_or(0, |(step, _)| {
+                                        Self::count_references(step, &cte.name)
+                                    })
+                            })
+                            .sum::<usize>();
+                    if references > 1 {
+                        let logical_plan = self.build_logical_plan(&cte.plan)?;
+                        CteBinding::Materialized {
+                            rows: Arc::new(self.collect_rows(&logical_plan)?),
+                            message_type: logical_plan.get_message_type().clone(),
+                        }
+                    } else {
+                        CteBinding::Inline {
+                            raw_plan: cte.plan.clone(),
+                            visible: self.ctes.borrow().len(),
+                        }
+                    }
+                }
+            };
+            self.ctes.borrow_mut().push((cte.name.clone(), binding));
+        }
+        Ok(())
+    }
+
+    //Recursive ctes start with the rows of the first query, then the step query is run on the
+    //rows found by the previous step until it returns no new rows. UNION without ALL skips rows
+    //that were already found, which also stops the recursion on cyclic data
+    fn materialize_recursive(
+        &self,
+        cte: &RawCte,
+        step: &RawPlan,
+        all: bool,
+    ) -> Result<CteBinding, PlannerError> {
+        let base = self.build_logical_plan(&cte.plan)?;
+        let message_type = base.get_message_type().clone();
+
+        let mut seen = HashSet::<RowKey>::new();
+        let mut keep_new = |rows: Vec<Message>| -> Vec<Message> {
+            if all {
+                return rows;
+            }
+            rows.into_iter()
+                .filter(|row| seen.insert(RowKey(row.fields.clone())))
+                .collect()
+        };
+
+        let mut rows = keep_new(self.collect_rows(&base)?);
+        let mut working = rows.clone();
+        let mut steps = 0usize;
+        while !working.is_empty() {
+            steps += 1;
+            if steps > RECURSION_LIMIT {
+                return Err(PlannerError::RecursionLimit(cte.name.clone()));
+            }
+
+            let previous = CteBinding::Materialized {
+                rows: Arc::new(working),
+                message_type: message_type.clone(),
+            };
+            self.ctes.borrow_mut().push((cte.name.clone(), previous));
+            let result = self.build_logical_plan(step).and_then(|logical_plan| {
+                Self::check_insert_compatibility(&message_type, logical_plan.get_message_type())?;
+                self.collect_rows(&logical_plan)
+            });
+            self.ctes.borrow_mut().pop();
+
+            working = keep_new(result?);
+            rows.extend(working.iter().cloned());
+        }
+
+        Ok(CteBinding::Materialized {
+            rows: Arc::new(rows),
+            message_type,
+        })
+    }
+
+    //number of scans of the table name, including the ones in subqueries
+    fn count_references(raw_plan: &RawPlan, name: &str) -> usize {
+        let in_expressions = |raw_expressions: &mut dyn Iterator<Item = &RawExpression>| {
+            let mut plans = vec![];
+            raw_expressions.for_each(|raw_expression| raw_expression.subqueries(&mut plans));
+            plans
+                .into_iter()
+                .map(|plan| Self::count_references(plan, name))
+                .sum::<usize>()
+        };
+
+        match raw_plan {
+            RawPlan::Scan { table_name } => usize::from(table_name == name),
+            RawPlan::Filter {
+                raw_expression,
+                source,
+            } => {
+                in_expressions(&mut std::iter::once(raw_expression))
+                    + Self::count_references(source, name)
+            }
+            RawPlan::Projection {
+                raw_expressions,
                 source,
-                message_type: _,
             } => {
-                let boxed = self.build_physical_plan(source.deref());
-                Box::new(Projection {
-                    expressions: expressions.iter().map(|expr| expr.1.clone()).collect(),
-                    source: boxed,
+                in_expressions(&mut raw_expressions.iter().map(|(_, raw)| raw))
+                    + Self::count_references(source, name)
+            }
+            RawPlan::Distinct { source } => Self::count_references(source, name),
+            RawPlan::SetOperation { op: _, left, right } => {
+                Self::count_references(left, name) + Self::count_references(right, name)
+            }
+            RawPlan::With { ctes, source } => {
+                ctes.iter()
+                    .map(|cte| {
+                        Self::count_references(&cte.plan, name)
+                            + cte
+                                .recursion
+                                .as_ref()
+                                .map_or(0, |(step, _)| Self::count_references(step, name))
+                    })
+                    .sum::<usize>()
+                    + Self::count_references(source, name)
+            }
+        }
+    }
+
+    fn collect_rows(&self, logical_plan: &LogicalPlan) -> Result<Vec<Message>, PlannerError> {
+        let mut physical_plan = self.build_physical_plan(logical_plan);
+        physical_plan.open()?;
+        Ok(physical_plan.collect::<Result<Vec<Message>, ExecutorError>>()?)
+    }
+
+    //Columns of set operations are matched by position and named after the left query. Numeric
+    //columns are widened to a common type, other types and dependencies have to match
+    fn set_operation_type(
+        left: &MessageType,
+        right: &MessageType,
+    ) -> Result<MessageType, PlannerError> {
+        if left.columns.len() != right.columns.len() {
+            return Err(PlannerError::IncompatibleSchema(format!(
+                "expected {} columns, got {}",
+                left.columns.len(),
+                right.columns.len()
+            )));
+        }
+
+        let columns = left
+            .columns
+            .iter()
+            .zip(right.columns.iter())
+            .map(|(l, r)| {
+                let column_type = l.column_type.common_type(&r.column_type).ok_or_else(|| {
+                    PlannerError::IncompatibleSchema(format!(
+                        "column {} has type {:?}, expected {:?}",
+                        r.column_name, r.column_type, l.column_type
+                    ))
+                })?;
+                if l.dependencies != r.dependencies {
+                    return Err(PlannerError::IncompatibleSchema(format!(
+                        "column {} has dependencies {:?}, expected {:?}",
+                        r.column_name, r.dependencies, l.dependencies
+                    )));
+                }
+
+                Ok(Column {
+                    column_name: l.column_name.clone(),
+                    column_type,
+                    dependencies: l.dependencies.clone(),
+                })
+            })
+            .collect::<Result<Vec<Column>, PlannerError>>()?;
+
+        Ok(MessageType {
+            name: "".to_owned(),
+            columns,
+        })
+    }
+
+    //projects the plan to the columns of message_type if some of them have to be widened
+    fn coerce_plan(
+        logical_plan: LogicalPlan,
+        message_type: &MessageType,
+    ) -> Result<LogicalPlan, PlannerError> {
+        let source_type = logical_plan.get_message_type().clone();
+        let types_match = source_type
+            .columns
+            .iter()
+            .zip(message_type.columns.iter())
+            .all(|(source, target)| source.column_type == target.column_type);
+        if types_match {
+            return Ok(logical_plan);
+        }
+
+        let expressions = source_type
+            .columns
+            .iter()
+            .zip(message_type.columns.iter())
+            .enumerate()
+            .map(|(index, (source, target))| {
+                Ok((
+                    target.column_name.clone(),
+                    Self::coerce(
+                        Expression::ColumnRef(index),
+                        &source.column_type,
+                        &target.column_type,
+                    )?,
+                ))
+            })
+            .collect::<Result<Vec<(String, Expression)>, PlannerError>>()?;
+
+        Ok(LogicalPlan::Projection {
+            expressions,
+            source: Box::new(logical_plan),
+            message_type: message_type.clone(),
+        })
+    }
+
+    //Columns are matched by position, names of the query columns do not matter
+    pub fn check_insert_compatibility(
+        schema: &MessageType,
+        message_type: &MessageType,
+    ) -> Result<(), PlannerError> {
+        if schema.columns.len() != message_type.columns.len() {
+            return Err(PlannerError::IncompatibleSchema(format!(
+                "expected {} columns, got {}",
+                schema.columns.len(),
+                message_type.columns.len()
+            )));
+        }
+
+        for (expected, column) in schema.columns.iter().zip(message_type.columns.iter()) {
+            if expected.column_type != column.column_type {
+                return Err(PlannerError::IncompatibleSchema(format!(
+                    "column {} has type {:?}, expected {:?}",
+                    column.column_name, column.column_type, expected.column_type
+                )));
+            }
+            if expected.dependencies != column.dependencies {
+                return Err(PlannerError::IncompatibleSchema(format!(
+                    "column {} has dependencies {:?}, expected {:?}",
+                    column.column_name, column.dependencies, expected.dependencies
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    //Statements that rewrite a table fill a scratch table named {table}#{purpose} first and swap
+    //it in when all rows are written. Names of user tables and views can not contain '#', so
+    //scratch tables never clash with them
+    fn scratch_table(name: &str, purpose: &str) -> String {
+        format!("{}#{}", name, purpose)
+    }
+
+    fn check_name(name: &str) -> Result<(), PlannerError> {
+        match name.contains('#') {
+            true => Err(PlannerError::ReservedName(name.to_owned())),
+            false => Ok(()),
+        }
+    }
+
+    //Materialized views are stored as tables, table statements must not change them
+    fn check_not_view(&self, name: &str) -> Result<(), PlannerError> {
+        match self.state.views.contains_key(name) {
+            true => Err(PlannerError::ViewNotTable(name.to_owned())),
+            false => Ok(()),
+        }
+    }
+
+    pub fn create_table(
+        &mut self,
+        table_name: &str,
+        message_type: MessageType,
+    ) -> Result<(), PlannerError> {
+        Self::check_name(table_name)?;
+        self.check_not_view(table_name)?;
+        Ok(self
+            .table_manager
+            .create_table(table_name.to_owned(), message_type)?)
+    }
+
+    pub fn drop_table(&mut self, table_name: &str) -> Result<(), PlannerError> {
+        self.check_not_view(table_name)?;
+        Ok(self.table_manager.drop_table(table_name.to_owned())?)
+    }
+
+    //Message literals are built from the registered types, tables that keep an older version
+    //have to be altered first
+    pub fn insert_messages(
+        &mut self,
+        table_name: &str,
+        messages: Vec<Message>,
+    ) -> Result<(), PlannerError> {
+        self.check_not_view(table_name)?;
+        let schema = self.table_manager.schema(table_name.to_owned())?;
+        if self
+            .state
+            .message_types
+            .get(&schema.name)
+            .is_some_and(|current| *current != schema)
+        {
+            return Err(PlannerError::IncompatibleSchema(format!(
+                "table {} stores an older version of {}, convert it with ALTER TABLE",
+                table_name, schema.name
+            )));
+        }
+        Ok(self
+            .table_manager
+            .insert_messages(table_name.to_owned(), messages.into_iter())?)
+    }
+
+    pub fn insert_from_plan(
+        &mut self,
+        table_name: &str,
+        logical_plan: &LogicalPlan,
+    ) -> Result<usize, PlannerError> {
+        self.check_not_view(table_name)?;
+        self.fill_table(table_name, logical_plan)
+    }
+
+    //Results are inserted in batches. The physical plan borrows the table manager, so it is
+    //dropped before every insertion and resumed from its cursor afterwards. Scans only see
+    //rows that existed when the query started, so a table can be inserted into itself.
+    //Batches inserted before an error are kept
+    fn fill_table(
+        &mut self,
+        table_name: &str,
+        logical_plan: &LogicalPlan,
+    ) -> Result<usize, PlannerError> {
+        let schema = self.table_manager.schema(table_name.to_owned())?;
+        Self::check_insert_compatibility(&schema, logical_plan.get_message_type())?;
+
+        let mut cursor: Option<PlanCursor> = None;
+        let mut inserted = 0usize;
+
+        loop {
+            let mut batch = Vec::<Message>::with_capacity(INSERT_BATCH_SIZE);
+            {
+                let mut physical_plan = PhysicalPlan::from(self.build_physical_plan(logical_plan));
+                if let Some(cursor) = cursor {
+                    physical_plan.seek(cursor);
+                }
+                physical_plan.open()?;
+
+                for result in physical_plan.by_ref().take(INSERT_BATCH_SIZE) {
+                    batch.push(Message {
+                        type_name: Some(schema.name.clone()),
+                        fields: result?.fields,
+                    });
+                }
+                cursor = physical_plan.cursor();
+
+                //DISTINCT, INTERSECT and EXCEPT keep state that can not be resumed, plans with them
+                //are read completely
+                if cursor.is_none() {
+                    for result in physical_plan.by_ref() {
+                        batch.push(Message {
+                            type_name: Some(schema.name.clone()),
+                            fields: result?.fields,
+                        });
+                    }
+                }
+            }
+
+            let finished = batch.len() < INSERT_BATCH_SIZE || cursor.is_none();
+            inserted += batch.len();
+            self.table_manager
+                .insert_messages(table_name.to_owned(), batch.into_iter())?;
+
+            if finished {
+                return Ok(inserted);
+            }
+        }
+    }
+
+    //Rewrites every message of the table into the new type. Without USING columns are taken
+    //from the old columns with the same names. The new messages are written into a separate
+    //table first, so the table is left unchanged if the conversion fails
+    pub fn alter_table_type(
+        &mut self,
+        table_name: &str,
+        type_name: &String,
+        using: Option<&ast::Expression>,
+    ) -> Result<usize, PlannerError> {
+        self.check_not_view(table_name)?;
+        let new_type = self.get_message_type(type_name)?;
+        let source = self.table_manager.schema(table_name.to_owned())?;
+
+        let expressions = match using {
+            Some(using) => {
+                let expression =
+                    self.build_expression(&self.from_parsed_expression(using)?, &source)?;
+                let db_type = self.deduce_expression_type(&expression, &source)?;
+                if db_type != DBType::MessageType(new_type.clone()) {
+                    return Err(PlannerError::IncompatibleSchema(format!(
+                        "USING expression has type {:?}, expected {}",
+                        db_type, new_type.name
+                    )));
+                }
+
+                match expression {
+                    Expression::MessageConstructor { fields, .. } => fields,
+                    _ => (0..new_type.columns.len())
+                        .map(|index| Expression::UnaryOp {
+                            op: UnaryOperator::MessageField(index),
+                            expr: Box::new(expression.clone()),
+                        })
+                        .collect(),
+                }
+            }
+            None => new_type
+                .columns
+                .iter()
+                .map(|column| {
+                    let index = source
+                        .columns
+                        .iter()
+                        .position(|old| old.column_name == column.column_name)
+                        .ok_or_else(|| {
+                            PlannerError::IncompatibleSchema(format!(
+                                "column {} is missing, use USING to set it",
+                                column.column_name
+                            ))
+                        })?;
+                    let old_type = &source.columns[index].column_type;
+                    if old_type.common_type(&column.column_type) != Some(column.column_type.clone())
+                    {
+                        return Err(PlannerError::IncompatibleSchema(format!(
+                            "column {} has type {:?}, expected {:?}",
+                            column.column_name, old_type, column.column_type
+                        )));
+                    }
+                    Self::coerce(Expression::ColumnRef(index), old_type, &column.column_type)
                 })
+                .collect::<Result<Vec<Expression>, PlannerError>>()?,
+        };
+
+        let logical_plan = LogicalPlan::Projection {
+            expressions: new_type
+                .columns
+                .iter()
+                .map(|column| column.column_name.clone())
+                .zip(expressions)
+                .collect(),
+            source: Box::new(LogicalPlan::Scan {
+                table_name: table_name.to_owned(),
+                message_type: source,
+            }),
+            message_type: new_type.clone(),
+        };
+
+        let migration = Self::scratch_table(table_name, "migration");
+        let count = self.fill_scratch_table(&migration, new_type, &logical_plan)?;
+        self.table_manager
+            .replace_table(table_name.to_owned(), migration)?;
+        Ok(count)
+    }
+
+    //Writes the query results into a new table that is dropped again if the query fails, so
+    //callers can swap it in with replace_table only when all rows are written
+    fn fill_scratch_table(
+        &mut self,
+        scratch: &str,
+        message_type: MessageType,
+        logical_plan: &LogicalPlan,
+    ) -> Result<usize, PlannerError> {
+        //left over if the process stopped before the swap
+        match self.table_manager.drop_table(scratch.to_owned()) {
+            Ok(()) | Err(ExecutorError::TableNotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.table_manager
+            .create_table(scratch.to_owned(), message_type)?;
+        match self.fill_table(scratch, logical_plan) {
+            Ok(count) => Ok(count),
+            Err(e) => {
+                self.table_manager.drop_table(scratch.to_owned())?;
+                Err(e)
+            }
+        }
+    }
+
+    //Type names used for CREATE TABLE ... AS SELECT without an explicit type name
+    fn generate_type_name(&self, table_name: &str) -> String {
+        let is_free = |name: &String| {
+            !self.state.message_types.contains_key(name)
+                && !self.state.enum_types.contains_key(name)
+        };
+
+        let base = format!("{}_row", table_name);
+        if is_free(&base) {
+            return base;
+        }
+        (1usize..)
+            .map(|i| format!("{}_{}", base, i))
+            .find(is_free)
+            .unwrap()
+    }
+
+    //Registers the query result type under the given or a generated name, creates the table
+    //and fills it with the query results. Returns the type name and the number of inserted rows.
+    //The results are written into a separate table first, so nothing is created if the query fails
+    pub fn create_table_as(
+        &mut self,
+        table_name: &str,
+        type_name: Option<String>,
+        logical_plan: &LogicalPlan,
+    ) -> Result<(String, usize), PlannerError> {
+        Self::check_name(table_name)?;
+        self.check_not_view(table_name)?;
+        let type_name = match type_name {
+            Some(type_name) => {
+                if self.state.message_types.contains_key(&type_name) {
+                    return Err(PlannerError::DuplicateMessageType(type_name));
+                }
+                if self.state.enum_types.contains_key(&type_name) {
+                    return Err(PlannerError::DuplicateEnumType(type_name));
+                }
+                type_name
+            }
+            None => self.generate_type_name(table_name),
+        };
+
+        let mut message_type = logical_plan.get_message_type().clone();
+        message_type.name = type_name.clone();
+        Self::check_column_names(&message_type)?;
+
+        if self.table_manager.schema(table_name.to_owned()).is_ok() {
+            return Err(ExecutorError::TableAlreadyExists.into());
+        }
+
+        let creation = Self::scratch_table(table_name, "creation");
+        let inserted = self.fill_scratch_table(&creation, message_type.clone(), logical_plan)?;
+
+        self.table_manager
+            .create_table(table_name.to_owned(), message_type.clone())?;
+        self.table_manager
+            .replace_table(table_name.to_owned(), creation)?;
+        self.add_message_type(type_name.clone(), message_type)?;
+        Ok((type_name, inserted))
+    }
+
+    fn check_column_names(message_type: &MessageType) -> Result<(), PlannerError> {
+        for (i, column) in message_type.columns.iter().enumerate() {
+            if message_type.columns[..i]
+                .iter()
+                .any(|other| other.column_name == column.column_name)
+            {
+                return Err(PlannerError::DuplicateField(
+                    message_type.name.clone(),
+                    column.column_name.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_view(&self, name: &str) -> Result<View, PlannerError> {
+        self.state
+            .views
+            .get(name)
+            .cloned()
+            .ok_or_else(|| PlannerError::UnexistingView(name.to_owned()))
+    }
+
+    //Returns the number of stored messages for materialized views. Nothing is created if the
+    //query of a materialized view fails
+    pub fn create_view(
+        &mut self,
+        name: &str,
+        select: &ast::SelectQuery,
+        materialized: bool,
+    ) -> Result<usize, PlannerError> {
+        Self::check_name(name)?;
+        if self.state.views.contains_key(name) {
+            return Err(PlannerError::DuplicateView(name.to_owned()));
+        }
+        if self.table_manager.schema(name.to_owned()).is_ok() {
+            return Err(ExecutorError::TableAlreadyExists.into());
+        }
+
+        let logical_plan = self.build_logical_plan(&self.from_parsed_select(select)?)?;
+        let mut message_type = logical_plan.get_message_type().clone();
+        message_type.name = name.to_owned();
+        Self::check_column_names(&message_type)?;
+
+        let mut inserted = 0usize;
+        if materialized {
+            let creation = Self::scratch_table(name, "creation");
+            inserted = self.fill_scratch_table(&creation, message_type.clone(), &logical_plan)?;
+            self.table_manager
+                .create_table(name.to_owned(), message_type.clone())?;
+            self.table_manager
+                .replace_table(name.to_owned(), creation)?;
+        }
+
+        self.state.views.insert(
+            name.to_owned(),
+            View {
+                query: select.clone(),
+                message_type,
+                materialized,
+            },
+        );
+        save(
+            self.table_manager.paged_storage.marble(),
+            &self.state,
+            PLANNER_STATE_INDEX,
+        )?;
+
+        Ok(inserted)
+    }
+
+    //The old messages are kept until the query results are written, a failing query leaves
+    //them in place
+    pub fn refresh_materialized_view(&mut self, name: &str) -> Result<usize, PlannerError> {
+        let view = self.get_view(name)?;
+        if !view.materialized {
+            return Err(PlannerError::NotMaterialized(name.to_owned()));
+        }
+
+        let logical_plan = self.build_logical_plan(&self.from_parsed_select(&view.query)?)?;
+        Self::check_insert_compatibility(&view.message_type, logical_plan.get_message_type())?;
+
+        let refresh = Self::scratch_table(name, "refresh");
+        let inserted = self.fill_scratch_table(&refresh, view.message_type, &logical_plan)?;
+        self.table_manager.replace_table(name.to_owned(), refresh)?;
+        Ok(inserted)
+    }
+
+    //The view is removed from the catalog even if its messages are already gone
+    pub fn drop_view(&mut self, name: &str) -> Result<(), PlannerError> {
+        let view = self.get_view(name)?;
+        self.state.views.remove(name);
+        save(
+            self.table_manager.paged_storage.marble(),
+            &self.state,
+            PLANNER_STATE_INDEX,
+        )?;
+
+        if view.materialized {
+            match self.table_manager.drop_table(name.to_owned()) {
+                Ok(()) | Err(ExecutorError::TableNotFound) => {}
+                Err(e) => return Err(e.into()),
             }
         }
+
+        Ok(())
+    }
+
+    //Tables and views sorted by name, scratch tables are not listed
+    pub fn relations(&self) -> Vec<(String, RelationKind, MessageType)> {
+        let mut relations = self
+            .table_manager
+            .state
+            .tables
+            .iter()
+            .filter(|(name, _)| Self::check_name(name).is_ok())
+            .map(|(name, object_storage)| {
+                let kind = match self.state.views.contains_key(name) {
+                    true => RelationKind::MaterializedView,
+                    false => RelationKind::Table,
+                };
+                (name.clone(), kind, object_storage.schema.clone())
+            })
+            .chain(
+                self.state
+                    .views
+                    .iter()
+                    .filter(|(_, view)| !view.materialized)
+                    .map(|(name, view)| {
+                        (name.clone(), RelationKind::View, view.message_type.clone())
+                    }),
+            )
+            .collect::<Vec<(String, RelationKind, MessageType)>>();
+        relations.sort_by(|l, r| l.0.cmp(&r.0));
+        relations
+    }
+
+    pub fn describe_relation(
+        &self,
+        name: &str,
+    ) -> Result<(RelationKind, MessageType), PlannerError> {
+        if let Some(system_table) = self.system_table(name) {
+            return Ok((
+                RelationKind::System,
+                system_table.get_message_type().clone(),
+            ));
+        }
+
+        self.relations()
+            .into_iter()
+            .find(|(relation, _, _)| relation == name)
+            .map(|(_, kind, message_type)| (kind, message_type))
+            .ok_or_else(|| ExecutorError::TableNotFound.into())
+    }
+
+    //Registered message and enum types sorted by name, with their current versions
+    pub fn types(&self) -> Vec<(DBType, usize)> {
+        let mut types = self
+            .state
+            .message_types
+            .values()
+            .map(|message_type| {
+                let version = self.message_type_version(message_type).unwrap_or(1);
+                (DBType::MessageType(message_type.clone()), version)
+            })
+            .chain(
+                self.state
+                    .enum_types
+                    .values()
+                    .map(|enum_type| (DBType::EnumType(enum_type.clone()), 1usize)),
+            )
+            .collect::<Vec<(DBType, usize)>>();
+        types.sort_by_key(|(db_type, _)| db_type.to_string());
+        types
+    }
+
+    pub fn describe_type(&self, type_name: &String) -> Result<DBType, PlannerError> {
+        self.registered_type(type_name)
+            .ok_or_else(|| PlannerError::UnexistingType(type_name.clone()))
+    }
+
+    //Closest name from the catalog for an error about a misspelled table, view, column, type,
+    //field or variant. Missing tables are looked up among the ones the query reads directly
+    pub fn did_you_mean(&self, query: &ast::Query, error: &PlannerError) -> Option<String> {
+        let relations = self
+            .relations()
+            .into_iter()
+            .map(|(name, _, message_type)| (name, message_type))
+            .collect::<Vec<(String, MessageType)>>();
+        let types = self.known_types();
+
+        let (name, candidates) = match error {
+            PlannerError::ExecutorError(ExecutorError::TableNotFound) => {
+                let mut tables = vec![];
+                let mut ctes = vec![];
+                match query {
+                    ast::Query::Select(select)
+                    | ast::Query::CreateTableAs { select, .. }
+                    | ast::Query::CreateView { select, .. } => {
+                        query_tables(select, &mut tables, &mut ctes)
+                    }
+                    ast::Query::InsertSelect { table, select } => {
+                        tables.push(table.clone());
+                        query_tables(select, &mut tables, &mut ctes);
+                    }
+                    ast::Query::DropTable(table)
+                    | ast::Query::InsertMessages { table, .. }
+                    | ast::Query::AlterTableType { table, .. }
+                    | ast::Query::Describe(table) => tables.push(table.clone()),
+                    _ => {}
+                }
+                let missing = tables.into_iter().find(|table| {
+                    !ctes.contains(table)
+                        && self.system_table(table).is_none()
+                        && !relations.iter().any(|(name, _)| name == table)
+                })?;
+                let candidates = relations
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .chain(SYSTEM_TABLES.iter().map(|name| name.to_string()))
+                    .chain(ctes)
+                    .collect::<Vec<String>>();
+                (missing, candidates)
+            }
+            PlannerError::UnexistingView(name) | PlannerError::NotMaterialized(name) => {
+                (name.clone(), self.state.views.keys().cloned().collect())
+            }
+            //columns of every relation and fields of enum variants, which can be bound in
+            //match arms
+            PlannerError::ColumnNotFound(name) => {
+                let mut candidates = relations
+                    .iter()
+                    .flat_map(|(_, message_type)| message_type.columns.iter())
+                    .map(|column| column.column_name.clone())
+                    .collect::<Vec<String>>();
+                for enum_type in self.state.enum_types.values() {
+                    for variant in enum_type.variants.iter() {
+                        candidates.extend(variant.content.iter().map(|(name, _)| name.clone()));
+                    }
+                }
+                (name.clone(), candidates)
+            }
+            PlannerError::UnexistingMessageType(name)
+            | PlannerError::UnexistingEnumType(name)
+            | PlannerError::UnexistingType(name) => (name.clone(), types.keys().cloned().collect()),
+            PlannerError::UnknownField(type_name, field) => match types.get(type_name) {
+                Some(DBType::MessageType(message_type)) => (
+                    field.clone(),
+                    message_type
+                        .columns
+                        .iter()
+                        .map(|column| column.column_name.clone())
+                        .collect(),
+                ),
+                _ => return None,
+            },
+            PlannerError::EnumVariantNotFound(type_name, variant) => match types.get(type_name) {
+                Some(DBType::EnumType(enum_type)) => (
+                    variant.clone(),
+                    enum_type
+                        .variants
+                        .iter()
+                        .map(|variant| variant.name.clone())
+                        .collect(),
+                ),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        closest(&name, candidates.iter().map(String::as_str))
+    }
+
+    //Read only tables describing the catalog, built when a query uses them
+    fn system_table(&self, table_name: &str) -> Option<LogicalPlan> {
+        let string = |value: &dyn ToString| DBValue::String(value.to_string());
+        let columns = |names: &[(&str, DBType)]| MessageType {
+            name: table_name.to_owned(),
+            columns: names
+                .iter()
+                .map(|(name, column_type)| Column {
+                    column_name: name.to_string(),
+                    column_type: column_type.clone(),
+                    dependencies: vec![],
+                })
+                .collect(),
+        };
+
+        let (message_type, rows) = match table_name {
+            "sys.tables" => (
+                columns(&[
+                    ("name", DBType::String),
+                    ("kind", DBType::String),
+                    ("type_name", DBType::String),
+                ]),
+                self.relations()
+                    .iter()
+                    .map(|(name, kind, message_type)| {
+                        vec![string(name), string(kind), string(&message_type.name)]
+                    })
+                    .collect::<Vec<Vec<DBValue>>>(),
+            ),
+            "sys.columns" => (
+                columns(&[
+                    ("table_name", DBType::String),
+                    ("name", DBType::String),
+                    ("position", DBType::UInt),
+                    ("type_name", DBType::String),
+                    ("dependencies", DBType::String),
+                ]),
+                self.relations()
+                    .iter()
+                    .flat_map(|(name, _, message_type)| {
+                        message_type
+                            .columns
+                            .iter()
+                            .enumerate()
+                            .map(move |(position, column)| {
+                                let dependencies = column
+                                    .dependencies
+                                    .iter()
+                                    .map(|index| message_type.columns[*index].column_name.clone())
+                                    .collect::<Vec<String>>();
+                                vec![
+                                    string(name),
+                                    string(&column.column_name),
+                                    DBValue::UInt(position as u32),
+                                    string(&column.column_type),
+                                    string(&dependencies.join(", ")),
+                                ]
+                            })
+                    })
+                    .collect(),
+            ),
+            "sys.types" => (
+                columns(&[
+                    ("name", DBType::String),
+                    ("kind", DBType::String),
+                    ("version", DBType::UInt),
+                    ("definition", DBType::String),
+                ]),
+                self.types()
+                    .iter()
+                    .map(|(db_type, version)| {
+                        let (kind, definition) = match db_type {
+                            DBType::MessageType(message_type) => {
+                                ("message", message_type.to_string())
+                            }
+                            DBType::EnumType(enum_type) => ("enum", enum_type.to_string()),
+                            _ => unreachable!(),
+                        };
+                        vec![
+                            string(db_type),
+                            string(&kind),
+                            DBValue::UInt(*version as u32),
+                            string(&definition),
+                        ]
+                    })
+                    .collect(),
+            ),
+            _ => return None,
+        };
+
+        Some(LogicalPlan::Values {
+            rows: Arc::new(
+                rows.into_iter()
+                    .map(|fields| Message {
+                        type_name: None,
+                        fields,
+                    })
+                    .collect(),
+            ),
+            message_type,
+        })
+    }
+
+    //Plain views are planned without the ctes of the query that uses them
+    fn expand_view(&self, view: &View) -> Result<LogicalPlan, PlannerError> {
+        let hidden = self.ctes.borrow_mut().split_off(0);
+        let result = self
+            .from_parsed_select(&view.query)
+            .and_then(|raw_plan| self.build_logical_plan(&raw_plan));
+        self.ctes.borrow_mut().extend(hidden);
+
+        let logical_plan = result?;
+        Self::check_insert_compatibility(&view.message_type, logical_plan.get_message_type())?;
+        Ok(logical_plan)
     }
 
     pub fn build_logical_plan(&self, raw_plan: &RawPlan) -> Result<LogicalPlan, PlannerError> {
         let logical_plan = match raw_plan {
-            RawPlan::Scan { table_name } => LogicalPlan::Scan {
-                table_name: table_name.clone(),
-                message_type: self.table_manager.schema(table_name.clone())?,
-            },
+            RawPlan::Scan { table_name } => {
+                let binding = self
+                    .ctes
+                    .borrow()
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| name == table_name)
+                    .map(|(_, binding)| binding.clone());
+
+                match binding {
+                    Some(CteBinding::Inline { raw_plan, visible }) => {
+                        let hidden = self.ctes.borrow_mut().split_off(visible);
+                        let result = self.build_logical_plan(&raw_plan);
+                        self.ctes.borrow_mut().extend(hidden);
+                        result?
+                    }
+                    Some(CteBinding::Materialized { rows, message_type }) => {
+                        LogicalPlan::Values { rows, message_type }
+                    }
+                    None => match self.state.views.get(table_name) {
+                        Some(view) if !view.materialized => self.expand_view(view)?,
+                        _ if table_name.starts_with("sys.") => self
+                            .system_table(table_name)
+                            .ok_or(ExecutorError::TableNotFound)?,
+                        _ => LogicalPlan::Scan {
+                            table_name: table_name.clone(),
+                            message_type: self.table_manager.schema(table_name.clone())?,
+                        },
+                    },
+                }
+            }
+            RawPlan::Distinct { source } => {
+                let logical_source = self.build_logical_plan(source)?;
+                let message_type = logical_source.get_message_type().clone();
+
+                LogicalPlan::Distinct {
+                    source: Box::new(logical_source),
+                    message_type,
+                }
+            }
+            RawPlan::SetOperation { op, left, right } => {
+                let left = self.build_logical_plan(left)?;
+                let right = self.build_logical_plan(right)?;
+                let message_type =
+                    Self::set_operation_type(left.get_message_type(), right.get_message_type())?;
+
+                LogicalPlan::SetOperation {
+                    op: *op,
+                    left: Box::new(Self::coerce_plan(left, &message_type)?),
+                    right: Box::new(Self::coerce_plan(right, &message_type)?),
+                    message_type,
+                }
+            }
+            RawPlan::With { ctes, source } => {
+                self.with_ctes(ctes, source, |planner| planner.build_logical_plan(source))?
+            }
             RawPlan::Filter {
                 raw_expression,
                 source,
@@ -505,6 +3096,55 @@ impl QueryPlanner {
                 let db_type = self.deduce_expression_type(expr.deref(), message_type)?;
                 self.deduce_unary_op_type(op, &db_type)?
             }
+            Expression::InList { expr, list } => {
+                let db_type = self.deduce_expression_type(expr.deref(), message_type)?;
+                for item in list.iter() {
+                    let item_type = self.deduce_expression_type(item, message_type)?;
+                    if item_type != db_type && !Self::mixed_integers(&item_type, &db_type) {
+                        return Err(PlannerError::WrongOperandTypes);
+                    }
+                }
+                DBType::Bool
+            }
+            Expression::Cast { expr: _, db_type } => db_type.clone(),
+            Expression::PatternMatch { expr, pattern: _ } => {
+                if self.deduce_expression_type(expr.deref(), message_type)? != DBType::String {
+                    return Err(PlannerError::WrongOperandTypes);
+                }
+                DBType::Bool
+            }
+            Expression::MessageConstructor {
+                message_type: constructed_type,
+                fields: _,
+            } => DBType::MessageType(constructed_type.clone()),
+            Expression::EnumConstructor {
+                enum_type,
+                choice: _,
+                fields: _,
+            } => DBType::EnumType(enum_type.clone()),
+            Expression::Exists(_)
+            | Expression::InSubquery {
+                expr: _,
+                subquery: _,
+            }
+            | Expression::InSet { expr: _, values: _ } => DBType::Bool,
+            Expression::ScalarSubquery {
+                subquery: _,
+                db_type,
+            } => db_type.clone(),
+            Expression::FunctionCall { function, args } => {
+                let arg_types = args
+                    .iter()
+                    .map(|arg| self.deduce_expression_type(arg, message_type))
+                    .collect::<Result<Vec<DBType>, PlannerError>>()?;
+
+                match function.signature.resolve(&arg_types) {
+                    Some((parameter_types, return_type)) if parameter_types == arg_types => {
+                        return_type
+                    }
+                    _ => return Err(PlannerError::WrongFunctionArguments(function.name.clone())),
+                }
+            }
         };
 
         Ok(deduced_type)
@@ -534,8 +3174,10 @@ impl QueryPlanner {
         left_type: DBType,
         right_type: DBType,
     ) -> Result<DBType, PlannerError> {
-        //later we assume that types are equal
-        if left_type != right_type {
+        //later we assume that types are equal, except for comparisons of Int and UInt
+        if left_type != right_type
+            && !(op.is_comparison() && Self::mixed_integers(&left_type, &right_type))
+        {
             return Err(PlannerError::WrongOperandTypes);
         }
 
@@ -544,7 +3186,8 @@ impl QueryPlanner {
             BinaryOperator::Add
             | BinaryOperator::Subtract
             | BinaryOperator::Multiply
-            | BinaryOperator::Divide => {
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo => {
                 if left_type == DBType::Double
                     || left_type == DBType::UInt
                     || left_type == DBType::Int
@@ -553,18 +3196,18 @@ impl QueryPlanner {
                 }
                 Err(PlannerError::WrongOperandTypes)
             }
-            BinaryOperator::Equals | BinaryOperator::NotEquals => Ok(left_type),
-            //these can be applied to numeric types and strings
-            BinaryOperator::LessThan | BinaryOperator::GreaterThan => {
-                if left_type == DBType::Double
-                    || left_type == DBType::UInt
-                    || left_type == DBType::Int
-                    || left_type == DBType::String
-                {
-                    return Ok(DBType::Bool);
+            BinaryOperator::Concat => {
+                if left_type == DBType::String {
+                    return Ok(DBType::String);
                 }
                 Err(PlannerError::WrongOperandTypes)
             }
+            BinaryOperator::Equals | BinaryOperator::NotEquals => Ok(DBType::Bool),
+            //every value type has a defined ordering, see DBValue::compare
+            BinaryOperator::LessThan
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessOrEqual
+            | BinaryOperator::GreaterOrEqual => Ok(DBType::Bool),
             BinaryOperator::And | BinaryOperator::Or => {
                 if left_type == DBType::Bool {
                     return Ok(DBType::Bool);
@@ -601,22 +3244,31 @@ impl QueryPlanner {
                 }
                 _ => Err(PlannerError::WrongOperandTypes),
             },
-            UnaryOperator::EnumMatch(expressions) => match db_type {
+            UnaryOperator::IsVariant(choice) => match db_type {
+                DBType::EnumType(enum_type) if *choice < enum_type.variants.len() => {
+                    Ok(DBType::Bool)
+                }
+                _ => Err(PlannerError::WrongOperandTypes),
+            },
+            UnaryOperator::EnumMatch(arms) => match db_type {
                 DBType::EnumType(enum_type) => {
-                    if expressions.is_empty() {
+                    if arms.is_empty() {
                         return Err(PlannerError::EmptyMatchCases);
                     }
 
-                    if expressions.len() != enum_type.variants.len() {
-                        return Err(PlannerError::WrongOperandTypes);
-                    }
-
-                    let iter = expressions.iter().zip(enum_type.variants.iter()).map(
-                        |(expression, variant)| {
-                            let message_type: MessageType = variant.into();
-                            self.deduce_expression_type(expression, &message_type)
-                        },
-                    );
+                    let iter = arms.iter().map(|arm| {
+                        let variant = match arm.choice {
+                            Some(choice) => Some(
+                                enum_type
+                                    .variants
+                                    .get(choice)
+                                    .ok_or(PlannerError::WrongOperandTypes)?,
+                            ),
+                            None => None,
+                        };
+                        let scope = Self::match_arm_scope(variant, &arm.bindings, None);
+                        self.deduce_expression_type(&arm.expression, &scope)
+                    });
 
                     let mut types: Vec<DBType> = vec![];
                     for result in iter {
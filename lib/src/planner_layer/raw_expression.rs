@@ -1,6 +1,7 @@
 use std::string::String;
 
-use super::super::executor_layer::{expression::*, schema::*};
+use super::super::executor_layer::{expression::*, pattern::PatternKind, schema::*};
+use super::logical_plan::RawPlan;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RawExpression {
@@ -15,12 +16,113 @@ pub enum RawExpression {
         op: RawUnaryOperator,
         expr: Box<RawExpression>,
     },
+    InList {
+        expr: Box<RawExpression>,
+        list: Vec<RawExpression>,
+    },
+    Cast {
+        expr: Box<RawExpression>,
+        db_type: DBType,
+    },
+    FunctionCall {
+        name: String,
+        args: Vec<RawExpression>,
+    },
+    //fields are checked against the type once it is resolved
+    MessageConstructor {
+        type_name: String,
+        fields: Vec<RawExpression>,
+    },
+    EnumConstructor {
+        type_name: String,
+        variant_name: String,
+        fields: Vec<RawExpression>,
+    },
+    //subqueries may refer to columns of the enclosing query
+    Exists(Box<RawPlan>),
+    InSubquery {
+        expr: Box<RawExpression>,
+        plan: Box<RawPlan>,
+    },
+    ScalarSubquery(Box<RawPlan>),
+    //pattern has to be a string known at planning time
+    PatternMatch {
+        expr: Box<RawExpression>,
+        pattern: Box<RawExpression>,
+        kind: PatternKind,
+    },
+}
+
+impl RawExpression {
+    //plans of the subqueries used directly in the expression
+    pub fn subqueries<'a>(&'a self, plans: &mut Vec<&'a RawPlan>) {
+        match self {
+            RawExpression::Literal(_) | RawExpression::ColumnRef(_) => {}
+            RawExpression::BinaryOp { op: _, left, right } => {
+                left.subqueries(plans);
+                right.subqueries(plans);
+            }
+            RawExpression::UnaryOp { op, expr } => {
+                if let RawUnaryOperator::EnumMatch(arms) = op {
+                    for arm in arms.iter() {
+                        if let Some(guard) = &arm.guard {
+                            guard.subqueries(plans);
+                        }
+                        arm.expression.subqueries(plans);
+                    }
+                }
+                expr.subqueries(plans);
+            }
+            RawExpression::InList { expr, list } => {
+                expr.subqueries(plans);
+                list.iter().for_each(|item| item.subqueries(plans));
+            }
+            RawExpression::Cast { expr, db_type: _ } => expr.subqueries(plans),
+            RawExpression::PatternMatch {
+                expr,
+                pattern,
+                kind: _,
+            } => {
+                expr.subqueries(plans);
+                pattern.subqueries(plans);
+            }
+            RawExpression::FunctionCall {
+                name: _,
+                args: fields,
+            }
+            | RawExpression::MessageConstructor {
+                type_name: _,
+                fields,
+            }
+            | RawExpression::EnumConstructor {
+                type_name: _,
+                variant_name: _,
+                fields,
+            } => fields.iter().for_each(|field| field.subqueries(plans)),
+            RawExpression::Exists(plan) | RawExpression::ScalarSubquery(plan) => plans.push(plan),
+            RawExpression::InSubquery { expr, plan } => {
+                expr.subqueries(plans);
+                plans.push(plan);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RawUnaryOperator {
-    Negate,                        // -
-    Not,                           // NOT
-    MessageField(String),          // foo.bar
-    EnumMatch(Vec<RawExpression>), // match enum, foo => bar, lol => kek etc
+    Negate,                      // -
+    Not,                         // NOT
+    MessageField(String),        // foo.bar
+    IsVariant(String, String),   // foo IS EnumType::Variant
+    EnumMatch(Vec<RawMatchArm>), // match enum, foo => bar, lol => kek etc
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawMatchArm {
+    //enum type and variant names, None for the wildcard arm
+    pub variant: Option<(String, String)>,
+    //field, alias pairs, None binds every field by its name
+    pub bindings: Option<Vec<(String, String)>>,
+    pub guard: Option<RawExpression>,
+    pub expression: RawExpression,
 }
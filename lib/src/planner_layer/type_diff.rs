@@ -0,0 +1,343 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::super::executor_layer::schema::*;
+
+//Difference between a registered type and a new definition with the same name. Compatible
+//changes keep every stored value valid, breaking ones require converting the tables that store
+//the type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeChange {
+    AddedType(String),
+    //message replaced with an enum or the other way around
+    ChangedKind(String),
+    AddedField { type_name: String, field: String },
+    RemovedField { type_name: String, field: String },
+    ChangedFieldType { type_name: String, field: String },
+    ChangedDependencies { type_name: String, field: String },
+    ReorderedFields(String),
+    AddedVariant { type_name: String, variant: String },
+    RemovedVariant { type_name: String, variant: String },
+    ChangedVariant { type_name: String, variant: String },
+    //stored enum values refer to variants by position
+    ReorderedVariants(String),
+}
+
+impl TypeChange {
+    pub fn type_name(&self) -> &str {
+        match self {
+            TypeChange::AddedType(type_name)
+            | TypeChange::ChangedKind(type_name)
+            | TypeChange::ReorderedFields(type_name)
+            | TypeChange::ReorderedVariants(type_name)
+            | TypeChange::AddedField { type_name, .. }
+            | TypeChange::RemovedField { type_name, .. }
+            | TypeChange::ChangedFieldType { type_name, .. }
+            | TypeChange::ChangedDependencies { type_name, .. }
+            | TypeChange::AddedVariant { type_name, .. }
+            | TypeChange::RemovedVariant { type_name, .. }
+            | TypeChange::ChangedVariant { type_name, .. } => type_name,
+        }
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        !matches!(
+            self,
+            TypeChange::AddedType(_) | TypeChange::AddedVariant { .. }
+        )
+    }
+}
+
+impl fmt::Display for TypeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeChange::AddedType(type_name) => write!(f, "added type {}", type_name),
+            TypeChange::ChangedKind(type_name) => {
+                write!(f, "{}: changed between message and enum", type_name)
+            }
+            TypeChange::AddedField { type_name, field } => {
+                write!(f, "{}: added field {}", type_name, field)
+            }
+            TypeChange::RemovedField { type_name, field } => {
+                write!(f, "{}: removed field {}", type_name, field)
+            }
+            TypeChange::ChangedFieldType { type_name, field } => {
+                write!(f, "{}: changed type of field {}", type_name, field)
+            }
+            TypeChange::ChangedDependencies { type_name, field } => {
+                write!(f, "{}: changed dependencies of field {}", type_name, field)
+            }
+            TypeChange::ReorderedFields(type_name) => write!(f, "{}: reordered fields", type_name),
+            TypeChange::AddedVariant { type_name, variant } => {
+                write!(f, "{}: added variant {}", type_name, variant)
+            }
+            TypeChange::RemovedVariant { type_name, variant } => {
+                write!(f, "{}: removed variant {}", type_name, variant)
+            }
+            TypeChange::ChangedVariant { type_name, variant } => {
+                write!(f, "{}: changed fields of variant {}", type_name, variant)
+            }
+            TypeChange::ReorderedVariants(type_name) => {
+                write!(f, "{}: reordered variants", type_name)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeDiff {
+    pub changes: Vec<TypeChange>,
+}
+
+impl TypeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn breaking(&self) -> impl Iterator<Item = &TypeChange> {
+        self.changes.iter().filter(|change| change.is_breaking())
+    }
+}
+
+impl fmt::Display for TypeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            match change.is_breaking() {
+                true => writeln!(f, "{} (breaking)", change)?,
+                false => writeln!(f, "{}", change)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+//nested messages and enums are compared by name, their own changes are reported separately
+fn same_type_reference(old: &DBType, new: &DBType) -> bool {
+    match (old, new) {
+        (DBType::MessageType(old), DBType::MessageType(new)) => old.name == new.name,
+        (DBType::EnumType(old), DBType::EnumType(new)) => old.name == new.name,
+        _ => old == new,
+    }
+}
+
+//true if the common names appear in the same order in both lists
+fn same_order(old: &[&String], new: &[&String]) -> bool {
+    let old_common = old.iter().filter(|name| new.contains(name));
+    let new_common = new.iter().filter(|name| old.contains(name));
+    old_common.eq(new_common)
+}
+
+fn column_names(message_type: &MessageType) -> Vec<&String> {
+    message_type
+        .columns
+        .iter()
+        .map(|column| &column.column_name)
+        .collect()
+}
+
+pub fn diff_message_types(old: &MessageType, new: &MessageType) -> Vec<TypeChange> {
+    let type_name = &new.name;
+    let dependency_names = |message_type: &MessageType, column: &Column| {
+        column
+            .dependencies
+            .iter()
+            .map(|index| message_type.columns[*index].column_name.clone())
+            .collect::<Vec<String>>()
+    };
+    let mut changes = vec![];
+
+    for column in old.columns.iter() {
+        match new
+            .columns
+            .iter()
+            .find(|new_column| new_column.column_name == column.column_name)
+        {
+            None => changes.push(TypeChange::RemovedField {
+                type_name: type_name.clone(),
+                field: column.column_name.clone(),
+            }),
+            Some(new_column) => {
+                if !same_type_reference(&column.column_type, &new_column.column_type) {
+                    changes.push(TypeChange::ChangedFieldType {
+                        type_name: type_name.clone(),
+                        field: column.column_name.clone(),
+                    });
+                }
+                if dependency_names(old, column) != dependency_names(new, new_column) {
+                    changes.push(TypeChange::ChangedDependencies {
+                        type_name: type_name.clone(),
+                        field: column.column_name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    for column in new.columns.iter() {
+        if !old
+            .columns
+            .iter()
+            .any(|old_column| old_column.column_name == column.column_name)
+        {
+            changes.push(TypeChange::AddedField {
+                type_name: type_name.clone(),
+                field: column.column_name.clone(),
+            });
+        }
+    }
+
+    if !same_order(&column_names(old), &column_names(new)) {
+        changes.push(TypeChange::ReorderedFields(type_name.clone()));
+    }
+
+    changes
+}
+
+pub fn diff_enum_types(old: &EnumType, new: &EnumType) -> Vec<TypeChange> {
+    let type_name = &new.name;
+    let mut changes = vec![];
+
+    for variant in old.variants.iter() {
+        match new
+            .variants
+            .iter()
+            .find(|new_variant| new_variant.name == variant.name)
+        {
+            None => changes.push(TypeChange::RemovedVariant {
+                type_name: type_name.clone(),
+                variant: variant.name.clone(),
+            }),
+            Some(new_variant) => {
+                let same_content = variant.content.len() == new_variant.content.len()
+                    && variant.content.iter().zip(new_variant.content.iter()).all(
+                        |((old_name, old_type), (new_name, new_type))| {
+                            old_name == new_name && same_type_reference(old_type, new_type)
+                        },
+                    );
+                if !same_content {
+                    changes.push(TypeChange::ChangedVariant {
+                        type_name: type_name.clone(),
+                        variant: variant.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    for variant in new.variants.iter() {
+        if !old
+            .variants
+            .iter()
+            .any(|old_variant| old_variant.name == variant.name)
+        {
+            changes.push(TypeChange::AddedVariant {
+                type_name: type_name.clone(),
+                variant: variant.name.clone(),
+            });
+        }
+    }
+
+    //new variants have to be appended, otherwise positions of stored values change
+    let old_names = old
+        .variants
+        .iter()
+        .map(|variant| &variant.name)
+        .collect::<Vec<&String>>();
+    let new_names = new
+        .variants
+        .iter()
+        .map(|variant| &variant.name)
+        .collect::<Vec<&String>>();
+    let appended = new_names
+        .iter()
+        .skip_while(|name| old_names.contains(name))
+        .all(|name| !old_names.contains(name));
+    if !appended || !same_order(&old_names, &new_names) {
+        changes.push(TypeChange::ReorderedVariants(type_name.clone()));
+    }
+
+    changes
+}
+
+//names of all messages and enums a type consists of, including its own name
+pub fn referenced_types(db_type: &DBType, names: &mut HashSet<String>) {
+    match db_type {
+        DBType::MessageType(message_type) => {
+            if !names.insert(message_type.name.clone()) {
+                return;
+            }
+            for column in message_type.columns.iter() {
+                referenced_types(&column.column_type, names);
+            }
+        }
+        DBType::EnumType(enum_type) => {
+            if !names.insert(enum_type.name.clone()) {
+                return;
+            }
+            for variant in enum_type.variants.iter() {
+                for (_, field_type) in variant.content.iter() {
+                    referenced_types(field_type, names);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+//Replaces types with their new definitions. The map contains the registered and the new
+//definition of every changed type, other versions of these types are left as they are
+pub fn upgrade_type(db_type: &DBType, changed: &HashMap<String, (DBType, DBType)>) -> DBType {
+    let name = match db_type {
+        DBType::MessageType(message_type) => &message_type.name,
+        DBType::EnumType(enum_type) => &enum_type.name,
+        _ => return db_type.clone(),
+    };
+    if let Some((_, new_type)) = changed
+        .get(name)
+        .filter(|(registered, _)| registered == db_type)
+    {
+        return new_type.clone();
+    }
+
+    match db_type {
+        DBType::MessageType(message_type) => DBType::MessageType(MessageType {
+            name: message_type.name.clone(),
+            columns: message_type
+                .columns
+                .iter()
+                .map(|column| Column {
+                    column_name: column.column_name.clone(),
+                    column_type: upgrade_type(&column.column_type, changed),
+                    dependencies: column.dependencies.clone(),
+                })
+                .collect(),
+        }),
+        DBType::EnumType(enum_type) => DBType::EnumType(EnumType {
+            name: enum_type.name.clone(),
+            variants: enum_type
+                .variants
+                .iter()
+                .map(|variant| EnumVariantType {
+                    name: variant.name.clone(),
+                    content: variant
+                        .content
+                        .iter()
+                        .map(|(name, field_type)| (name.clone(), upgrade_type(field_type, changed)))
+                        .collect(),
+                })
+                .collect(),
+        }),
+        _ => unreachable!(),
+    }
+}
+
+pub fn upgrade_message_type(
+    message_type: &MessageType,
+    changed: &HashMap<String, (DBType, DBType)>,
+) -> MessageType {
+    match upgrade_type(&DBType::MessageType(message_type.clone()), changed) {
+        DBType::MessageType(upgraded) => upgraded,
+        //only possible if the type became an enum, which is a breaking change
+        _ => message_type.clone(),
+    }
+}
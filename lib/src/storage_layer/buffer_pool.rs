@@ -25,17 +25,24 @@ impl BufferPool {
         page_size: usize,
         capacity: usize,
     ) -> Result<Self, StorageError> {
+        Ok(Self::with_storage(Storage::new(path, page_size)?, capacity))
+    }
+
+    //fails if the storage was not created before
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, StorageError> {
+        Ok(Self::with_storage(Storage::open(path)?, capacity))
+    }
+
+    fn with_storage(storage: Storage, capacity: usize) -> Self {
         if capacity == 0 {
             panic!("Buffer pool capacity must not be zero!");
         }
 
-        let storage = Storage::new(path, page_size)?;
-
-        Ok(Self {
+        Self {
             storage,
             pages: RefCell::new(HashMap::with_capacity(capacity)),
             capacity,
-        })
+        }
     }
 
     pub fn page_size(&self) -> usize {
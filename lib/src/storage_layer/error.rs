@@ -17,6 +17,12 @@ pub enum StorageError {
     #[error("Page full")]
     PageFull,
 
+    #[error("Database has page size {stored}, requested {requested}")]
+    PageSizeMismatch { requested: usize, stored: usize },
+
+    #[error("Storage is not initialized")]
+    NotInitialized,
+
     #[error("Invalid operation")]
     InvalidOperation,
 
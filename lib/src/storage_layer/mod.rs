@@ -133,6 +133,46 @@ mod tests {
         utility::cleanup(path);
     }
 
+    #[test]
+    fn page_size_test() {
+        let path = "temp_path30";
+        utility::cleanup(path);
+
+        //opening does not create a storage
+        assert!(matches!(
+            paged_storage::PagedStorage::open(path, 3usize),
+            Err(error::StorageError::NotInitialized)
+        ));
+        assert!(matches!(
+            paged_storage::PagedStorage::open(path, 3usize),
+            Err(error::StorageError::NotInitialized)
+        ));
+
+        {
+            let paged_storage = paged_storage::PagedStorage::new(path, 8192usize, 3usize).unwrap();
+            paged_storage.check_page_size(8192).unwrap();
+        }
+
+        let paged_storage = paged_storage::PagedStorage::open(path, 3usize).unwrap();
+        assert_eq!(paged_storage.page_size(), 8192);
+        drop(paged_storage);
+
+        {
+            //the stored page size wins over the requested one
+            let paged_storage = paged_storage::PagedStorage::new(path, 4096usize, 3usize).unwrap();
+            assert_eq!(paged_storage.page_size(), 8192);
+            assert!(matches!(
+                paged_storage.check_page_size(4096),
+                Err(error::StorageError::PageSizeMismatch {
+                    requested: 4096,
+                    stored: 8192
+                })
+            ));
+        }
+
+        utility::cleanup(path);
+    }
+
     #[test]
     fn paged_storage_test() {
         let path = "temp_path3";
@@ -23,10 +23,27 @@ impl PagedStorage {
         Ok(Self { buffer_pool })
     }
 
+    //opens an existing database without writing to it, used in read-only mode
+    pub fn open<P: AsRef<Path>>(path: P, buffer_capacity: usize) -> Result<Self, StorageError> {
+        let buffer_pool = BufferPool::open(path, buffer_capacity)?;
+        Ok(Self { buffer_pool })
+    }
+
     pub fn page_size(&self) -> usize {
         self.buffer_pool.page_size()
     }
 
+    //page size is chosen when the database is created, opening it with another one is an error
+    pub fn check_page_size(&self, page_size: usize) -> Result<(), StorageError> {
+        match self.page_size() {
+            stored if stored != page_size => Err(StorageError::PageSizeMismatch {
+                requested: page_size,
+                stored,
+            }),
+            _ => Ok(()),
+        }
+    }
+
     pub fn marble(&self) -> &Marble {
         self.buffer_pool.marble()
     }
@@ -47,6 +47,16 @@ impl Storage {
         Ok(Self { marble, state })
     }
 
+    /// Open a storage that was already created, nothing is written to disk
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let marble = marble::open(path)?;
+
+        match load(&marble, STORAGE_STATE_INDEX)? {
+            Some(state) => Ok(Self { marble, state }),
+            None => Err(StorageError::NotInitialized),
+        }
+    }
+
     fn save_state(&self) -> Result<(), StorageError> {
         save(&self.marble, &self.state, STORAGE_STATE_INDEX)?;
         Ok(())
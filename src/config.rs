@@ -0,0 +1,146 @@
+use std::path::Path;
+
+pub const USAGE: &str = "Usage: dbuf_db_bin [OPTIONS]
+
+    --config PATH            read options from a config file, flags override it
+    --db PATH                directory of the database, dbuf_db_storage by default
+    --page-size BYTES        page size of a new database, an existing one must have the same
+    --buffer-capacity PAGES  number of pages cached in memory, 10 by default
+    --read-only              reject statements that change the database
+    -f, --file SCRIPT        run the statements of the script and exit
+    --continue-on-error      run the remaining statements of a script after a failed one
+
+Without a script statements are read from stdin, interactively if it is a terminal
+
+A config file has an option on each line, # starts a comment:
+
+    db = \"path/to/database\"
+    page_size = 4096
+    buffer_capacity = 10
+    read_only = false";
+
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+pub struct Options {
+    pub db: String,
+    //the page size of an existing database is only checked if it is set explicitly
+    pub page_size: Option<usize>,
+    pub buffer_capacity: usize,
+    pub read_only: bool,
+    pub script: Option<String>,
+    pub continue_on_error: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            db: "dbuf_db_storage".to_owned(),
+            page_size: None,
+            buffer_capacity: 10,
+            read_only: false,
+            script: None,
+            continue_on_error: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut config = None;
+        //flags are applied after the config file
+        let mut flags = Vec::<(&str, String)>::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value of {}", name))
+            };
+            match arg.as_str() {
+                "--config" => config = Some(value("--config")?),
+                "--db" => flags.push(("db", value("--db")?)),
+                "--page-size" => flags.push(("page_size", value("--page-size")?)),
+                "--buffer-capacity" => flags.push(("buffer_capacity", value("--buffer-capacity")?)),
+                "--read-only" => flags.push(("read_only", "true".to_owned())),
+                "-f" | "--file" => options.script = Some(value("--file")?),
+                "--continue-on-error" => options.continue_on_error = true,
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        if let Some(path) = config {
+            for (line, key, value) in read_config(&path)? {
+                options
+                    .set(&key, &value)
+                    .map_err(|e| format!("{}:{}: {}", path, line, e))?;
+            }
+        }
+        for (key, value) in flags {
+            options.set(key, &value)?;
+        }
+
+        Ok(options)
+    }
+
+    //sets an option by its name in the config file
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "db" => self.db = value.to_owned(),
+            "page_size" => self.page_size = Some(positive(key, value)?),
+            "buffer_capacity" => self.buffer_capacity = positive(key, value)?,
+            "read_only" => {
+                self.read_only = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(format!("read_only must be true or false, not {}", value)),
+                }
+            }
+            _ => return Err(format!("Unknown option: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+fn positive(key: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|number| *number > 0)
+        .ok_or_else(|| format!("{} must be a positive number, not {}", key, value))
+}
+
+//Config files have a key = value pair on each line, values may be quoted. Returns the pairs with
+//their line numbers
+fn read_config<P: AsRef<Path>>(path: P) -> Result<Vec<(usize, String, String)>, String> {
+    let display = path.as_ref().display().to_string();
+    let text =
+        std::fs::read_to_string(&path).map_err(|e| format!("Can't read {}: {}", display, e))?;
+    let mut pairs = vec![];
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", display, index + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected key = value"))?;
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, rest)) if rest.trim().is_empty() || rest.trim().starts_with('#') => {
+                    value
+                }
+                Some(_) => return Err(error("unexpected text after the value")),
+                None => return Err(error("unterminated string")),
+            },
+            None => value.split('#').next().unwrap_or_default().trim(),
+        };
+
+        pairs.push((index + 1, key.trim().to_owned(), value.to_owned()));
+    }
+
+    Ok(pairs)
+}
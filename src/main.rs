@@ -1,13 +1,30 @@
+mod config;
+
+use config::{DEFAULT_PAGE_SIZE, Options, USAGE};
+
 use lib::executor_layer::{physical_plan::*, schema, table_manager::TableManager};
-use lib::parser_layer::{ast::*, fetch_types::*, query::QueryParser};
-use lib::planner_layer::{logical_plan::*, query_planner::QueryPlanner, raw_expression::*};
+use lib::parser_layer::{
+    ast::*,
+    fetch_types::*,
+    parse_query::{parse_script, parse_statement, split_statements},
+    query::{QueryParser, ScriptParser},
+};
+use lib::planner_layer::{error::PlannerError, query_planner::QueryPlanner};
 use lib::storage_layer::paged_storage::PagedStorage;
 
-use std::io::{self, Stdin};
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::process::ExitCode;
 
 pub struct Executor {
     pub query_planner: QueryPlanner,
     pub query_parser: QueryParser,
+    pub script_parser: ScriptParser,
+    //statements that change the database are rejected
+    pub read_only: bool,
+    //number of failed statements
+    errors: Cell<usize>,
 }
 
 impl Executor {
@@ -15,100 +32,211 @@ impl Executor {
         Self {
             query_planner,
             query_parser,
+            script_parser: ScriptParser::new(),
+            read_only: false,
+            errors: Cell::new(0),
         }
     }
 
-    fn read_query() -> Result<String, ()> {
-        let mut query = String::new();
-        let stdin: Stdin = io::stdin();
+    //reads lines until every statement in them ends with a semicolon, None at the end of input
+    fn read_statements() -> io::Result<Option<String>> {
+        let mut text = String::new();
 
         loop {
             let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            text.push_str(&line);
 
-            if stdin.read_line(&mut line).is_err() {
-                println!("Error: Failed to read from stdin");
-                return Err(());
+            let statements = split_statements(&text);
+            if statements
+                .last()
+                .is_some_and(|statement| text[statement.clone()].ends_with(';'))
+            {
+                return Ok(Some(text));
             }
+        }
+    }
 
-            if let Some(pos) = line.find(';') {
-                if pos != line.trim_end_matches(&['\n', '\r'][..]).len() - 1 {
-                    println!("Error: Semicolon must only appear at the end of the query");
-                    return Err(());
-                }
+    fn fail(&self, message: String) {
+        println!("{}", message);
+        self.errors.set(self.errors.get() + 1);
+    }
 
-                if query.contains(';') {
-                    println!("Error: Multiple semicolons detected");
-                    return Err(());
-                }
+    //prints the error with the closest name from the catalog if a name is misspelled
+    fn report(&self, context: &str, query: &Query, error: &PlannerError) {
+        let mut message = format!("{}:\n{:?}", context, error);
+        if let Some(name) = self.query_planner.did_you_mean(query, error) {
+            message.push_str(&format!("\nhelp: did you mean `{}`?", name));
+        }
+        self.fail(message);
+    }
+
+    //Runs the statements of a script in order and returns false if any of them failed. Unless
+    //errors are skipped, nothing runs if the script has a syntax error and execution stops at
+    //the first failed statement
+    pub fn run_script(&mut self, source: &str, text: &str, continue_on_error: bool) -> bool {
+        let errors = self.errors.get();
 
-                query.push_str(&line);
-                return Ok(query);
-            } else {
-                query.push_str(&line);
+        if continue_on_error {
+            for statement in split_statements(text) {
+                match parse_statement(&self.query_parser, source, text, statement) {
+                    Ok(query) => self.execute_query(query),
+                    Err(e) => self.fail(format!("Parser error:\n{}", e.render())),
+                }
+            }
+        } else {
+            match parse_script(&self.script_parser, source, text) {
+                Ok(queries) => {
+                    for query in queries {
+                        self.execute_query(query);
+                        if self.errors.get() > errors {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => self.fail(format!("Parser error:\n{}", e.render())),
             }
         }
+
+        self.errors.get() == errors
     }
 
     //TODO better error handling
-    fn execute_query(&mut self, query_string: String) -> () {
-        let result = self.query_parser.parse(&query_string);
-
-        if let Err(e) = result {
-            println!("Parser error:\n{:?}", e);
+    fn execute_query(&mut self, query: Query) {
+        if self.read_only && !query.is_read_only() {
+            self.fail("Database is opened in read-only mode".to_owned());
             return;
         }
 
-        let query = result.unwrap();
-        match query {
-            Query::FetchTypes(path) => {
-                let result = std::fs::read_to_string(path);
+        match query.clone() {
+            Query::FetchTypes(path, mode) => {
+                let result = read_sources(&path);
                 if let Err(e) = result {
-                    println!("Can't read file:\n{:?}", e);
+                    self.fail(format!("Can't read files:\n{}", e.render()));
                     return;
                 }
-                let file = result.unwrap();
-
-                match parse_types(file) {
-                    Err(e) => println!("Dbuf parser error:\n{:?}", e),
-                    Ok(types) => {
-                        for fetched in types.iter() {
-                            match fetched {
-                                FetchedType::MessageType(message_type) => {
-                                    let _ = self.query_planner.add_message_type(
-                                        message_type.name.clone(),
-                                        message_type.clone(),
-                                    );
-                                }
-                                FetchedType::EnumType(enum_type) => {
-                                    let _ = self
-                                        .query_planner
-                                        .add_enum_type(enum_type.name.clone(), enum_type.clone());
-                                }
-                            }
-                        }
+                let sources = result.unwrap();
+
+                let result = parse_sources(&sources, &self.query_planner.known_types());
+                if let Err(e) = result {
+                    self.fail(format!("Dbuf parser error:\n{}", e.render()));
+                    return;
+                }
+                let types = result.unwrap();
+
+                if mode == FetchMode::Check {
+                    let diff = self.query_planner.diff_types(&types);
+                    if diff.is_empty() {
+                        println!("No changes");
+                    } else {
+                        print!("{}", diff);
                     }
+                    return;
+                }
+
+                match self
+                    .query_planner
+                    .fetch_types(&types, mode == FetchMode::Replace)
+                {
+                    Ok(diff) => print!("{}", diff),
+                    Err(e) => self.fail(format!("Failed to fetch types:\n{:?}", e)),
                 }
             }
             Query::CreateTable(table_name, type_name) => {
-                if let Ok(message_type) = self.query_planner.get_message_type(&type_name) {
+                let result = self.query_planner.get_message_type(&type_name);
+                if let Ok(message_type) = result {
                     if let Err(e) = self
                         .query_planner
-                        .table_manager
-                        .create_table(table_name, message_type.clone())
+                        .create_table(&table_name, message_type.clone())
                     {
-                        println!("Failed to create table:\n{:?}", e);
+                        self.report("Failed to create table", &query, &e);
                     }
-                } else {
-                    println!("Could not find type {:?}", type_name);
+                } else if let Err(e) = result {
+                    self.report("Could not find type", &query, &e);
                 }
             }
-            Query::DropTable(table_name) => {
-                if let Err(e) = self
+            Query::CreateTableAs {
+                table,
+                type_name,
+                select,
+            } => {
+                let result = self.query_planner.from_parsed_select(&select);
+                if let Err(e) = result {
+                    self.report("Ill-formed expression", &query, &e);
+                    return;
+                }
+                let raw_plan = result.unwrap();
+
+                let result = self.query_planner.build_logical_plan(&raw_plan);
+                if let Err(ref e) = result {
+                    self.report("Error building a logical plan", &query, e);
+                    return;
+                }
+                let logical_plan = result.unwrap();
+
+                match self
                     .query_planner
-                    .table_manager
-                    .drop_table(table_name.clone())
+                    .create_table_as(&table, type_name, &logical_plan)
                 {
-                    println!("Failed to drop table:\n{:?}", e);
+                    Ok((type_name, count)) => println!(
+                        "Created table {} of type {} with {} messages",
+                        table, type_name, count
+                    ),
+                    Err(e) => self.report("Failed to create table", &query, &e),
+                }
+            }
+            Query::AlterTableType {
+                table,
+                type_name,
+                using,
+            } => match self
+                .query_planner
+                .alter_table_type(&table, &type_name, using.as_ref())
+            {
+                Ok(count) => println!(
+                    "Converted {} messages of table {} to type {}",
+                    count, table, type_name
+                ),
+                Err(e) => self.report("Failed to alter table", &query, &e),
+            },
+            Query::CreateView {
+                name,
+                select,
+                materialized,
+            } => match self.query_planner.create_view(&name, &select, materialized) {
+                Ok(count) if materialized => {
+                    println!("Created materialized view {} with {} messages", name, count)
+                }
+                Ok(_) => println!("Created view {}", name),
+                Err(e) => self.report("Failed to create view", &query, &e),
+            },
+            Query::RefreshMaterializedView(name) => {
+                match self.query_planner.refresh_materialized_view(&name) {
+                    Ok(count) => println!("Refreshed view {} with {} messages", name, count),
+                    Err(e) => self.report("Failed to refresh view", &query, &e),
+                }
+            }
+            Query::DropView(name) => {
+                if let Err(e) = self.query_planner.drop_view(&name) {
+                    self.report("Failed to drop view", &query, &e);
+                }
+            }
+            Query::DropTable(table_name) => {
+                if let Err(e) = self.query_planner.drop_table(&table_name) {
+                    self.report("Failed to drop table", &query, &e);
+                }
+            }
+            Query::DropType { type_name, cascade } => {
+                match self.query_planner.drop_type(&type_name, cascade) {
+                    Ok(dropped) => {
+                        for referent in dropped.iter() {
+                            println!("Dropped {}", referent);
+                        }
+                        println!("Dropped type {}", type_name);
+                    }
+                    Err(e) => self.report("Failed to drop type", &query, &e),
                 }
             }
             Query::InsertMessages { table, messages } => {
@@ -118,9 +246,10 @@ impl Executor {
                     let value = Value::Message(message.clone());
                     let result = self.query_planner.from_parsed_value(&value);
                     if let Err(ref e) = result {
-                        println!(
-                            "Ill formed message or could not convert to desired type:\n{:?}",
-                            e
+                        self.report(
+                            "Ill formed message or could not convert to desired type",
+                            &query,
+                            e,
                         );
                         return;
                     }
@@ -135,58 +264,89 @@ impl Executor {
 
                 if let Err(e) = self
                     .query_planner
-                    .table_manager
-                    .insert_messages(table.clone(), converted_messages.into_iter())
+                    .insert_messages(&table, converted_messages)
                 {
-                    println!("Insertion failed:\n{:?}", e);
+                    self.report("Insertion failed", &query, &e);
                 }
             }
-            Query::Select {
-                table,
-                fields,
-                condition,
-            } => {
-                let table_scan = RawPlan::Scan { table_name: table };
-
-                let mut raw_expressions = Vec::<(String, RawExpression)>::new();
-                for (parsed_expression, alias) in fields.iter() {
-                    let result = self.query_planner.from_parsed_expression(parsed_expression);
-                    if let Err(e) = result {
-                        println!("Ill-formed expression:\n{:?}", e);
-                        return;
-                    }
-                    raw_expressions.push((alias.clone(), result.unwrap()));
+            Query::InsertSelect { table, select } => {
+                let result = self.query_planner.from_parsed_select(&select);
+                if let Err(e) = result {
+                    self.report("Ill-formed expression", &query, &e);
+                    return;
                 }
+                let raw_plan = result.unwrap();
 
-                let raw_plan = match condition {
-                    Some(filter_expression) => {
-                        let result = self
-                            .query_planner
-                            .from_parsed_expression(&filter_expression);
-                        if let Err(e) = result {
-                            println!("Ill-formed expression:\n{:?}", e);
-                            return;
-                        }
-
-                        let filter = RawPlan::Filter {
-                            raw_expression: result.unwrap(),
-                            source: Box::new(table_scan),
-                        };
+                let result = self.query_planner.build_logical_plan(&raw_plan);
+                if let Err(ref e) = result {
+                    self.report("Error building a logical plan", &query, e);
+                    return;
+                }
+                let logical_plan = result.unwrap();
 
-                        RawPlan::Projection {
-                            raw_expressions: raw_expressions,
-                            source: Box::new(filter),
+                match self.query_planner.insert_from_plan(&table, &logical_plan) {
+                    Ok(count) => println!("Inserted {} messages", count),
+                    Err(e) => self.report("Insertion failed", &query, &e),
+                }
+            }
+            Query::ShowTables => {
+                for (name, kind, message_type) in self.query_planner.relations() {
+                    println!("{} ({}, {})", name, kind, message_type.name);
+                }
+            }
+            Query::ShowTypes => {
+                for (db_type, version) in self.query_planner.types() {
+                    match db_type {
+                        schema::DBType::EnumType(_) => println!("{} (enum)", db_type),
+                        _ => println!("{} (message, version {})", db_type, version),
+                    }
+                }
+            }
+            Query::Describe(name) => match self.query_planner.describe_relation(&name) {
+                Ok((kind, message_type)) => {
+                    match self.query_planner.message_type_version(&message_type) {
+                        Some(version) => println!(
+                            "{} {} of type {} (version {})",
+                            kind, name, message_type.name, version
+                        ),
+                        None => println!("{} {} of type {}", kind, name, message_type.name),
+                    }
+                    for column in message_type.columns.iter() {
+                        let dependencies = column
+                            .dependencies
+                            .iter()
+                            .map(|index| message_type.columns[*index].column_name.clone())
+                            .collect::<Vec<String>>();
+                        match dependencies.is_empty() {
+                            true => println!("    {} {}", column.column_name, column.column_type),
+                            false => println!(
+                                "    {} {} (depends on {})",
+                                column.column_name,
+                                column.column_type,
+                                dependencies.join(", ")
+                            ),
                         }
                     }
-                    None => RawPlan::Projection {
-                        raw_expressions: raw_expressions,
-                        source: Box::new(table_scan),
-                    },
-                };
+                }
+                Err(e) => self.report("Failed to describe table", &query, &e),
+            },
+            Query::DescribeType(type_name) => match self.query_planner.describe_type(&type_name) {
+                Ok(schema::DBType::MessageType(message_type)) => println!("{}", message_type),
+                Ok(schema::DBType::EnumType(enum_type)) => println!("{}", enum_type),
+                Ok(_) => unreachable!(),
+                Err(e) => self.report("Failed to describe type", &query, &e),
+            },
+            Query::Select(select) => {
+                let result = self.query_planner.from_parsed_select(&select);
+                if let Err(e) = result {
+                    self.report("Ill-formed expression", &query, &e);
+                    return;
+                }
+                let raw_plan = result.unwrap();
 
                 let result = self.query_planner.build_logical_plan(&raw_plan);
                 if let Err(ref e) = result {
-                    println!("Error building a logical plan:\n{:?}", e);
+                    self.report("Error building a logical plan", &query, e);
                     return;
                 }
                 let logical_plan = result.unwrap();
@@ -196,39 +356,117 @@ impl Executor {
                 };
 
                 if let Err(e) = physical_plan.open() {
-                    println!("Error while preparing plan for execution: {:?}", e);
+                    self.fail(format!("Error while preparing plan for execution: {:?}", e));
                     return;
                 }
 
-                let answer: Vec<lib::executor_layer::schema::Message> = physical_plan.collect();
-                for message in answer.iter() {
-                    println!("{:#?}", message);
+                for result in physical_plan {
+                    match result {
+                        Ok(message) => println!("{:#?}", message),
+                        Err(e) => {
+                            self.fail(format!("Error while executing query:\n{:?}", e));
+                            return;
+                        }
+                    }
                 }
             }
         }
     }
 
+    //interactive console, errors don't stop it
     pub fn run(&mut self) {
         loop {
-            let read = Self::read_query();
-            if read.is_err() {
-                continue;
+            match Self::read_statements() {
+                Ok(Some(text)) => {
+                    self.run_script("<query>", &text, true);
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    println!("Error: Failed to read from stdin: {}", e);
+                    return;
+                }
             }
-            let query = read.unwrap();
-
-            self.execute_query(query);
         }
     }
 }
 
-fn main() {
-    let path = "dbuf_db_storage".to_owned();
-    let paged_storage = PagedStorage::new(path, 4096usize, 10usize).unwrap();
-    let table_manager = TableManager::new(paged_storage).unwrap();
-    let query_planner = QueryPlanner::new(table_manager).unwrap();
+fn open_database(options: &Options) -> Result<QueryPlanner, String> {
+    //a read-only database is never created or initialized
+    let paged_storage = match options.read_only {
+        true => PagedStorage::open(&options.db, options.buffer_capacity),
+        false => PagedStorage::new(
+            &options.db,
+            options.page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+            options.buffer_capacity,
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    if let Some(page_size) = options.page_size {
+        paged_storage
+            .check_page_size(page_size)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let table_manager = TableManager::new(paged_storage).map_err(|e| e.to_string())?;
+    QueryPlanner::new(table_manager).map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    if options.read_only && !Path::new(&options.db).exists() {
+        eprintln!("Database {} doesn't exist", options.db);
+        return ExitCode::FAILURE;
+    }
+    let query_planner = match open_database(&options) {
+        Ok(query_planner) => query_planner,
+        Err(message) => {
+            eprintln!("Can't open database {}: {}", options.db, message);
+            return ExitCode::FAILURE;
+        }
+    };
 
     let query_parser = QueryParser::new();
 
     let mut executor = Executor::new(query_planner, query_parser);
-    executor.run();
+    executor.read_only = options.read_only;
+
+    let script = match options.script {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(text) => Some((path, text)),
+            Err(e) => {
+                eprintln!("Can't read {}: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        },
+        //statements piped into stdin are run as a script
+        None if !io::stdin().is_terminal() => {
+            let mut text = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut text) {
+                eprintln!("Can't read stdin: {}", e);
+                return ExitCode::FAILURE;
+            }
+            Some(("<stdin>".to_owned(), text))
+        }
+        None => None,
+    };
+
+    match script {
+        Some((source, text)) => {
+            match executor.run_script(&source, &text, options.continue_on_error) {
+                true => ExitCode::SUCCESS,
+                false => ExitCode::FAILURE,
+            }
+        }
+        None => {
+            executor.run();
+            ExitCode::SUCCESS
+        }
+    }
 }